@@ -115,7 +115,7 @@ fn grow_grass(query: Query<(&VoxelModelInstance, &Mesh3d), With<Floor>>) -> Opti
             let vox_below = model
                 .get_voxel_at_point(pos - IVec3::Y)
                 .unwrap_or(Voxel::EMPTY);
-            let grass_voxels: RangeInclusive<u8> = 161..=165;
+            let grass_voxels: RangeInclusive<u16> = 161..=165;
             let grow_grass = grass_voxels.contains(&vox_below.0);
             let mut plant_grass = !grow_grass && value < 5 && vox_below != Voxel::EMPTY;
             if plant_grass {
@@ -132,7 +132,7 @@ fn grow_grass(query: Query<(&VoxelModelInstance, &Mesh3d), With<Floor>>) -> Opti
                 }
             }
             if plant_grass || grow_grass {
-                Voxel((161 + value % 5) as u8)
+                Voxel((161 + value % 5) as u16)
             } else {
                 Voxel::EMPTY
             }
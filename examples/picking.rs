@@ -1,7 +1,7 @@
 use bevy::prelude::*;
 use bevy_vox_scene::{
-    SDF, VoxLoaderSettings, VoxScenePlugin, Voxel, VoxelModel, VoxelModelInstance, VoxelModifier,
-    VoxelPalette, VoxelQueryable, VoxelRegion, VoxelRegionMode, create_voxel_context,
+    SDF, VoxLoaderSettings, VoxScenePlugin, Voxel, VoxelModelInstance, VoxelModifier, VoxelPalette,
+    VoxelPickingPlugin, VoxelPointerHits, VoxelRegionMode, create_voxel_context,
     create_voxel_scene, modify_voxel_model,
 };
 use utilities::{PanOrbitCamera, PanOrbitCameraPlugin};
@@ -12,8 +12,8 @@ fn main() {
     App::new()
         .add_plugins((
             DefaultPlugins,
-            MeshPickingPlugin,
             VoxScenePlugin::default(),
+            VoxelPickingPlugin,
             PanOrbitCameraPlugin,
         ))
         .add_systems(Startup, (spawn_camera, spawn_voxels))
@@ -24,7 +24,6 @@ fn spawn_camera(mut commands: Commands) {
     commands.spawn((
         Camera3d::default(),
         PanOrbitCamera::default(),
-        MeshPickingCamera,
         Transform::from_xyz(2.0, 3.0, 6.0).looking_at(Vec3::ZERO, Vec3::Y),
     ));
 
@@ -66,47 +65,25 @@ fn spawn_voxels(world: &mut World) {
 /// Tap with left button to add voxels, right button to erase
 fn on_tap_voxels(
     trigger: Trigger<Pointer<Pressed>>,
-    query: Query<(&VoxelModelInstance, &Mesh3d, &GlobalTransform)>,
-    models: Res<Assets<VoxelModel>>,
+    query: Query<(&VoxelModelInstance, &Mesh3d, &VoxelPointerHits)>,
 ) -> Option<VoxelModifier> {
-    let Some(global_point) = trigger.hit.position else {
+    let Ok((instance, mesh, pointer_hits)) = query.get(trigger.target) else {
         return None;
     };
-    let Some(global_normal) = trigger.hit.normal else {
-        return None;
-    };
-    let Ok((instance, mesh, global_xform)) = query.get(trigger.target) else {
-        return None;
-    };
-    let Some(model) = models.get(&instance.model) else {
-        return None;
-    };
-    // pick a point close to the center of the voxel whose surface we tapped:
-    let global_voxel_center = global_point - (global_normal * (VOXEL_SIZE * 0.5));
-    // and convert it into voxel space:
-    let voxel_point = model.global_point_to_voxel_space(global_voxel_center, global_xform);
-    let brush_radius = 4;
-    let brush_radius_squared = brush_radius * brush_radius;
+    // the voxel picking backend tells us exactly which voxel we tapped:
+    let pick = pointer_hits.get(&trigger.pointer_id)?;
+    let voxel_point = pick.hit.voxel_coord;
     let brush: Voxel = match trigger.button {
         PointerButton::Primary => Voxel(2), // draw with the ROSE color we defined above
         PointerButton::Secondary | PointerButton::Middle => Voxel::EMPTY, // eraser
     };
-    // define a region to update that is no bigger than our brush to keep the operation performant
-    let region = VoxelRegionMode::Box(VoxelRegion::from_center(
-        voxel_point,
-        IVec3::splat(brush_radius),
-    ));
-    let modifier = VoxelModifier::new(
-        instance.clone(),
-        mesh.0.clone(),
-        region,
-        move |pos, underlying_vox, _| {
-            if pos.distance_squared(voxel_point) < brush_radius_squared {
-                brush.clone()
-            } else {
-                underlying_vox.clone()
-            }
-        },
-    );
+    // only the voxels inside of the sphere-shaped brush are visited, which keeps the operation performant
+    let region = VoxelRegionMode::Sphere {
+        center: voxel_point.as_vec3(),
+        radius: 3.5,
+    };
+    let modifier = VoxelModifier::new(instance.clone(), mesh.0.clone(), region, move |_, _, _| {
+        brush.clone()
+    });
     Some(modifier)
 }
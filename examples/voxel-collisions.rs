@@ -10,7 +10,7 @@ use bevy::{
 };
 use bevy_vox_scene::{
     VoxLoaderSettings, VoxScenePlugin, Voxel, VoxelInstanceReady, VoxelModel, VoxelModelInstance,
-    VoxelModifier, VoxelQueryable, VoxelRegion, VoxelRegionMode, modify_voxel_model,
+    VoxelModifier, VoxelQueryable, VoxelRegion, VoxelRegionMode, VoxelResize, modify_voxel_model,
 };
 use rand::Rng;
 use utilities::{PanOrbitCamera, PanOrbitCameraPlugin};
@@ -24,7 +24,8 @@ enum AppState {
 
 //TODO: fix
 
-// When a snowflake lands on the scenery, it is added to scenery's voxel data, so that snow gradually builds up
+// When a snowflake lands on the scenery, it is added to scenery's voxel data, so that snow gradually builds up,
+// growing the bounds of the model so that the snow can pile up above it
 fn main() {
     // Making this frequency not cleanly divisible by the snowflake speed ensures that expensive collisions
     // don't all happen on the same frame
@@ -224,13 +225,15 @@ fn update_snow(
                 origin: vox_pos - IVec3::splat(flake_radius),
                 size: IVec3::splat(1 + (flake_radius * 2)),
             };
+            // if the model grows below its minimum corner to fit the snow, its voxel coordinates shift by the same amount
+            let flake_center = vox_pos + (-flake_region.origin).max(IVec3::ZERO);
             let modifier = VoxelModifier::new(
                 item_instance.clone(),
                 mesh.0.clone(),
                 VoxelRegionMode::Box(flake_region),
                 move |pos, voxel, model| {
                     // a signed distance field for a sphere, but _only_ drawing it on empty cells directly above solid voxels
-                    if *voxel == Voxel::EMPTY && pos.distance_squared(vox_pos) <= radius_squared {
+                    if *voxel == Voxel::EMPTY && pos.distance_squared(flake_center) <= radius_squared {
                         if let Ok(voxel_below) = model.get_voxel_at_point(pos - IVec3::Y) {
                             if voxel_below != Voxel::EMPTY {
                                 // draw our snow material
@@ -241,7 +244,8 @@ fn update_snow(
                     // else we return the underlying voxel, unmodified
                     voxel.clone()
                 },
-            );
+            )
+            .with_resize(VoxelResize::ToFit);
             commands.run_system_cached_with(modify_voxel_model, Some(modifier));
             commands.entity(snowflake).despawn();
         }
@@ -0,0 +1,225 @@
+use bevy::{
+    animation::AnimationTarget,
+    asset::{AssetId, Assets},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        hierarchy::{ChildOf, Children},
+        name::Name,
+        query::{Has, QueryData},
+        system::{Commands, In, Query, Res, ResMut},
+    },
+    log::warn,
+    math::{Affine3A, Mat4},
+    pbr::{MeshMaterial3d, StandardMaterial},
+    platform::collections::HashMap,
+    render::{
+        mesh::{Mesh, Mesh3d},
+        render_asset::RenderAssetUsages,
+        view::{Visibility, VisibilityRange},
+    },
+    transform::components::Transform,
+};
+
+use crate::{
+    VoxelAnimationPlayer, VoxelContext, VoxelLayer, VoxelModel, VoxelModelInstance,
+    load::VoxelAnimationFrame,
+};
+
+/// The components of a node in a spawned voxel scene that determine whether it can be baked
+#[derive(QueryData)]
+pub struct BakeNode {
+    transform: &'static Transform,
+    visibility: Option<&'static Visibility>,
+    layer: Option<&'static VoxelLayer>,
+    instance: Option<&'static VoxelModelInstance>,
+    has_mesh: Has<Mesh3d>,
+    has_lod: Has<VisibilityRange>,
+    is_animated: Option<&'static AnimationTarget>,
+    animation: Option<&'static VoxelAnimationPlayer>,
+    frame: Option<&'static VoxelAnimationFrame>,
+    children: Option<&'static Children>,
+}
+
+/// Merges the static, opaque voxel models in a spawned voxel scene into as few meshes as possible, to reduce the number of draw calls.
+///
+/// Takes the root of the spawned scene, and whether to bake a separate mesh for each [`VoxelLayer`], so that the
+/// visibility of layers can still be toggled. All of the visible instances below the root that share a
+/// [`VoxelContext`] are merged into a single mesh, with their transforms relative to the root baked in, which is spawned
+/// as a child of the root. The [`Mesh3d`] and [`bevy::pbr::MeshMaterial3d`] are then removed from the baked instances,
+/// although the entities themselves, and their [`VoxelModelInstance`]s, remain. Returns the entities containing the merged meshes.
+///
+/// Instances that are hidden, are part of a voxel animation, are animated by an [`AnimationTarget`], have levels of
+/// detail, or contain transmissive voxels are left as they are. The merged meshes are not updated if a baked model is later modified.
+///
+/// ### Example
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy::scene::SceneInstanceReady;
+/// # use bevy_vox_scene::bake_voxel_scene;
+/// # fn setup(mut commands: Commands, assets: Res<AssetServer>) {
+/// commands
+///     .spawn(SceneRoot(assets.load("study.vox")))
+///     .observe(|trigger: Trigger<SceneInstanceReady>, mut commands: Commands| {
+///         let root = trigger.target();
+///         commands.queue(move |world: &mut World| {
+///             world.run_system_cached_with(bake_voxel_scene, (root, true)).ok();
+///         });
+///     });
+/// # }
+/// ```
+pub fn bake_voxel_scene(
+    In((root, group_by_layer)): In<(Entity, bool)>,
+    mut commands: Commands,
+    nodes: Query<BakeNode>,
+    models: Res<Assets<VoxelModel>>,
+    contexts: Res<Assets<VoxelContext>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) -> Vec<Entity> {
+    let mut instances: Vec<BakedInstance> = Vec::new();
+    collect_instances(root, Affine3A::IDENTITY, None, true, &nodes, &mut instances);
+
+    let mut model_meshes: HashMap<AssetId<VoxelModel>, Option<Mesh>> = HashMap::default();
+    let mut groups: Vec<BakedGroup> = Vec::new();
+    let mut group_for_key: HashMap<(AssetId<VoxelContext>, Option<u32>), usize> =
+        HashMap::default();
+    for instance in instances {
+        let Some(context) = contexts.get(instance.instance.context.id()) else {
+            continue;
+        };
+        let model_mesh = model_meshes
+            .entry(instance.instance.model.id())
+            .or_insert_with(|| {
+                let model = models.get(instance.instance.model.id())?;
+                let (ior, has_mesh) = model.data.surface(
+                    &context.palette.indices_of_refraction,
+                    &context.palette.density_for_voxel,
+                );
+                // transmissive models keep their own material, as their index of refraction varies per model
+                if !has_mesh || ior.is_some() {
+                    return None;
+                }
+                Some(model.data.mesh(
+                    &context.palette.indices_of_refraction,
+                    &context.palette.density_for_voxel,
+                ))
+            });
+        let Some(model_mesh) = model_mesh else {
+            continue;
+        };
+        let mut mesh = model_mesh
+            .clone()
+            .transformed_by(Transform::from_matrix(Mat4::from(instance.transform)));
+        // a reflection turns the faces of the mesh inside out
+        if instance.transform.matrix3.determinant() < 0.0
+            && let Err(error) = mesh.invert_winding()
+        {
+            warn!("Unable to bake reflected voxel model: {}", error);
+            continue;
+        }
+        let layer = instance.layer.filter(|_| group_by_layer);
+        let key = (
+            instance.instance.context.id(),
+            layer.as_ref().map(|layer| layer.id),
+        );
+        let group = *group_for_key.entry(key).or_insert_with(|| {
+            groups.push(BakedGroup {
+                context: instance.instance.context.id(),
+                layer,
+                mesh: None,
+            });
+            groups.len() - 1
+        });
+        let group = &mut groups[group];
+        match &mut group.mesh {
+            Some(merged) => {
+                if let Err(error) = merged.merge(&mesh) {
+                    warn!("Unable to bake voxel model: {}", error);
+                    continue;
+                }
+            }
+            None => group.mesh = Some(mesh),
+        }
+        commands
+            .entity(instance.entity)
+            .remove::<(Mesh3d, MeshMaterial3d<StandardMaterial>)>();
+    }
+
+    groups
+        .into_iter()
+        .filter_map(|group| {
+            let mut mesh = group.mesh?;
+            mesh.asset_usage = RenderAssetUsages::RENDER_WORLD;
+            let context = contexts.get(group.context)?;
+            let name = group.layer.as_ref().map_or("baked".to_string(), |layer| {
+                format!("baked-layer-{}", layer.id)
+            });
+            let mut entity = commands.spawn((
+                Name::new(name),
+                Mesh3d(meshes.add(mesh)),
+                MeshMaterial3d(context.opaque_material.clone()),
+                Transform::IDENTITY,
+                ChildOf(root),
+                BakedVoxelMesh,
+            ));
+            if let Some(layer) = group.layer {
+                entity.insert(layer);
+            }
+            Some(entity.id())
+        })
+        .collect()
+}
+
+/// Marks the meshes spawned by [`bake_voxel_scene`], so that a [`crate::VoxelMaterialPlugin`] can replace their material
+#[derive(Component)]
+pub(crate) struct BakedVoxelMesh;
+
+struct BakedInstance {
+    entity: Entity,
+    instance: VoxelModelInstance,
+    transform: Affine3A,
+    layer: Option<VoxelLayer>,
+}
+
+struct BakedGroup {
+    context: AssetId<VoxelContext>,
+    layer: Option<VoxelLayer>,
+    mesh: Option<Mesh>,
+}
+
+/// Collects the static, visible instances in the hierarchy below `entity`, along with their transforms relative to the root
+fn collect_instances(
+    entity: Entity,
+    parent_transform: Affine3A,
+    parent_layer: Option<&VoxelLayer>,
+    is_root: bool,
+    nodes: &Query<BakeNode>,
+    instances: &mut Vec<BakedInstance>,
+) {
+    let Ok(node) = nodes.get(entity) else {
+        return;
+    };
+    if node.is_animated.is_some() || node.animation.is_some() || node.frame.is_some() {
+        return;
+    }
+    if !is_root && node.visibility == Some(&Visibility::Hidden) {
+        return;
+    }
+    let transform = if is_root {
+        Affine3A::IDENTITY
+    } else {
+        parent_transform * node.transform.compute_affine()
+    };
+    let layer = node.layer.or(parent_layer);
+    if let Some(instance) = node.instance.filter(|_| node.has_mesh && !node.has_lod) {
+        instances.push(BakedInstance {
+            entity,
+            instance: instance.clone(),
+            transform,
+            layer: layer.cloned(),
+        });
+    }
+    for child in node.children.into_iter().flatten() {
+        collect_instances(*child, transform, layer, false, nodes, instances);
+    }
+}
@@ -42,36 +42,56 @@
 //!```
 
 use bevy::{
-    app::{App, Plugin, Update},
+    app::{App, Plugin, PreUpdate, Update},
     asset::AssetApp,
 };
 
+mod bake;
 mod load;
+mod material;
 mod model;
 mod observers;
+#[cfg(feature = "picking")]
+mod picking;
+mod save;
 mod systems;
 
 #[cfg(test)]
 mod tests;
 
-#[doc(inline)]
-use load::VoxSceneLoader;
+pub use bake::bake_voxel_scene;
 use load::VoxelAnimationFrame;
 pub use load::{
-    UnitOffset, VoxLoaderSettings, VoxelAnimationPlayer, VoxelLayer, VoxelModelInstance,
+    UnitOffset, VoxLoaderSettings, VoxSceneLoader, VoxelAnimationPlayer, VoxelLayer,
+    VoxelModelInstance,
 };
+pub use material::{VoxelMaterial, VoxelMaterialPlugin};
+#[cfg(all(feature = "generate_voxels", feature = "modify_voxels"))]
+pub use model::islands::{VoxelConnectivity, VoxelIsland, split_voxel_islands};
 #[cfg(feature = "generate_voxels")]
 pub use model::sdf::SDF;
 pub use model::{
-    Voxel, VoxelContext, VoxelData, VoxelElement, VoxelModel, VoxelPalette, create_voxel_animation,
+    Voxel, VoxelBox, VoxelChunks, VoxelCollider, VoxelColliderSettings, VoxelContext, VoxelData,
+    VoxelElement, VoxelModel, VoxelPalette, VoxelStorage, create_voxel_animation,
     create_voxel_context, create_voxel_scene,
+    palette_animation::{VoxelPaletteAnimation, VoxelPaletteEffect},
+    recolor::{edit_voxel_palette, set_voxel_palette},
 };
 #[cfg(feature = "modify_voxels")]
 pub use model::{
+    history::{VoxelEditHistory, redo_voxel_edit, undo_voxel_edit},
     modify::{VoxelModifier, VoxelRegion, VoxelRegionMode, modify_voxel_model},
-    queryable::VoxelQueryable,
+    modify_async::{VoxelModelRemeshed, modify_voxel_model_async},
+    queryable::{VoxelQueryable, VoxelRayHit},
+    resize::VoxelResize,
 };
 pub use observers::VoxelInstanceReady;
+#[cfg(feature = "picking")]
+pub use picking::{VoxelPick, VoxelPickingPlugin, VoxelPointerHits};
+pub use save::{
+    VoxFile, VoxFileNode, VoxSaver, VoxSaverError, VoxelModelLoader, VoxelModelLoaderError,
+    VoxelModelRestored, VoxelModelSaver, create_vox_file,
+};
 
 /// Plugin adding functionality for loading `.vox` files.
 ///
@@ -88,14 +108,35 @@ impl Plugin for VoxScenePlugin {
     fn build(&self, app: &mut App) {
         app.init_asset::<VoxelModel>()
             .init_asset::<VoxelContext>()
+            .init_asset::<VoxelCollider>()
+            .init_asset::<VoxFile>()
             .register_type::<VoxelLayer>()
             .register_type::<VoxelModelInstance>()
+            .register_type::<VoxelChunks>()
+            .register_type::<model::transmissive::TransmissiveMesh>()
+            .register_type::<model::lod::LodMesh>()
             .register_type::<VoxelAnimationPlayer>()
             .register_type::<VoxelAnimationFrame>()
+            .register_type::<VoxelModelRestored>()
             .register_asset_loader(VoxSceneLoader {
                 global_settings: self.global_settings.clone(),
             })
+            .register_asset_loader(VoxelModelLoader)
             .add_observer(observers::on_voxel_scene_ready)
-            .add_systems(Update, systems::update_animations);
+            .add_observer(model::palette_animation::on_remove_palette_animation)
+            .add_systems(
+                Update,
+                (
+                    systems::update_animations,
+                    model::palette_animation::animate_voxel_palettes,
+                ),
+            );
+        #[cfg(feature = "generate_voxels")]
+        app.add_observer(observers::on_add_model_instance)
+            .add_systems(PreUpdate, systems::mesh_restored_instances);
+        #[cfg(feature = "modify_voxels")]
+        app.init_resource::<model::modify_async::VoxelModifierTasks>()
+            .add_event::<VoxelModelRemeshed>()
+            .add_systems(Update, model::modify_async::update_voxel_modifier_tasks);
     }
 }
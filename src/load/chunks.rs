@@ -0,0 +1,79 @@
+use bevy::{log::warn, math::Vec3};
+use dot_vox::Dict;
+
+/// A chunk of a `.vox` file that [`dot_vox`] doesn't parse, such as the render settings and cameras.
+pub(super) struct RawChunk<'a> {
+    pub id: &'a [u8],
+    pub content: &'a [u8],
+}
+
+/// Returns the children of the `MAIN` chunk of a `.vox` file.
+pub(super) fn read_chunks(bytes: &[u8]) -> Vec<RawChunk<'_>> {
+    let mut chunks = Vec::new();
+    // skip the "VOX " magic number, version, and the header of the MAIN chunk
+    let Some(main_content_size) = read_u32(bytes, 12) else {
+        return chunks;
+    };
+    let mut offset = 20 + main_content_size as usize;
+    while offset + 12 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let (Some(content_size), Some(children_size)) =
+            (read_u32(bytes, offset + 4), read_u32(bytes, offset + 8))
+        else {
+            break;
+        };
+        let start = offset + 12;
+        let end = start + content_size as usize;
+        let Some(content) = bytes.get(start..end) else {
+            warn!("Chunk {} is truncated", String::from_utf8_lossy(id));
+            break;
+        };
+        chunks.push(RawChunk { id, content });
+        offset = end + children_size as usize;
+    }
+    chunks
+}
+
+/// Reads a dictionary starting at `offset`, returning it and the offset of the first byte after it.
+pub(super) fn read_dict(bytes: &[u8], mut offset: usize) -> Option<(Dict, usize)> {
+    let count = read_u32(bytes, offset)?;
+    offset += 4;
+    let mut dict = Dict::new();
+    for _ in 0..count {
+        let (key, next) = read_string(bytes, offset)?;
+        let (value, next) = read_string(bytes, next)?;
+        dict.insert(key, value);
+        offset = next;
+    }
+    Some((dict, offset))
+}
+
+pub(super) fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let word = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(word.try_into().ok()?))
+}
+
+fn read_string(bytes: &[u8], offset: usize) -> Option<(String, usize)> {
+    let length = read_u32(bytes, offset)? as usize;
+    let start = offset + 4;
+    let string = bytes.get(start..start + length)?;
+    Some((String::from_utf8_lossy(string).to_string(), start + length))
+}
+
+/// Parses a dictionary value in the form "x y z"
+pub(super) fn parse_vec3(value: Option<&String>) -> Option<Vec3> {
+    let components: Vec<f32> = value?
+        .split_whitespace()
+        .map(|c| c.parse::<f32>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    match components[..] {
+        [x, y, z] => Some(Vec3::new(x, y, z)),
+        _ => None,
+    }
+}
+
+/// Parses a dictionary value containing a single number
+pub(super) fn parse_f32(value: Option<&String>) -> Option<f32> {
+    value?.trim().parse().ok()
+}
@@ -1,27 +1,37 @@
+mod chunks;
 mod components;
+mod parse_animation;
+mod parse_camera;
 mod parse_model;
+mod parse_render;
 mod parse_scene;
 
 use anyhow::anyhow;
 use bevy::{
     asset::{AssetLoader, LoadContext, io::Reader},
     color::LinearRgba,
+    ecs::world::World,
     log::info,
     math::Vec3,
     platform::collections::HashSet,
     scene::Scene,
 };
+use chunks::read_chunks;
 use components::LayerInfo;
 pub use components::{
     AnimationUpdate, VoxelAnimationFrame, VoxelAnimationPlayer, VoxelLayer, VoxelModelInstance,
 };
+use parse_camera::{VoxCamera, find_cameras};
+use parse_render::RenderSettings;
+#[cfg(test)]
+pub(crate) use parse_scene::transform_from_frame;
 use parse_scene::{find_model_names, parse_scene_graph};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use crate::{
     VoxelContext, VoxelData, VoxelQueryable,
-    model::{MaterialProperty, VoxelModel, VoxelPalette},
+    model::{MaterialProperty, VoxelColliderSettings, VoxelModel, VoxelPalette, VoxelStorage},
 };
 
 /// An asset loader capable of loading models in `.vox` files as [`bevy::scene::Scene`]s.
@@ -30,7 +40,9 @@ use crate::{
 /// The meshes generated by this asset loader only use standard [`bevy::render::mesh::Mesh`] attributes for easier compatibility with shaders.
 /// You can load multiple models from the same `.vox` file by appending `#{name}` to the asset loading path, where `{name}` corresponds to the object's name in the Magical Voxel world editor.
 /// You can load unnamed models by appending `#model{no}` to the asset loading path, where `{no}` corresponds to the model index in the file. Note that this index is subject to change if you delete models in the Magica Voxel file.
-pub(super) struct VoxSceneLoader {
+/// If any transform nodes in the scene have been keyframed in Magica Voxel's animation timeline, their keyframes are loaded as a [`bevy::animation::AnimationClip`]
+/// labeled `#animation` for the whole scene or `#{name}@animation` for a named node, and the root of the scene is given an [`bevy::animation::AnimationPlayer`] that loops it.
+pub struct VoxSceneLoader {
     pub(super) global_settings: Option<VoxLoaderSettings>,
 }
 
@@ -53,6 +65,54 @@ pub struct VoxLoaderSettings {
     pub diffuse_roughness: f32,
     /// Set to `true` if you want to modify the voxel model after it has been spawned, `false` if the voxels will be immutable
     pub supports_remeshing: bool,
+    /// The number of Magica Voxel animation frames that play each second. Used to time the keyframes of animated
+    /// transform nodes, which are converted into an [`bevy::animation::AnimationClip`], and the frames of animated
+    /// shape nodes, which play with a [`VoxelAnimationPlayer`]. Defaults to 8.0.
+    pub animation_frame_rate: f32,
+    /// Set to `true` to import the cameras saved in Magica Voxel's camera slots. Each camera is spawned as an inactive
+    /// [`bevy::core_pipeline::core_3d::Camera3d`] named `camera-{no}` in the scene, and can also be loaded on its own as
+    /// a sub-asset by appending `#camera-{no}` to the asset loading path. Defaults to false.
+    pub import_cameras: bool,
+    /// Set to `true` to approximate the lighting from Magica Voxel's render tab. The sun is added to the scene as a
+    /// [`bevy::pbr::DirectionalLight`] named `sun`, and the sky light as the [`bevy::pbr::AmbientLight`] and
+    /// [`bevy::render::camera::ClearColor`] resources. Fog and bloom are camera effects in Bevy, so if `import_cameras`
+    /// is also `true`, the imported cameras are given a [`bevy::pbr::DistanceFog`] and [`bevy::core_pipeline::bloom::Bloom`].
+    /// Defaults to false.
+    pub import_render_settings: bool,
+    /// The distances from the camera at which each level of detail takes over from the previous one. For each distance,
+    /// a mesh is generated with the model downsampled by a further factor of 2 (2x, 4x, and so on), labeled
+    /// `{name}@mesh-lod{no}`, and instances of the model switch between the meshes with
+    /// [`bevy::render::view::VisibilityRange`]. When the model is modified, the levels of detail are regenerated
+    /// and shared by all of its instances.
+    /// Defaults to empty, so that only the full resolution mesh is generated.
+    pub lod_distances: Vec<f32>,
+    /// Set to `true` to bake ambient occlusion into the vertices of the mesh, darkening the corners and crevices where
+    /// voxels meet. The occlusion of each vertex is calculated from the voxels surrounding it and stored in
+    /// [`bevy::render::mesh::Mesh::ATTRIBUTE_COLOR`], which [`bevy::pbr::StandardMaterial`] multiplies with the
+    /// palette colors. Faces are only merged into larger quads if their occlusion matches, so meshes will have more
+    /// vertices. Defaults to false.
+    pub ambient_occlusion: bool,
+    /// Bevy's [`bevy::pbr::StandardMaterial`] only has a single index of refraction, so by default the indices of
+    /// refraction of all of the transmissive voxels in a model are averaged. Set this to split the transmissive voxels
+    /// into a separate mesh for each group of indices of refraction, each with its own material, spawned as children of
+    /// the model. Indices of refraction are grouped into buckets of this width, with each mesh using the average of its
+    /// bucket, or `Some(0.0)` gives a mesh to each distinct index of refraction. The meshes and materials are labeled
+    /// `{name}@mesh-ior{no}` and `{name}@material-ior{no}`. When the model is modified, the meshes are regenerated and
+    /// the children of its instances replaced. Defaults to `None`.
+    pub ior_bucket_size: Option<f32>,
+    /// Set to decompose each model into axis-aligned boxes for collision, which are added as a
+    /// [`crate::VoxelCollider`] sub-asset labeled `{name}@collider`. Defaults to `None`.
+    pub colliders: Option<VoxelColliderSettings>,
+    /// Set to split the mesh of each model into cubic chunks with sides of this many voxels, each spawned as a child of
+    /// the model, so that modifying a large model with [`crate::VoxelModifier::for_chunks`] only remeshes the chunks
+    /// that the modification affects. The meshes are labeled `{name}@mesh-chunk{no}`, and are listed in the
+    /// [`crate::VoxelChunks`] of the model's instances. Levels of detail are not generated for chunked models. Requires
+    /// `supports_remeshing` for the chunks to be modified. Defaults to `None`.
+    pub chunk_size: Option<u32>,
+    /// How the voxels of each model are stored in memory. [`VoxelStorage::Sparse`] only stores the 8x8x8 bricks of a
+    /// model that contain voxels, for large models that are mostly empty. Meshing a model still visits every voxel,
+    /// so large sparse models are best split into chunks with `chunk_size`. Defaults to [`VoxelStorage::Dense`].
+    pub storage: VoxelStorage,
 }
 
 impl Default for VoxLoaderSettings {
@@ -65,6 +125,15 @@ impl Default for VoxLoaderSettings {
             uses_srgb: true,
             diffuse_roughness: 0.8,
             supports_remeshing: false,
+            animation_frame_rate: 8.0,
+            import_cameras: false,
+            import_render_settings: false,
+            lod_distances: Vec::new(),
+            ambient_occlusion: false,
+            ior_bucket_size: None,
+            colliders: None,
+            chunk_size: None,
+            storage: VoxelStorage::Dense,
         }
     }
 }
@@ -180,14 +249,28 @@ impl VoxSceneLoader {
             .map(|(index, (maybe_name, model))| {
                 let name = maybe_name.clone().unwrap_or(format!("model-{}", index));
                 let data = VoxelData::from_model(&model, settings.clone());
-                let (visible_voxels, ior, has_mesh) =
-                    data.visible_voxels(&palette.indices_of_refraction, &palette.density_for_voxel);
+                let (ior, has_mesh) =
+                    data.surface(&palette.indices_of_refraction, &palette.density_for_voxel);
                 let (cloud_voxels, has_cloud) = data.cloud_voxels(&palette.density_for_voxel);
-                if has_mesh {
+                if has_mesh && settings.chunk_size.is_some() {
+                    let chunk_meshes = data
+                        .chunk_meshes(&palette.indices_of_refraction, &palette.density_for_voxel);
+                    for (index, mesh) in chunk_meshes.into_iter().enumerate() {
+                        load_context
+                            .add_labeled_asset(format!("{}@mesh-chunk{}", name, index), mesh);
+                    }
+                } else if has_mesh {
                     load_context.labeled_asset_scope(format!("{}@mesh", name), |_| {
-                        crate::model::mesh::mesh_model(&visible_voxels, &data)
+                        data.mesh(&palette.indices_of_refraction, &palette.density_for_voxel)
                     });
-
+                    let lod_meshes =
+                        data.lod_meshes(&palette.indices_of_refraction, &palette.density_for_voxel);
+                    for (level, mesh) in lod_meshes.into_iter().enumerate() {
+                        load_context
+                            .add_labeled_asset(format!("{}@mesh-lod{}", name, level + 1), mesh);
+                    }
+                }
+                if has_mesh {
                     if let Some(ior) = ior {
                         load_context.labeled_asset_scope(format!("{}@material", name), |_| {
                             let mut material = translucent_material.clone();
@@ -207,11 +290,32 @@ impl VoxSceneLoader {
                         });
                     }
                 }
+                let transmissive_meshes = data.transmissive_meshes(
+                    &palette.indices_of_refraction,
+                    &palette.density_for_voxel,
+                );
+                for (index, (ior, mesh)) in transmissive_meshes.into_iter().enumerate() {
+                    load_context.add_labeled_asset(format!("{}@mesh-ior{}", name, index), mesh);
+                    load_context.labeled_asset_scope(
+                        format!("{}@material-ior{}", name, index),
+                        |_| {
+                            let mut material = translucent_material.clone();
+                            material.ior = ior;
+                            material.thickness = data.size().min_element() as f32;
+                            material
+                        },
+                    );
+                }
                 if has_cloud {
                     load_context.labeled_asset_scope(format!("{}@cloud-image", name), |_| {
                         crate::model::cloud::create_cloud_image(&cloud_voxels, &data)
                     });
                 }
+                if let Some(collider_settings) = &settings.colliders {
+                    load_context.labeled_asset_scope(format!("{}@collider", name), |_| {
+                        data.collider(&palette, collider_settings)
+                    });
+                }
                 let model = VoxelModel {
                     name: name.clone(),
                     data,
@@ -233,7 +337,7 @@ impl VoxSceneLoader {
                 transmissive_material,
             },
         );
-        let scene = parse_scene_graph(
+        let mut scene = parse_scene_graph(
             load_context,
             &file.scenes,
             &file.scenes[0],
@@ -241,9 +345,36 @@ impl VoxSceneLoader {
             &models,
             &mut subassets,
             &layers,
-            settings.voxel_size,
+            &settings,
         );
 
+        // Render settings and cameras
+        if settings.import_render_settings || settings.import_cameras {
+            let chunks = read_chunks(bytes);
+            let render_settings = settings
+                .import_render_settings
+                .then(|| RenderSettings::from_chunks(&chunks, settings.voxel_size));
+            if let Some(render_settings) = &render_settings {
+                render_settings.spawn_lighting(&mut scene.world);
+            }
+            let spawn_camera = |world: &mut World, camera: &VoxCamera| {
+                let entity = camera.spawn(world, settings.voxel_size);
+                if let Some(render_settings) = &render_settings {
+                    render_settings.insert_camera_effects(&mut world.entity_mut(entity));
+                }
+            };
+            if settings.import_cameras {
+                for camera in find_cameras(&chunks) {
+                    load_context.labeled_asset_scope(camera.name(), |_| {
+                        let mut world = World::default();
+                        spawn_camera(&mut world, &camera);
+                        Scene::new(world)
+                    });
+                    spawn_camera(&mut scene.world, &camera);
+                }
+            }
+        }
+
         Ok(scene)
     }
 }
@@ -0,0 +1,84 @@
+use bevy::{
+    animation::{
+        AnimationClip, AnimationTarget, AnimationTargetId, animated_field,
+        animation_curves::{AnimatableCurve, AnimatableKeyframeCurve, AnimatedField},
+        gltf_curves::SteppedKeyframeCurve,
+    },
+    ecs::entity::Entity,
+    prelude::{EntityWorldMut, Transform},
+};
+use dot_vox::Frame;
+
+use super::{VoxLoaderSettings, parse_scene::transform_from_frame};
+
+/// Collects the keyframes of the animated transform nodes in a scene into a single [`AnimationClip`]
+pub(super) struct SceneAnimation {
+    /// The root entity of the scene, which will hold the [`bevy::animation::AnimationPlayer`]
+    player: Entity,
+    pub(super) clip: AnimationClip,
+}
+
+impl SceneAnimation {
+    pub(super) fn new(player: Entity) -> Self {
+        Self {
+            player,
+            clip: AnimationClip::default(),
+        }
+    }
+
+    /// True if no transform nodes in the scene had more than one keyframe
+    pub(super) fn is_empty(&self) -> bool {
+        self.clip.curves().is_empty()
+    }
+
+    /// Adds the keyframes of a transform node to the clip, and makes the `entity` a target of the animation.
+    /// Translation is interpolated between keyframes, whereas rotation and scale change instantaneously,
+    /// matching playback in Magica Voxel.
+    pub(super) fn add_keyframes(
+        &mut self,
+        entity: &mut EntityWorldMut,
+        node_id: u32,
+        frames: &[Frame],
+        settings: &VoxLoaderSettings,
+    ) {
+        if frames.len() < 2 {
+            return;
+        }
+        let keyframes: Vec<(f32, Transform)> = frames
+            .iter()
+            .map(|frame| {
+                let time = frame.frame_index().unwrap_or(0) as f32 / settings.animation_frame_rate;
+                let transform =
+                    Transform::from_matrix(transform_from_frame(frame, settings.voxel_size));
+                (time, transform)
+            })
+            .collect();
+        let (Ok(translation), Ok(rotation), Ok(scale)) = (
+            AnimatableKeyframeCurve::new(
+                keyframes.iter().map(|(t, xform)| (*t, xform.translation)),
+            ),
+            SteppedKeyframeCurve::new(keyframes.iter().map(|(t, xform)| (*t, xform.rotation))),
+            SteppedKeyframeCurve::new(keyframes.iter().map(|(t, xform)| (*t, xform.scale))),
+        ) else {
+            // all of the keyframes are on the same frame
+            return;
+        };
+        let target_id = AnimationTargetId::from_iter(["vox-node".to_string(), node_id.to_string()]);
+        self.clip.add_curve_to_target(
+            target_id,
+            AnimatableCurve::new(animated_field!(Transform::translation), translation),
+        );
+        self.clip.add_curve_to_target(
+            target_id,
+            AnimatableCurve::new(animated_field!(Transform::rotation), rotation),
+        );
+        self.clip.add_curve_to_target(
+            target_id,
+            AnimatableCurve::new(animated_field!(Transform::scale), scale),
+        );
+        entity.insert(AnimationTarget {
+            id: target_id,
+            player: self.player,
+        });
+    }
+}
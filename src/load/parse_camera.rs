@@ -0,0 +1,91 @@
+use bevy::{
+    core_pipeline::core_3d::Camera3d,
+    ecs::{entity::Entity, name::Name, world::World},
+    math::{Quat, Vec3},
+    render::camera::{
+        Camera, OrthographicProjection, PerspectiveProjection, Projection, ScalingMode,
+    },
+    transform::components::Transform,
+};
+use dot_vox::Dict;
+
+use super::chunks::{RawChunk, parse_f32, parse_vec3, read_dict, read_u32};
+
+/// A camera saved in one of Magica Voxel's camera slots
+pub(super) struct VoxCamera {
+    id: u32,
+    attributes: Dict,
+}
+
+pub(super) fn find_cameras(chunks: &[RawChunk]) -> Vec<VoxCamera> {
+    chunks
+        .iter()
+        .filter(|chunk| chunk.id == b"rCAM")
+        .filter_map(|chunk| {
+            let id = read_u32(chunk.content, 0)?;
+            let (attributes, _) = read_dict(chunk.content, 4)?;
+            Some(VoxCamera { id, attributes })
+        })
+        .collect()
+}
+
+impl VoxCamera {
+    /// The name of the camera entity, which is also the label of the camera's sub-asset
+    pub(super) fn name(&self) -> String {
+        format!("camera-{}", self.id)
+    }
+
+    /// Spawns the camera into `world`. The camera is inactive, so that it doesn't compete with any other cameras
+    /// in the app. Set [`Camera::is_active`] to view the scene through it.
+    pub(super) fn spawn(&self, world: &mut World, voxel_size: f32) -> Entity {
+        let focus = parse_vec3(self.attributes.get("_focus")).unwrap_or(Vec3::ZERO);
+        let angle = parse_vec3(self.attributes.get("_angle")).unwrap_or(Vec3::ZERO);
+        let radius = parse_f32(self.attributes.get("_radius")).unwrap_or(0.0);
+        let fov = parse_f32(self.attributes.get("_fov")).unwrap_or(45.0);
+        let frustum =
+            parse_f32(self.attributes.get("_frustum")).unwrap_or((fov.to_radians() * 0.5).tan());
+
+        // The camera orbits the focus point in Magica Voxel's Z-up space, looking along Y when the angles are zero
+        let rotation = Quat::from_rotation_z(angle.z.to_radians())
+            * Quat::from_rotation_x(angle.x.to_radians())
+            * Quat::from_rotation_y(angle.y.to_radians());
+        let forward = rotation * Vec3::Y;
+        let up = rotation * Vec3::Z;
+        let position = focus - forward * radius;
+        let transform = Transform::from_translation(z_up_to_y_up(position) * voxel_size)
+            .looking_to(z_up_to_y_up(forward), z_up_to_y_up(up));
+
+        let projection = match self.attributes.get("_mode").map(|mode| mode.as_str()) {
+            Some("orth") | Some("iso") => Projection::Orthographic(OrthographicProjection {
+                // frame the same area at the focus point as the perspective projection would
+                scaling_mode: ScalingMode::FixedVertical {
+                    viewport_height: (2.0 * radius * frustum).max(1.0) * voxel_size,
+                },
+                ..OrthographicProjection::default_3d()
+            }),
+            _ => Projection::Perspective(PerspectiveProjection {
+                fov: fov.to_radians(),
+                ..Default::default()
+            }),
+        };
+
+        world
+            .spawn((
+                Name::new(self.name()),
+                Camera3d::default(),
+                Camera {
+                    is_active: false,
+                    order: self.id as isize,
+                    ..Default::default()
+                },
+                projection,
+                transform,
+            ))
+            .id()
+    }
+}
+
+/// Converts a vector from Magica Voxel's left-handed Z-up space to bevy's right-handed Y-up space
+pub(super) fn z_up_to_y_up(vector: Vec3) -> Vec3 {
+    Vec3::new(-vector.x, vector.z, vector.y)
+}
@@ -7,13 +7,13 @@ use super::VoxLoaderSettings;
 
 impl VoxelData {
     /// Ingest Magica Voxel data and perform coordinate conversion from MV's left-handed Z-up to bevy's right-handed Y-up
-    pub(super) fn from_model(model: &Model, settings: VoxLoaderSettings) -> VoxelData {
+    pub(crate) fn from_model(model: &Model, settings: VoxLoaderSettings) -> VoxelData {
         let mut data = VoxelData::new(
             UVec3::new(model.size.x, model.size.z, model.size.y),
             settings,
         );
         model.voxels.iter().for_each(|voxel| {
-            let raw_voxel = RawVoxel(voxel.i);
+            let raw_voxel = RawVoxel(voxel.i as u16);
             data.set_voxel(
                 raw_voxel.into(),
                 UVec3::new(
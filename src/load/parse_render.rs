@@ -0,0 +1,153 @@
+use bevy::{
+    color::Color,
+    core_pipeline::bloom::{Bloom, BloomPrefilter},
+    ecs::{name::Name, world::World},
+    math::Vec3,
+    pbr::{AmbientLight, DirectionalLight, DistanceFog, FogFalloff, light_consts::lux},
+    prelude::EntityWorldMut,
+    render::{camera::Camera, camera::ClearColor},
+    transform::components::Transform,
+};
+use dot_vox::Dict;
+
+use super::{
+    chunks::{RawChunk, parse_f32, parse_vec3, read_dict},
+    parse_camera::z_up_to_y_up,
+};
+
+/// Brightness of the [`AmbientLight`] when Magica Voxel's sky light has an intensity of 1.0
+const SKY_BRIGHTNESS: f32 = 1000.0;
+
+/// An approximation of the lighting, fog, and bloom set in Magica Voxel's render tab
+pub(super) struct RenderSettings {
+    sun: Option<(DirectionalLight, Transform)>,
+    sky: Option<AmbientLight>,
+    fog: Option<DistanceFog>,
+    bloom: Option<Bloom>,
+}
+
+impl RenderSettings {
+    pub(super) fn from_chunks(chunks: &[RawChunk], voxel_size: f32) -> Self {
+        let mut settings = RenderSettings {
+            sun: None,
+            sky: None,
+            fog: None,
+            bloom: None,
+        };
+        for chunk in chunks.iter().filter(|chunk| chunk.id == b"rOBJ") {
+            let Some((attributes, _)) = read_dict(chunk.content, 0) else {
+                continue;
+            };
+            match attributes.get("_type").map(|t| t.as_str()) {
+                Some("_inf") => settings.sun = sun(&attributes),
+                Some("_uni") => settings.sky = sky(&attributes),
+                Some("_fog_uni") => settings.fog = fog(&attributes, voxel_size),
+                Some("_bloom") => settings.bloom = bloom(&attributes),
+                _ => {}
+            }
+        }
+        settings
+    }
+
+    /// Adds the sun to the scene as a [`DirectionalLight`] named `sun`, and the sky as the [`AmbientLight`]
+    /// and [`ClearColor`] resources.
+    pub(super) fn spawn_lighting(&self, world: &mut World) {
+        if let Some((light, transform)) = &self.sun {
+            world.spawn((Name::new("sun"), light.clone(), *transform));
+        }
+        if let Some(sky) = &self.sky {
+            world.insert_resource(sky.clone());
+            world.insert_resource(ClearColor(sky.color));
+        }
+    }
+
+    /// Adds the fog and bloom to a camera
+    pub(super) fn insert_camera_effects(&self, camera: &mut EntityWorldMut) {
+        if let Some(fog) = &self.fog {
+            camera.insert(fog.clone());
+        }
+        if let Some(bloom) = &self.bloom {
+            if let Some(mut camera) = camera.get_mut::<Camera>() {
+                camera.hdr = true;
+            }
+            camera.insert(bloom.clone());
+        }
+    }
+}
+
+fn sun(attributes: &Dict) -> Option<(DirectionalLight, Transform)> {
+    let intensity = parse_f32(attributes.get("_i"))?;
+    let color = parse_color(attributes.get("_k")).unwrap_or(Color::WHITE);
+    // the angle of the sun above the horizon, and around the vertical axis
+    let angle = attributes
+        .get("_angle")
+        .map(|angle| {
+            angle
+                .split_whitespace()
+                .filter_map(|a| a.parse::<f32>().ok())
+                .collect::<Vec<f32>>()
+        })
+        .unwrap_or_default();
+    let elevation = angle.first().cloned().unwrap_or(45.0).to_radians();
+    let azimuth = angle.get(1).cloned().unwrap_or(0.0).to_radians();
+    let to_sun = Vec3::new(
+        elevation.cos() * azimuth.cos(),
+        elevation.cos() * azimuth.sin(),
+        elevation.sin(),
+    );
+    Some((
+        DirectionalLight {
+            color,
+            illuminance: intensity * lux::AMBIENT_DAYLIGHT,
+            shadows_enabled: true,
+            ..Default::default()
+        },
+        Transform::IDENTITY.looking_to(-z_up_to_y_up(to_sun), Vec3::Y),
+    ))
+}
+
+fn sky(attributes: &Dict) -> Option<AmbientLight> {
+    let intensity = parse_f32(attributes.get("_i"))?;
+    Some(AmbientLight {
+        color: parse_color(attributes.get("_k")).unwrap_or(Color::WHITE),
+        brightness: intensity * SKY_BRIGHTNESS,
+        ..Default::default()
+    })
+}
+
+fn fog(attributes: &Dict, voxel_size: f32) -> Option<DistanceFog> {
+    let density = parse_f32(attributes.get("_d"))?;
+    if density <= 0.0 {
+        return None;
+    }
+    Some(DistanceFog {
+        color: parse_color(attributes.get("_k")).unwrap_or(Color::WHITE),
+        // Magica Voxel's fog density is measured per voxel
+        falloff: FogFalloff::Exponential {
+            density: density / voxel_size,
+        },
+        ..Default::default()
+    })
+}
+
+fn bloom(attributes: &Dict) -> Option<Bloom> {
+    let mix = parse_f32(attributes.get("_mix"))?;
+    if mix <= 0.0 {
+        return None;
+    }
+    Some(Bloom {
+        // Magica Voxel's default mix of 0.5 corresponds to Bevy's natural bloom
+        intensity: mix * Bloom::NATURAL.intensity * 2.0,
+        prefilter: BloomPrefilter {
+            threshold: parse_f32(attributes.get("_threshold")).unwrap_or(0.0),
+            ..Default::default()
+        },
+        ..Bloom::NATURAL
+    })
+}
+
+/// Parses a dictionary value in the form "r g b", with each component in the range 0-255
+fn parse_color(value: Option<&String>) -> Option<Color> {
+    let rgb = parse_vec3(value)? / 255.0;
+    Some(Color::srgb(rgb.x, rgb.y, rgb.z))
+}
@@ -1,4 +1,7 @@
+use std::time::Duration;
+
 use bevy::{
+    animation::{AnimationPlayer, graph::AnimationGraph, graph::AnimationGraphHandle},
     asset::{Handle, LoadContext},
     ecs::{hierarchy::ChildSpawner, name::Name},
     image::Image,
@@ -10,13 +13,20 @@ use bevy::{
     render::mesh::{Mesh, Mesh3d},
     scene::Scene,
 };
-use dot_vox::{Frame, SceneNode};
+use dot_vox::{Frame, SceneNode, ShapeModel};
 
-use crate::{VoxelLayer, VoxelModel, VoxelModelInstance, VoxelQueryable};
+use crate::{
+    VoxelLayer, VoxelModel, VoxelModelInstance, VoxelQueryable,
+    model::{
+        chunk::insert_chunk_meshes, lod::insert_lod_meshes,
+        transmissive::insert_transmissive_meshes,
+    },
+};
 
 use super::{
-    VoxelAnimationFrame,
+    VoxLoaderSettings, VoxelAnimationFrame,
     components::{LayerInfo, VoxelAnimationPlayer},
+    parse_animation::SceneAnimation,
 };
 
 pub(super) fn find_model_names(
@@ -86,7 +96,7 @@ pub(super) fn parse_scene_graph(
     vox_models: &Vec<VoxelModel>,
     subassets: &mut HashSet<String>,
     layers: &Vec<LayerInfo>,
-    scene_scale: f32,
+    settings: &VoxLoaderSettings,
 ) -> Scene {
     let mut world = World::default();
     match scene_node {
@@ -108,6 +118,7 @@ pub(super) fn parse_scene_graph(
                 Visibility::Inherited
             };
             entity.insert(visibility);
+            let mut animation = SceneAnimation::new(entity.id());
             load_xform_child(
                 context,
                 graph,
@@ -117,9 +128,22 @@ pub(super) fn parse_scene_graph(
                 vox_models,
                 subassets,
                 layers,
-                scene_scale,
+                settings,
+                &mut animation,
             );
 
+            if !animation.is_empty() {
+                let label = node_name.as_ref().map_or("animation".to_string(), |name| {
+                    format!("{}@animation", name)
+                });
+                let clip = context.add_labeled_asset(label.clone(), animation.clip);
+                let (graph, node_index) = AnimationGraph::from_clip(clip);
+                let graph = context.add_labeled_asset(format!("{}-graph", label), graph);
+                let mut player = AnimationPlayer::default();
+                player.play(node_index).repeat();
+                entity.insert((player, AnimationGraphHandle(graph)));
+            }
+
             if let Some(layer) = maybe_layer {
                 entity.insert(VoxelLayer {
                     id: *layer_id,
@@ -145,7 +169,8 @@ fn load_xform_node(
     vox_models: &Vec<VoxelModel>,
     subassets: &mut HashSet<String>,
     layers: &Vec<LayerInfo>,
-    scene_scale: f32,
+    settings: &VoxLoaderSettings,
+    animation: &mut SceneAnimation,
 ) {
     match scene_node {
         SceneNode::Transform {
@@ -187,13 +212,15 @@ fn load_xform_node(
                 vox_models,
                 subassets,
                 layers,
-                scene_scale,
+                settings,
+                animation,
             );
 
             entity.insert(Transform::from_matrix(transform_from_frame(
                 &frames[0],
-                scene_scale,
+                settings.voxel_size,
             )));
+            animation.add_keyframes(&mut entity, *child, frames, settings);
 
             if let Some(node_name) = node_name {
                 // create sub-asset
@@ -207,7 +234,7 @@ fn load_xform_node(
                             vox_models,
                             subassets,
                             layers,
-                            scene_scale,
+                            settings,
                         )
                     });
                 }
@@ -225,7 +252,8 @@ fn load_xform_node(
                 vox_models,
                 subassets,
                 layers,
-                scene_scale,
+                settings,
+                animation,
             );
         }
     }
@@ -240,7 +268,8 @@ fn load_xform_child(
     vox_models: &Vec<VoxelModel>,
     subassets: &mut HashSet<String>,
     layers: &Vec<LayerInfo>,
-    scene_scale: f32,
+    settings: &VoxLoaderSettings,
+    animation: &mut SceneAnimation,
 ) {
     match scene_node {
         SceneNode::Transform { .. } => {
@@ -257,7 +286,8 @@ fn load_xform_child(
                     vox_models,
                     subassets,
                     layers,
-                    scene_scale,
+                    settings,
+                    animation,
                 );
             });
         }
@@ -277,7 +307,8 @@ fn load_xform_child(
                         vox_models,
                         subassets,
                         layers,
-                        scene_scale,
+                        settings,
+                        animation,
                     );
                 }
             });
@@ -294,12 +325,9 @@ fn load_xform_child(
                     context: context.get_label_handle("voxel-context"),
                 });
                 if model.has_mesh {
-                    let mesh: Handle<Mesh> =
-                        context.get_label_handle(format!("{}@mesh", model.name));
-                    let material: Handle<StandardMaterial> =
-                        context.get_label_handle(format!("{}@material", model.name));
-                    entity.insert((Mesh3d(mesh), MeshMaterial3d(material)));
+                    insert_model_mesh(context, entity, model, settings);
                 }
+                insert_model_transmissive_meshes(context, entity, model);
                 if model.has_cloud {
                     let cloud_image: Handle<Image> =
                         context.get_label_handle(format!("{}@cloud-image", model.name));
@@ -313,8 +341,11 @@ fn load_xform_child(
                     ));
                 }
             } else if model_count > 1 {
+                let frames = animation_frames(models);
+                let first_frame = frames[0];
                 entity.insert(VoxelAnimationPlayer {
-                    frames: (0..model_count).collect(),
+                    frames,
+                    frame_rate: Duration::from_secs_f32(1.0 / settings.animation_frame_rate),
                     ..Default::default()
                 });
                 entity.with_children(|spawner| {
@@ -326,7 +357,7 @@ fn load_xform_child(
                                 context: context.get_label_handle("voxel-context"),
                             },
                             VoxelAnimationFrame(index),
-                            if index == 0 {
+                            if index == first_frame {
                                 Visibility::Inherited
                             } else {
                                 Visibility::Hidden
@@ -334,12 +365,9 @@ fn load_xform_child(
                         ));
 
                         if model.has_mesh {
-                            let mesh: Handle<Mesh> =
-                                context.get_label_handle(format!("{}@mesh", model.name));
-                            let material: Handle<StandardMaterial> =
-                                context.get_label_handle(format!("{}@material", model.name));
-                            frame.insert((Mesh3d(mesh), MeshMaterial3d(material)));
+                            insert_model_mesh(context, &mut frame, model, settings);
                         }
+                        insert_model_transmissive_meshes(context, &mut frame, model);
 
                         if model.has_cloud {
                             let cloud_image: Handle<Image> =
@@ -360,6 +388,78 @@ fn load_xform_child(
     }
 }
 
+/// Adds the model's mesh and material to the entity, along with its levels of detail, or spawns a child for each of
+/// its chunks if the mesh is split into chunks
+fn insert_model_mesh(
+    context: &mut LoadContext,
+    entity: &mut EntityWorldMut,
+    model: &VoxelModel,
+    settings: &VoxLoaderSettings,
+) {
+    let material: Handle<StandardMaterial> =
+        context.get_label_handle(format!("{}@material", model.name));
+    if settings.chunk_size.is_some() {
+        let chunk_meshes = (0..model.data.chunk_count())
+            .map(|index| context.get_label_handle(format!("{}@mesh-chunk{}", model.name, index)))
+            .collect();
+        insert_chunk_meshes(entity, chunk_meshes, material);
+        return;
+    }
+    let mesh: Handle<Mesh> = context.get_label_handle(format!("{}@mesh", model.name));
+    entity.insert((Mesh3d(mesh), MeshMaterial3d(material.clone())));
+    let lod_meshes = (1..=settings.lod_distances.len())
+        .map(|level| context.get_label_handle(format!("{}@mesh-lod{}", model.name, level)))
+        .collect();
+    insert_lod_meshes(entity, lod_meshes, material, &settings.lod_distances);
+}
+
+/// Adds a child to the entity for each of the model's transmissive meshes, if they are split by index of refraction
+fn insert_model_transmissive_meshes(
+    context: &mut LoadContext,
+    entity: &mut EntityWorldMut,
+    model: &VoxelModel,
+) {
+    let transmissive_meshes = (0..)
+        .map_while(|index| {
+            let mesh_label = format!("{}@mesh-ior{}", model.name, index);
+            if !context.has_labeled_asset(&mesh_label) {
+                return None;
+            }
+            let material_label = format!("{}@material-ior{}", model.name, index);
+            Some((
+                context.get_label_handle(mesh_label),
+                context.get_label_handle(material_label),
+            ))
+        })
+        .collect();
+    insert_transmissive_meshes(entity, transmissive_meshes);
+}
+
+/// Magica Voxel assigns each model in a shape node to a keyframe on the animation timeline, and a model remains
+/// on screen until the next keyframe. Returns the index of the model to show on each frame of the timeline.
+fn animation_frames(models: &[ShapeModel]) -> Vec<usize> {
+    let mut keyframes: Vec<(u32, usize)> = models
+        .iter()
+        .enumerate()
+        .map(|(index, model)| (model.frame_index().unwrap_or(index as u32), index))
+        .collect();
+    keyframes.sort_by_key(|(frame, _)| *frame);
+    let first_frame = keyframes[0].0;
+    let last_frame = keyframes[keyframes.len() - 1].0;
+    let mut current_keyframe = 0;
+    (first_frame..=last_frame)
+        .map(|frame| {
+            while keyframes
+                .get(current_keyframe + 1)
+                .is_some_and(|(next_frame, _)| *next_frame <= frame)
+            {
+                current_keyframe += 1;
+            }
+            keyframes[current_keyframe].1
+        })
+        .collect()
+}
+
 fn get_accumulated_and_node_name(
     parent_name: Option<&String>,
     node_name: Option<&String>,
@@ -387,7 +487,7 @@ fn parse_bool(value: Option<String>) -> bool {
     }
 }
 
-fn transform_from_frame(frame: &Frame, scene_scale: f32) -> Mat4 {
+pub(crate) fn transform_from_frame(frame: &Frame, scene_scale: f32) -> Mat4 {
     let Some(position) = frame.position() else {
         return Mat4::IDENTITY;
     };
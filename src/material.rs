@@ -0,0 +1,277 @@
+use std::marker::PhantomData;
+
+use bevy::{
+    app::{App, Plugin, PostUpdate},
+    asset::{AssetEvent, AssetEvents, AssetId, Assets, Handle},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::EventReader,
+        hierarchy::{ChildOf, Children},
+        observer::Trigger,
+        query::{Has, With},
+        resource::Resource,
+        schedule::IntoScheduleConfigs,
+        system::{Commands, Query, Res, ResMut},
+        world::{OnAdd, OnRemove},
+    },
+    log::warn,
+    pbr::{ExtendedMaterial, Material, MaterialExtension, MeshMaterial3d, StandardMaterial},
+    platform::collections::HashMap,
+    scene::SceneInstanceReady,
+};
+
+use crate::{VoxelModelInstance, bake::BakedVoxelMesh};
+
+/// A material that voxel models can be rendered with instead of [`StandardMaterial`].
+///
+/// The material is created from the [`StandardMaterial`] that would otherwise have been used, which carries the
+/// textures generated from the [`crate::VoxelPalette`] (base color, emission, metallic-roughness, and specular
+/// transmission), along with the index of refraction and thickness of transmissive models. Render voxel scenes with
+/// the material by adding a [`VoxelMaterialPlugin`].
+///
+/// It is implemented for [`ExtendedMaterial`]s that extend [`StandardMaterial`] with an extension that implements
+/// [`Default`]. For a fully custom material, implement it to copy whichever of the palette textures your shader uses.
+///
+/// ### Example
+/// ```
+/// # use bevy::{prelude::*, render::render_resource::AsBindGroup};
+/// # use bevy::pbr::{MaterialExtension, StandardMaterial};
+/// # use bevy_vox_scene::VoxelMaterial;
+/// #[derive(Asset, TypePath, AsBindGroup, Clone)]
+/// struct PaletteMaterial {
+///     #[texture(0)]
+///     #[sampler(1)]
+///     palette: Option<Handle<Image>>,
+/// }
+///
+/// impl Material for PaletteMaterial {}
+///
+/// impl VoxelMaterial for PaletteMaterial {
+///     fn from_standard_material(material: &StandardMaterial) -> Self {
+///         Self {
+///             palette: material.base_color_texture.clone(),
+///         }
+///     }
+/// }
+/// ```
+pub trait VoxelMaterial: Material {
+    /// Create the material from the [`StandardMaterial`] generated for a voxel model
+    fn from_standard_material(material: &StandardMaterial) -> Self;
+}
+
+impl<E: MaterialExtension + Default> VoxelMaterial for ExtendedMaterial<StandardMaterial, E> {
+    fn from_standard_material(material: &StandardMaterial) -> Self {
+        Self {
+            base: material.clone(),
+            extension: E::default(),
+        }
+    }
+}
+
+/// Plugin that renders voxel scenes with the [`VoxelMaterial`] `M`, instead of [`StandardMaterial`].
+///
+/// Add it alongside [`crate::VoxScenePlugin`]. The [`MeshMaterial3d<StandardMaterial>`] of each
+/// [`VoxelModelInstance`], and of the levels of detail, chunks and transmissive meshes spawned as its children, is
+/// replaced with a [`MeshMaterial3d<M>`] when the voxel scene has spawned, and whenever one is added later, for instance
+/// when a modified model gains a mesh or becomes transmissive. The meshes created by [`crate::bake_voxel_scene`] are
+/// replaced too. A material is only created once for each [`StandardMaterial`], so that instances of a model share it,
+/// and it is recreated when the [`StandardMaterial`] changes, such as when the index of refraction of a modified model
+/// is updated, or the palette is edited. As with any material, the [`bevy::pbr::MaterialPlugin`] for `M` must also be
+/// added.
+///
+/// ### Example
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy::pbr::{ExtendedMaterial, MaterialExtension};
+/// # use bevy::render::render_resource::AsBindGroup;
+/// # use bevy_vox_scene::{VoxScenePlugin, VoxelMaterialPlugin};
+/// #[derive(Asset, AsBindGroup, Reflect, Debug, Clone, Default)]
+/// struct ToonExtension {}
+///
+/// impl MaterialExtension for ToonExtension {}
+///
+/// type ToonMaterial = ExtendedMaterial<StandardMaterial, ToonExtension>;
+///
+/// App::new().add_plugins((
+///     DefaultPlugins,
+///     VoxScenePlugin::default(),
+///     MaterialPlugin::<ToonMaterial>::default(),
+///     VoxelMaterialPlugin::<ToonMaterial>::default(),
+/// ));
+/// ```
+pub struct VoxelMaterialPlugin<M: VoxelMaterial>(PhantomData<M>);
+
+impl<M: VoxelMaterial> Default for VoxelMaterialPlugin<M> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<M: VoxelMaterial> Plugin for VoxelMaterialPlugin<M> {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(VoxelMaterials::<M>(HashMap::default()))
+            .add_observer(replace_scene_materials::<M>)
+            .add_observer(replace_baked_material::<M>)
+            .add_observer(replace_added_material::<M>)
+            .add_observer(remove_replaced_material::<M>)
+            .add_systems(PostUpdate, update_voxel_materials::<M>.after(AssetEvents));
+    }
+}
+
+/// The [`StandardMaterial`] that the [`VoxelMaterial`] of an entity was created from, which keeps it alive so that
+/// modifying the model can still update it, or replace it with another [`StandardMaterial`]
+#[derive(Component, Clone, Debug)]
+pub(crate) struct SourceMaterial(pub(crate) Handle<StandardMaterial>);
+
+/// The material created for each of the [`StandardMaterial`]s used by voxel models, until the [`StandardMaterial`] is
+/// no longer used
+#[derive(Resource)]
+struct VoxelMaterials<M: VoxelMaterial>(HashMap<AssetId<StandardMaterial>, Handle<M>>);
+
+impl<M: VoxelMaterial> VoxelMaterials<M> {
+    /// Replaces the [`StandardMaterial`] on the entity
+    fn replace(
+        &mut self,
+        commands: &mut Commands,
+        entity: Entity,
+        material: &Handle<StandardMaterial>,
+        standard_materials: &Assets<StandardMaterial>,
+        materials: &mut Assets<M>,
+    ) {
+        let handle = match self.0.get(&material.id()) {
+            Some(handle) => handle.clone(),
+            None => {
+                let Some(standard_material) = standard_materials.get(material.id()) else {
+                    warn!("Voxel material has not loaded, so it cannot be replaced");
+                    return;
+                };
+                let handle = materials.add(M::from_standard_material(standard_material));
+                self.0.insert(material.id(), handle.clone());
+                handle
+            }
+        };
+        commands
+            .entity(entity)
+            .remove::<MeshMaterial3d<StandardMaterial>>()
+            .insert((MeshMaterial3d(handle), SourceMaterial(material.clone())));
+    }
+}
+
+fn replace_scene_materials<M: VoxelMaterial>(
+    trigger: Trigger<SceneInstanceReady>,
+    mut commands: Commands,
+    children: Query<&Children>,
+    nodes: Query<(
+        Option<&MeshMaterial3d<StandardMaterial>>,
+        Has<VoxelModelInstance>,
+    )>,
+    mut voxel_materials: ResMut<VoxelMaterials<M>>,
+    standard_materials: Res<Assets<StandardMaterial>>,
+    mut materials: ResMut<Assets<M>>,
+) {
+    for entity in children.iter_descendants(trigger.target()) {
+        let Ok((_, true)) = nodes.get(entity) else {
+            continue;
+        };
+        // the levels of detail and transmissive meshes of a model are spawned as its children
+        let mesh_entities =
+            std::iter::once(entity).chain(children.get(entity).into_iter().flatten().copied());
+        for mesh_entity in mesh_entities {
+            if let Ok((Some(material), _)) = nodes.get(mesh_entity) {
+                voxel_materials.replace(
+                    &mut commands,
+                    mesh_entity,
+                    &material.0,
+                    &standard_materials,
+                    &mut materials,
+                );
+            }
+        }
+    }
+}
+
+fn replace_baked_material<M: VoxelMaterial>(
+    trigger: Trigger<OnAdd, BakedVoxelMesh>,
+    mut commands: Commands,
+    baked: Query<&MeshMaterial3d<StandardMaterial>, With<BakedVoxelMesh>>,
+    mut voxel_materials: ResMut<VoxelMaterials<M>>,
+    standard_materials: Res<Assets<StandardMaterial>>,
+    mut materials: ResMut<Assets<M>>,
+) {
+    let Ok(material) = baked.get(trigger.target()) else {
+        return;
+    };
+    voxel_materials.replace(
+        &mut commands,
+        trigger.target(),
+        &material.0,
+        &standard_materials,
+        &mut materials,
+    );
+}
+
+/// Replaces the [`StandardMaterial`] added to a model instance, or one of its children, after the scene has spawned
+fn replace_added_material<M: VoxelMaterial>(
+    trigger: Trigger<OnAdd, MeshMaterial3d<StandardMaterial>>,
+    mut commands: Commands,
+    nodes: Query<(
+        &MeshMaterial3d<StandardMaterial>,
+        Has<VoxelModelInstance>,
+        Option<&ChildOf>,
+    )>,
+    instances: Query<(), With<VoxelModelInstance>>,
+    mut voxel_materials: ResMut<VoxelMaterials<M>>,
+    standard_materials: Res<Assets<StandardMaterial>>,
+    mut materials: ResMut<Assets<M>>,
+) {
+    let Ok((material, is_instance, parent)) = nodes.get(trigger.target()) else {
+        return;
+    };
+    if !is_instance && !parent.is_some_and(|parent| instances.contains(parent.parent())) {
+        return;
+    }
+    voxel_materials.replace(
+        &mut commands,
+        trigger.target(),
+        &material.0,
+        &standard_materials,
+        &mut materials,
+    );
+}
+
+/// Removes the [`VoxelMaterial`] of a model that has lost its mesh
+fn remove_replaced_material<M: VoxelMaterial>(
+    trigger: Trigger<OnRemove, SourceMaterial>,
+    mut commands: Commands,
+) {
+    commands
+        .entity(trigger.target())
+        .try_remove::<MeshMaterial3d<M>>();
+}
+
+/// Recreates the [`VoxelMaterial`]s whose [`StandardMaterial`] has changed, and drops those whose
+/// [`StandardMaterial`] is no longer used, such as the materials of a modified model that became opaque
+fn update_voxel_materials<M: VoxelMaterial>(
+    mut events: EventReader<AssetEvent<StandardMaterial>>,
+    mut voxel_materials: ResMut<VoxelMaterials<M>>,
+    standard_materials: Res<Assets<StandardMaterial>>,
+    mut materials: ResMut<Assets<M>>,
+) {
+    for event in events.read() {
+        match event {
+            AssetEvent::Modified { id } => {
+                let (Some(handle), Some(standard_material)) =
+                    (voxel_materials.0.get(id), standard_materials.get(*id))
+                else {
+                    continue;
+                };
+                materials.insert(handle, M::from_standard_material(standard_material));
+            }
+            AssetEvent::Removed { id } | AssetEvent::Unused { id } => {
+                voxel_materials.0.remove(id);
+            }
+            _ => {}
+        }
+    }
+}
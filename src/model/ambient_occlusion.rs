@@ -0,0 +1,116 @@
+use bevy::math::IVec3;
+use block_mesh::{OrientedBlockFace, UnorientedQuad, VoxelVisibility};
+use ndshape::{RuntimeShape, Shape};
+
+use super::voxel::VisibleVoxel;
+
+/// The brightness of a vertex at each level of ambient occlusion, from fully occluded to unoccluded
+pub(super) const AO_BRIGHTNESS: [f32; 4] = [0.4, 0.6, 0.8, 1.0];
+
+/// Calculates per-vertex ambient occlusion from the occupancy of the voxels surrounding each corner of a face
+pub(super) struct AmbientOcclusion<'a> {
+    voxels: &'a [VisibleVoxel],
+    shape: &'a RuntimeShape<u32, 3>,
+}
+
+impl<'a> AmbientOcclusion<'a> {
+    pub(super) fn new(voxels: &'a [VisibleVoxel], shape: &'a RuntimeShape<u32, 3>) -> Self {
+        Self { voxels, shape }
+    }
+
+    /// Fills the buffer with the ambient occlusion of the corners of the voxels' given face, so that the greedy mesher
+    /// only merges faces in that direction whose occlusion matches. The buffer is a copy of the voxels, reused for each
+    /// face.
+    pub(super) fn occlude_voxels(&self, face: &OrientedBlockFace, occluded: &mut [VisibleVoxel]) {
+        let normal = IVec3::from(face.signed_normal().to_array());
+        for (index, voxel) in occluded.iter_mut().enumerate() {
+            voxel.ambient_occlusion = 0;
+            if voxel.visibility == VoxelVisibility::Empty {
+                continue;
+            }
+            let point = IVec3::from(self.shape.delinearize(index as u32).map(|v| v as i32));
+            // faces against an opaque voxel are never meshed
+            if self.visibility(point + normal) == VoxelVisibility::Opaque {
+                continue;
+            }
+            voxel.ambient_occlusion = corner_directions(normal).into_iter().enumerate().fold(
+                0,
+                |levels, (corner, toward)| {
+                    levels | self.level(point, normal, toward) << (corner * 2)
+                },
+            );
+        }
+    }
+
+    /// The level of ambient occlusion at each vertex of the quad, in the order of [`OrientedBlockFace::quad_corners`]
+    pub(super) fn quad_levels(&self, face: &OrientedBlockFace, quad: &UnorientedQuad) -> [u8; 4] {
+        let normal = IVec3::from(face.signed_normal().to_array());
+        let minimum = IVec3::from(quad.minimum.map(|v| v as i32));
+        face.quad_corners(quad).map(|corner| {
+            let corner = IVec3::from(corner.as_ivec3().to_array());
+            // find the voxel of the quad that the vertex belongs to, and the direction of the vertex from its center
+            let mut point = minimum;
+            let mut toward = IVec3::ZERO;
+            for axis in 0..3 {
+                if normal[axis] != 0 {
+                    continue;
+                }
+                if corner[axis] > minimum[axis] {
+                    point[axis] = corner[axis] - 1;
+                    toward[axis] = 1;
+                } else {
+                    toward[axis] = -1;
+                }
+            }
+            self.level(point, normal, toward)
+        })
+    }
+
+    /// The ambient occlusion of the corner of a voxel's face, from 0 (fully occluded) to 3 (unoccluded), determined by
+    /// the two voxels alongside the corner and the one diagonally opposite it, in front of the face
+    fn level(&self, point: IVec3, normal: IVec3, toward: IVec3) -> u8 {
+        let front = point + normal;
+        let [first_side, second_side] = tangent_axes(normal).map(|axis| {
+            let mut side = IVec3::ZERO;
+            side[axis] = toward[axis];
+            self.is_occupied(front + side)
+        });
+        if first_side && second_side {
+            return 0;
+        }
+        3 - (first_side as u8 + second_side as u8 + self.is_occupied(front + toward) as u8)
+    }
+
+    fn is_occupied(&self, point: IVec3) -> bool {
+        self.visibility(point) != VoxelVisibility::Empty
+    }
+
+    fn visibility(&self, point: IVec3) -> VoxelVisibility {
+        let size = IVec3::from(self.shape.as_array().map(|v| v as i32));
+        if point.cmplt(IVec3::ZERO).any() || point.cmpge(size).any() {
+            return VoxelVisibility::Empty;
+        }
+        let index = self.shape.linearize(point.as_uvec3().into());
+        self.voxels[index as usize].visibility
+    }
+}
+
+/// The two axes that lie in the plane of a face
+fn tangent_axes(normal: IVec3) -> [usize; 2] {
+    match normal {
+        IVec3 { x: 0, y: 0, .. } => [0, 1],
+        IVec3 { x: 0, .. } => [0, 2],
+        _ => [1, 2],
+    }
+}
+
+/// The direction from the center of a face to each of its corners
+fn corner_directions(normal: IVec3) -> [IVec3; 4] {
+    let [u, v] = tangent_axes(normal);
+    [(-1, -1), (1, -1), (-1, 1), (1, 1)].map(|(du, dv)| {
+        let mut toward = IVec3::ZERO;
+        toward[u] = du;
+        toward[v] = dv;
+        toward
+    })
+}
@@ -0,0 +1,152 @@
+use bevy::{
+    asset::Handle,
+    ecs::{component::Component, reflect::ReflectComponent, world::EntityWorldMut},
+    math::{IVec3, UVec3},
+    pbr::{MeshMaterial3d, StandardMaterial},
+    reflect::Reflect,
+    render::mesh::{Mesh, Mesh3d},
+    transform::components::Transform,
+};
+use ndshape::{RuntimeShape, Shape};
+
+use crate::UnitOffset;
+
+use super::{VoxelData, VoxelQueryable, mesh::mesh_model, storage::Voxels};
+
+/// The meshes of a model whose mesh is split into chunks by [`crate::VoxLoaderSettings::chunk_size`].
+///
+/// Each chunk's mesh is spawned on a child of the [`crate::VoxelModelInstance`]. Pass this to
+/// [`crate::VoxelModifier::for_chunks`] to modify the model, so that only the chunks affected by the modification are
+/// remeshed.
+#[derive(Component, Reflect, Clone, Debug, Default)]
+#[reflect(Component)]
+pub struct VoxelChunks {
+    /// The mesh of each chunk, ordered along the x, then y, then z axes
+    pub meshes: Vec<Handle<Mesh>>,
+}
+
+impl VoxelData {
+    /// The number of chunks along each axis, if the mesh is split into chunks
+    pub(crate) fn chunk_grid(&self) -> Option<UVec3> {
+        let chunk_size = self.settings.chunk_size?.max(1);
+        Some((self.meshed_size() + UVec3::splat(chunk_size - 1)) / chunk_size)
+    }
+
+    /// The number of chunks, or 0 if the mesh isn't split into chunks
+    pub(crate) fn chunk_count(&self) -> usize {
+        self.chunk_grid()
+            .map_or(0, |grid| grid.element_product() as usize)
+    }
+
+    /// Creates the mesh of each chunk, in the same space as the mesh of the whole model
+    pub(crate) fn chunk_meshes(
+        &self,
+        ior_for_voxel: &[Option<f32>],
+        density_for_voxel: &[Option<f32>],
+    ) -> Vec<Mesh> {
+        (0..self.chunk_count())
+            .map(|index| self.chunk_mesh(index, ior_for_voxel, density_for_voxel))
+            .collect()
+    }
+
+    /// Creates the mesh of a single chunk
+    pub(crate) fn chunk_mesh(
+        &self,
+        index: usize,
+        ior_for_voxel: &[Option<f32>],
+        density_for_voxel: &[Option<f32>],
+    ) -> Mesh {
+        let grid = self.chunk_grid().expect("Model is split into chunks");
+        let chunk_size = self.settings.chunk_size.unwrap_or_default().max(1);
+        let chunk = UVec3::from(RuntimeShape::<u32, 3>::new(grid.into()).delinearize(index as u32));
+        let min = chunk * chunk_size + UVec3::ONE;
+        let max = (min + UVec3::splat(chunk_size)).min(self.meshed_size() + UVec3::ONE);
+        self.region_mesh(min, max, ior_for_voxel, density_for_voxel)
+    }
+
+    /// Creates the mesh of the voxels between `min` and `max` (exclusive), in the coordinates of the voxel buffer, in
+    /// the same space as the mesh of the whole model
+    pub(crate) fn region_mesh(
+        &self,
+        min: UVec3,
+        max: UVec3,
+        ior_for_voxel: &[Option<f32>],
+        density_for_voxel: &[Option<f32>],
+    ) -> Mesh {
+        // the mesher only meshes the interior of the voxel buffer, so the region's mesh is created from a copy of its
+        // voxels with a border of its neighbours' voxels, which hide the faces between regions
+        let extent = max - min + UVec3::splat(2);
+        let shape = RuntimeShape::<u32, 3>::new(extent.into());
+        let mut voxels = Voxels::dense(shape.size() as usize);
+        voxels.copy_from(
+            &shape,
+            UVec3::ZERO,
+            &self.voxels,
+            &self.shape,
+            min - UVec3::ONE,
+            extent,
+        );
+        let mut settings = self.settings.clone();
+        settings.mesh_outer_faces = true;
+        settings.mesh_offset = UnitOffset::ZERO;
+        let chunk_data = VoxelData {
+            shape,
+            voxels,
+            settings,
+            revision: self.revision,
+        };
+        let visible_voxels = chunk_data.visible_voxels(ior_for_voxel, density_for_voxel);
+        // move the region to where its voxels are in the mesh of the whole model
+        let leading_padding = (self.padding() / 2) as f32;
+        let offset = (min.as_vec3() - leading_padding) * self.settings.voxel_size
+            - self.model_size() * self.settings.mesh_offset.0;
+        mesh_model(&visible_voxels, &chunk_data, ior_for_voxel.len()).translated_by(offset)
+    }
+
+    /// The indices of the chunks whose meshes are affected by changing the voxels between `start` and `end` (exclusive),
+    /// in the coordinates of the voxel buffer. This includes the neighbouring chunks that border the changed voxels.
+    pub(crate) fn chunks_in_region(&self, start: IVec3, end: IVec3) -> Vec<usize> {
+        let Some(grid) = self.chunk_grid() else {
+            return Vec::new();
+        };
+        let chunk_size = self.settings.chunk_size.unwrap_or_default().max(1) as i32;
+        let grid = grid.as_ivec3();
+        // a chunk's mesh depends on its own voxels and a border of one voxel around them
+        let min = ((start - 2).div_euclid(IVec3::splat(chunk_size))).max(IVec3::ZERO);
+        let max = ((end - 1).div_euclid(IVec3::splat(chunk_size))).min(grid - 1);
+        let mut chunks = Vec::new();
+        for z in min.z..=max.z {
+            for y in min.y..=max.y {
+                for x in min.x..=max.x {
+                    chunks.push(((z * grid.y + y) * grid.x + x) as usize);
+                }
+            }
+        }
+        chunks
+    }
+
+    /// The size of the interior of the voxel buffer, which the mesher meshes
+    pub(crate) fn meshed_size(&self) -> UVec3 {
+        UVec3::from(self.shape.as_array()).saturating_sub(UVec3::splat(2))
+    }
+}
+
+/// Adds the [`VoxelChunks`] to the entity, and spawns a child with the mesh of each chunk
+pub(crate) fn insert_chunk_meshes(
+    entity: &mut EntityWorldMut,
+    chunk_meshes: Vec<Handle<Mesh>>,
+    material: Handle<StandardMaterial>,
+) {
+    entity.with_children(|spawner| {
+        for mesh in chunk_meshes.iter() {
+            spawner.spawn((
+                Mesh3d(mesh.clone()),
+                MeshMaterial3d(material.clone()),
+                Transform::IDENTITY,
+            ));
+        }
+    });
+    entity.insert(VoxelChunks {
+        meshes: chunk_meshes,
+    });
+}
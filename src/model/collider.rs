@@ -0,0 +1,143 @@
+use bevy::{
+    asset::Asset,
+    math::{UVec3, Vec3A, bounding::Aabb3d},
+    reflect::TypePath,
+};
+use ndshape::Shape;
+use serde::{Deserialize, Serialize};
+
+use super::{RawVoxel, VoxelData, VoxelModel, VoxelPalette, VoxelQueryable};
+
+/// Settings for decomposing a voxel model into a [`VoxelCollider`]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VoxelColliderSettings {
+    /// Leave transmissive voxels, such as glass or water, out of the collider. Defaults to false.
+    pub exclude_transmissive: bool,
+    /// Leave cloud voxels out of the collider. Defaults to true.
+    pub exclude_cloud: bool,
+}
+
+impl Default for VoxelColliderSettings {
+    fn default() -> Self {
+        Self {
+            exclude_transmissive: false,
+            exclude_cloud: true,
+        }
+    }
+}
+
+/// An axis-aligned box of voxels, in voxel space
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoxelBox {
+    /// The voxel at the lower-back-left corner of the box
+    pub min: UVec3,
+    /// The voxel beyond the upper-front-right corner of the box, so that `max - min` is the size of the box
+    pub max: UVec3,
+}
+
+/// The occupied voxels of a model, merged into as few axis-aligned boxes as possible, which can be used to build a
+/// compound collider for a physics engine.
+///
+/// Create one with [`VoxelData::collider`] or [`VoxelModel::collider`], or load one as a sub-asset by setting
+/// [`crate::VoxLoaderSettings::colliders`] and appending `#{name}@collider` to the asset loading path.
+#[derive(Asset, TypePath, Clone, Debug, Default)]
+pub struct VoxelCollider {
+    /// The boxes, in voxel space
+    pub voxel_boxes: Vec<VoxelBox>,
+    /// The same boxes, in the local space of the model's mesh
+    pub boxes: Vec<Aabb3d>,
+}
+
+impl VoxelData {
+    /// Decomposes the occupied voxels into axis-aligned boxes, by greedily growing each box along the x, y, and then z axes
+    ///
+    /// ### Arguments
+    /// * `palette` - the palette of the model, used to find its transmissive and cloud voxels
+    /// * `settings` - which voxels to leave out of the collider
+    pub fn collider(
+        &self,
+        palette: &VoxelPalette,
+        settings: &VoxelColliderSettings,
+    ) -> VoxelCollider {
+        let size = self.size().as_uvec3();
+        let leading_padding = UVec3::splat(self.padding() / 2);
+        let is_solid = |voxel: RawVoxel| {
+            voxel != RawVoxel::EMPTY
+                && !(settings.exclude_transmissive
+                    && voxel.lookup(&palette.indices_of_refraction).is_some())
+                && !(settings.exclude_cloud && voxel.lookup(&palette.density_for_voxel).is_some())
+        };
+        let index = |point: UVec3| (point.z * size.y + point.y) * size.x + point.x;
+        let mut remaining: Vec<bool> = (0..size.element_product())
+            .map(|i| {
+                let point = UVec3::new(i % size.x, (i / size.x) % size.y, i / (size.x * size.y));
+                is_solid(
+                    self.voxels
+                        .at(self.shape.linearize((point + leading_padding).into()) as usize),
+                )
+            })
+            .collect();
+        let is_filled = |remaining: &[bool], min: UVec3, max: UVec3| {
+            (min.z..max.z).all(|z| {
+                (min.y..max.y)
+                    .all(|y| (min.x..max.x).all(|x| remaining[index(UVec3::new(x, y, z)) as usize]))
+            })
+        };
+        let mut voxel_boxes = Vec::new();
+        for z in 0..size.z {
+            for y in 0..size.y {
+                for x in 0..size.x {
+                    let min = UVec3::new(x, y, z);
+                    if !remaining[index(min) as usize] {
+                        continue;
+                    }
+                    let mut max = min + UVec3::ONE;
+                    while max.x < size.x
+                        && is_filled(&remaining, min.with_x(max.x), max.with_x(max.x + 1))
+                    {
+                        max.x += 1;
+                    }
+                    while max.y < size.y
+                        && is_filled(&remaining, min.with_y(max.y), max.with_y(max.y + 1))
+                    {
+                        max.y += 1;
+                    }
+                    while max.z < size.z
+                        && is_filled(&remaining, min.with_z(max.z), max.with_z(max.z + 1))
+                    {
+                        max.z += 1;
+                    }
+                    for bz in min.z..max.z {
+                        for by in min.y..max.y {
+                            for bx in min.x..max.x {
+                                remaining[index(UVec3::new(bx, by, bz)) as usize] = false;
+                            }
+                        }
+                    }
+                    voxel_boxes.push(VoxelBox { min, max });
+                }
+            }
+        }
+        // match the vertex positions of the mesh
+        let offset = self.model_size() * self.settings.mesh_offset.0;
+        let boxes = voxel_boxes
+            .iter()
+            .map(|voxel_box| Aabb3d {
+                min: Vec3A::from(voxel_box.min.as_vec3() * self.settings.voxel_size - offset),
+                max: Vec3A::from(voxel_box.max.as_vec3() * self.settings.voxel_size - offset),
+            })
+            .collect();
+        VoxelCollider { voxel_boxes, boxes }
+    }
+}
+
+impl VoxelModel {
+    /// Decomposes the model's occupied voxels into axis-aligned boxes. See [`VoxelData::collider`].
+    pub fn collider(
+        &self,
+        palette: &VoxelPalette,
+        settings: &VoxelColliderSettings,
+    ) -> VoxelCollider {
+        self.data.collider(palette, settings)
+    }
+}
@@ -5,26 +5,49 @@ use bevy::{
 };
 use block_mesh::VoxelVisibility;
 use ndshape::{RuntimeShape, Shape};
-use std::fmt::Debug;
+use std::{
+    collections::BTreeSet,
+    fmt::Debug,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
 use crate::VoxLoaderSettings;
 
-use super::{RawVoxel, voxel::VisibleVoxel};
+use super::{
+    RawVoxel,
+    mesh::mesh_model,
+    storage::{BRICK_SIZE, VoxelStorage, Voxels},
+    voxel::VisibleVoxel,
+};
+
+/// The length of each side of the regions that a sparse model is meshed in, a multiple of the size of its bricks
+const SPARSE_REGION_SIZE: u32 = 4 * BRICK_SIZE;
 
 /// The voxel data used to create a mesh and a material.
 #[derive(Clone)]
 pub struct VoxelData {
     pub(crate) shape: RuntimeShape<u32, 3>,
-    pub(crate) voxels: Vec<RawVoxel>,
+    pub(crate) voxels: Voxels,
     pub(crate) settings: VoxLoaderSettings,
+    /// Changes whenever the voxels are written, and is shared by clones until they are written, so that a model can be
+    /// checked for changes since it was cloned
+    pub(crate) revision: u64,
+}
+
+/// The source of [`VoxelData::revision`]s, which are unique across all models
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(0);
+
+fn next_revision() -> u64 {
+    NEXT_REVISION.fetch_add(1, Ordering::Relaxed)
 }
 
 impl Default for VoxelData {
     fn default() -> Self {
         Self {
             shape: RuntimeShape::<u32, 3>::new([0, 0, 0]),
-            voxels: Default::default(),
+            voxels: Voxels::dense(0),
             settings: VoxLoaderSettings::default(),
+            revision: next_revision(),
         }
     }
 }
@@ -48,13 +71,28 @@ impl VoxelData {
             UVec3::ZERO
         };
         let shape = RuntimeShape::<u32, 3>::new((size + padding).into());
-        let size = shape.size() as usize;
         Self {
+            voxels: Voxels::new(&shape, settings.storage),
             shape,
-            voxels: vec![RawVoxel::EMPTY; size],
             settings,
+            revision: next_revision(),
         }
     }
+
+    /// Gives the data a new [`VoxelData::revision`] after its voxels have been written
+    pub(crate) fn mark_changed(&mut self) {
+        self.revision = next_revision();
+    }
+
+    /// How the voxels are stored, as set by [`VoxLoaderSettings::storage`] when the data was created
+    pub fn storage(&self) -> VoxelStorage {
+        self.voxels.storage()
+    }
+
+    /// The approximate number of bytes used to store the voxels
+    pub fn memory_usage(&self) -> usize {
+        self.voxels.memory_usage()
+    }
     /// The size of the voxel model, not including the padding that may have been added if the outer faces are being meshed.
     pub(crate) fn _size(&self) -> IVec3 {
         let raw_size: UVec3 = self.shape.as_array().into();
@@ -72,11 +110,10 @@ impl VoxelData {
         ior_for_voxel: &[Option<f32>],
         density_for_voxel: &[Option<f32>],
     ) -> (Option<Mesh>, Option<f32>, Option<Image>) {
-        let (visible_voxels, average_ior, needs_meshing) =
-            self.visible_voxels(ior_for_voxel, density_for_voxel);
+        let (average_ior, needs_meshing) = self.surface(ior_for_voxel, density_for_voxel);
         let (cloud_voxels, has_cloud) = self.cloud_voxels(density_for_voxel);
         let maybe_mesh = if needs_meshing {
-            Some(super::mesh::mesh_model(&visible_voxels, self))
+            Some(self.mesh(ior_for_voxel, density_for_voxel))
         } else {
             None
         };
@@ -88,75 +125,117 @@ impl VoxelData {
         (maybe_mesh, average_ior, maybe_image)
     }
 
-    /// Returns the [`VoxelVisibility`] of each Voxel, and, if the model contains
-    /// translucent voxels, the average Index of Refraction.
-    pub(crate) fn visible_voxels(
+    /// Creates the mesh of the model's visible voxels. Sparse models are meshed one region of bricks at a time, skipping
+    /// the regions without voxels, so that the visibility of their empty space is never allocated.
+    pub(crate) fn mesh(
         &self,
         ior_for_voxel: &[Option<f32>],
         density_for_voxel: &[Option<f32>],
-    ) -> (Vec<VisibleVoxel>, Option<f32>, bool) {
-        let mut refraction_indices: Vec<f32> = Vec::new();
-        let voxels: Vec<VisibleVoxel> = self
+    ) -> Mesh {
+        let buffer_max = self.meshed_size() + UVec3::ONE;
+        let regions: BTreeSet<[u32; 3]> = self
             .voxels
+            .filled_bricks()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|brick| (brick / SPARSE_REGION_SIZE).into())
+            .collect();
+        let mut meshes = regions.into_iter().filter_map(|region| {
+            let region = UVec3::from(region);
+            let min = (region * SPARSE_REGION_SIZE).max(UVec3::ONE);
+            let max = ((region + UVec3::ONE) * SPARSE_REGION_SIZE).min(buffer_max);
+            min.cmplt(max)
+                .all()
+                .then(|| self.region_mesh(min, max, ior_for_voxel, density_for_voxel))
+        });
+        let Some(mut mesh) = meshes.next() else {
+            let visible_voxels = self.visible_voxels(ior_for_voxel, density_for_voxel);
+            return mesh_model(&visible_voxels, self, ior_for_voxel.len());
+        };
+        for region_mesh in meshes {
+            mesh.merge(&region_mesh)
+                .expect("Regions are meshed with the same attributes");
+        }
+        mesh
+    }
+
+    /// Returns the [`VoxelVisibility`] of each Voxel. If [`VoxLoaderSettings::ior_bucket_size`] is set, translucent
+    /// voxels are left out, as they are meshed by [`VoxelData::transmissive_meshes`] instead.
+    pub(crate) fn visible_voxels(
+        &self,
+        ior_for_voxel: &[Option<f32>],
+        density_for_voxel: &[Option<f32>],
+    ) -> Vec<VisibleVoxel> {
+        self.voxels
             .iter()
             .map(|v| VisibleVoxel {
                 index: v.0,
-                visibility: if *v == RawVoxel::EMPTY {
+                visibility: if v == RawVoxel::EMPTY {
                     VoxelVisibility::Empty
-                } else if let Some(ior) = ior_for_voxel[v.0 as usize] {
-                    refraction_indices.push(ior);
-                    VoxelVisibility::Translucent
-                } else if density_for_voxel[v.0 as usize].is_some() {
+                } else if v.lookup(ior_for_voxel).is_some() {
+                    // transmissive voxels are meshed separately if they're split by index of refraction
+                    if self.settings.ior_bucket_size.is_some() {
+                        VoxelVisibility::Empty
+                    } else {
+                        VoxelVisibility::Translucent
+                    }
+                } else if v.lookup(density_for_voxel).is_some() {
                     VoxelVisibility::Empty
                 } else {
                     VoxelVisibility::Opaque
                 },
+                ambient_occlusion: 0,
             })
-            .collect();
-        let average_ior: Option<f32> = if refraction_indices.is_empty() {
-            None
-        } else {
-            let ior = refraction_indices
-                .iter()
-                .cloned()
-                .reduce(|acc, e| acc + e)
-                .unwrap_or(0.0)
-                / refraction_indices.len() as f32;
-            Some(ior)
-        };
-        let needs_meshing = voxels
-            .iter()
-            .any(|&v| v.visibility != VoxelVisibility::Empty);
-        (voxels, average_ior, needs_meshing)
+            .collect()
     }
 
+    /// Returns the average Index of Refraction of the model's translucent voxels, if it contains any that are meshed
+    /// with the rest of the model, and whether the model needs meshing. Only the voxels that aren't empty are visited.
+    pub(crate) fn surface(
+        &self,
+        ior_for_voxel: &[Option<f32>],
+        density_for_voxel: &[Option<f32>],
+    ) -> (Option<f32>, bool) {
+        let mut needs_meshing = false;
+        let (mut total_ior, mut transmissive_count) = (0.0, 0);
+        for (_, voxel) in self.voxels.filled() {
+            if let Some(ior) = voxel.lookup(ior_for_voxel) {
+                if self.settings.ior_bucket_size.is_none() {
+                    total_ior += ior;
+                    transmissive_count += 1;
+                    needs_meshing = true;
+                }
+            } else if voxel.lookup(density_for_voxel).is_none() {
+                needs_meshing = true;
+            }
+        }
+        let average_ior = (transmissive_count > 0).then(|| total_ior / transmissive_count as f32);
+        (average_ior, needs_meshing)
+    }
+
+    /// Returns the density of each voxel, without the outer layer of the voxel buffer, and whether the model contains
+    /// any cloud voxels. The densities are only filled in if it does.
     pub(crate) fn cloud_voxels(&self, density_for_voxel: &[Option<f32>]) -> (Vec<f32>, bool) {
-        let mut has_cloud: bool = false;
-        let max_bound = self.shape.as_array().map(|v| v - 1);
-        let densities: Vec<f32> = self
+        let has_cloud = self
             .voxels
-            .iter()
-            .enumerate()
-            .filter_map(|(index, v)| {
-                // remove the outer layer of voxels that the loader adds
-                let coords = self.shape.delinearize(index as u32);
-                if coords.contains(&0) {
-                    return None;
-                }
-                if coords[0] == max_bound[0]
-                    || coords[1] == max_bound[1]
-                    || coords[2] == max_bound[2]
-                {
-                    return None;
-                }
-                if let Some(density) = density_for_voxel[v.0 as usize] {
-                    has_cloud = true;
-                    Some(density)
-                } else {
-                    Some(0.0)
-                }
-            })
-            .collect();
-        (densities, has_cloud)
+            .filled()
+            .any(|(_, voxel)| voxel.lookup(density_for_voxel).is_some());
+        if !has_cloud {
+            return (Vec::new(), false);
+        }
+        // remove the outer layer of voxels that the loader adds
+        let interior = RuntimeShape::<u32, 3>::new(self.meshed_size().into());
+        let mut densities = vec![0.0; interior.size() as usize];
+        for (index, voxel) in self.voxels.filled() {
+            let Some(density) = voxel.lookup(density_for_voxel) else {
+                continue;
+            };
+            let point = UVec3::from(self.shape.delinearize(index as u32));
+            if point.cmpeq(UVec3::ZERO).any() || point.cmpgt(self.meshed_size()).any() {
+                continue;
+            }
+            densities[interior.linearize((point - UVec3::ONE).into()) as usize] = density;
+        }
+        (densities, true)
     }
 }
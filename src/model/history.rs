@@ -0,0 +1,337 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    asset::{AssetId, Assets},
+    ecs::{
+        resource::Resource,
+        system::{In, Res, ResMut},
+    },
+    math::{IVec3, Vec3},
+};
+use ndshape::Shape;
+
+use crate::{UnitOffset, VoxelModelInstance};
+
+use super::{
+    RawVoxel, VoxelContext, VoxelData, VoxelModel, VoxelQueryable,
+    modify::{ModifiedMeshes, VoxelModifier, VoxelRegion, VoxelRegionMode},
+    resize::VoxelResize,
+    resync::VoxelModelSync,
+};
+
+/// Records the voxels overwritten by each [`VoxelModifier`], so that modifications can be undone with
+/// [`undo_voxel_edit`] and redone with [`redo_voxel_edit`].
+///
+/// History is only recorded while this resource exists, so insert it to enable undo:
+/// `app.insert_resource(VoxelEditHistory::new(8 * 1024 * 1024))`. Modifications of every model are recorded in the
+/// one history, in the order that they were applied, and can be undone either in that order or one model at a time.
+///
+/// The previous voxels of each modified region are stored run-length encoded. A modification that resizes the model
+/// records the whole model, so that undoing it restores the model's size and the voxels that it dropped. Once the
+/// history grows beyond its `memory_budget`, the oldest edits are forgotten.
+///
+/// ### Notes
+/// Undoing a modification restores the voxels of the region it modified to how they were before it, so any changes
+/// made to the same region since then without recording history are also reverted.
+#[derive(Resource)]
+pub struct VoxelEditHistory {
+    /// The approximate number of bytes the history may use. When this is exceeded, the oldest edits that can be
+    /// undone are dropped first, followed by the oldest edits that can be redone.
+    pub memory_budget: usize,
+    undo: VecDeque<VoxelEdit>,
+    redo: VecDeque<VoxelEdit>,
+    memory_usage: usize,
+}
+
+impl Default for VoxelEditHistory {
+    /// A history with a memory budget of 16MiB
+    fn default() -> Self {
+        Self::new(16 * 1024 * 1024)
+    }
+}
+
+impl VoxelEditHistory {
+    /// Create an empty history that uses at most `memory_budget` bytes
+    pub fn new(memory_budget: usize) -> Self {
+        Self {
+            memory_budget,
+            undo: VecDeque::new(),
+            redo: VecDeque::new(),
+            memory_usage: 0,
+        }
+    }
+
+    /// Whether there is a modification to undo, either of the given `model`, or of any model if `model` is `None`
+    pub fn can_undo(&self, model: Option<AssetId<VoxelModel>>) -> bool {
+        self.undo.iter().any(|edit| edit.is_of(model))
+    }
+
+    /// Whether there is an undone modification to redo, either of the given `model`, or of any model if `model` is
+    /// `None`
+    pub fn can_redo(&self, model: Option<AssetId<VoxelModel>>) -> bool {
+        self.redo.iter().any(|edit| edit.is_of(model))
+    }
+
+    /// The approximate number of bytes used by the recorded edits
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+
+    /// Forget all of the recorded edits
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.memory_usage = 0;
+    }
+
+    /// Records a new modification, which discards the undone modifications of the same model
+    pub(super) fn record(&mut self, edit: VoxelEdit) {
+        let model = edit.instance.model.id();
+        self.shift(model, edit.shift);
+        let mut freed = 0;
+        self.redo.retain(|redo| {
+            let keep = redo.instance.model.id() != model;
+            if !keep {
+                freed += redo.size();
+            }
+            keep
+        });
+        self.memory_usage -= freed;
+        self.push_undo(edit);
+    }
+
+    /// Moves the regions of the model's edits with its voxels, after they moved by `shift` because the model was
+    /// resized
+    fn shift(&mut self, model: AssetId<VoxelModel>, shift: IVec3) {
+        if shift == IVec3::ZERO {
+            return;
+        }
+        for edit in self
+            .undo
+            .iter_mut()
+            .chain(self.redo.iter_mut())
+            .filter(|edit| edit.is_of(Some(model)))
+        {
+            edit.snapshot.origin += shift;
+        }
+    }
+
+    fn push_undo(&mut self, edit: VoxelEdit) {
+        self.memory_usage += edit.size();
+        self.undo.push_back(edit);
+        self.trim();
+    }
+
+    fn push_redo(&mut self, edit: VoxelEdit) {
+        self.memory_usage += edit.size();
+        self.redo.push_back(edit);
+        self.trim();
+    }
+
+    fn pop(
+        edits: &mut VecDeque<VoxelEdit>,
+        memory_usage: &mut usize,
+        model: Option<AssetId<VoxelModel>>,
+    ) -> Option<VoxelEdit> {
+        let index = edits.iter().rposition(|edit| edit.is_of(model))?;
+        let edit = edits.remove(index)?;
+        *memory_usage -= edit.size();
+        Some(edit)
+    }
+
+    /// Drops the oldest edits until the history is within its budget
+    fn trim(&mut self) {
+        while self.memory_usage > self.memory_budget {
+            let Some(edit) = self.undo.pop_front().or_else(|| self.redo.pop_front()) else {
+                break;
+            };
+            self.memory_usage -= edit.size();
+        }
+    }
+}
+
+/// The voxels of a region of a model before it was modified, and the meshes to update when they're restored
+pub(super) struct VoxelEdit {
+    instance: VoxelModelInstance,
+    meshes: ModifiedMeshes,
+    snapshot: VoxelSnapshot,
+    /// The size of the model before and after the modification, if it resized the model. The snapshot of a resize
+    /// holds the whole model as it was before.
+    resize: Option<(IVec3, IVec3)>,
+    /// How far the model's existing voxels moved when it was resized by the modification
+    shift: IVec3,
+}
+
+impl VoxelEdit {
+    pub(super) fn new(
+        modifier: &VoxelModifier,
+        snapshot: VoxelSnapshot,
+        resize: Option<(IVec3, IVec3)>,
+        shift: IVec3,
+    ) -> Self {
+        Self {
+            instance: modifier.instance.clone(),
+            meshes: modifier.meshes.clone(),
+            snapshot,
+            resize,
+            shift,
+        }
+    }
+
+    fn is_of(&self, model: Option<AssetId<VoxelModel>>) -> bool {
+        model.is_none_or(|model| self.instance.model.id() == model)
+    }
+
+    fn size(&self) -> usize {
+        size_of::<Self>() + self.snapshot.runs.len() * size_of::<(RawVoxel, u16)>()
+    }
+
+    /// Writes the recorded voxels back into the model, and returns the edit that reverses this
+    fn restore(
+        self,
+        models: &mut Assets<VoxelModel>,
+        contexts: &Assets<VoxelContext>,
+        sync: &mut VoxelModelSync,
+    ) -> Option<VoxelEdit> {
+        let VoxelSnapshot { origin, size, runs } = self.snapshot;
+        let voxels: Vec<RawVoxel> = runs
+            .into_iter()
+            .flat_map(|(voxel, count)| std::iter::repeat_n(voxel, count as usize))
+            .collect();
+        let (region, resize) = match self.resize {
+            // the model is resized back, which moves its voxels back by the shift, and then all of them are restored
+            Some((previous_size, resized)) => {
+                let growth = (previous_size - resized).as_vec3();
+                let anchor = Vec3::select(
+                    growth.cmpeq(Vec3::ZERO),
+                    Vec3::ZERO,
+                    -self.shift.as_vec3() / growth,
+                );
+                let resize = VoxelResize::To {
+                    size: previous_size.as_uvec3(),
+                    anchor: UnitOffset(anchor),
+                };
+                (VoxelRegionMode::All, Some(resize))
+            }
+            None => (VoxelRegionMode::Box(VoxelRegion { origin, size }), None),
+        };
+        // a resize since the edit was recorded may have added or removed chunks
+        let meshes = match self.meshes {
+            ModifiedMeshes::Chunks(chunks) => {
+                ModifiedMeshes::Chunks(sync.chunks(self.instance.model.id()).unwrap_or(chunks))
+            }
+            meshes => meshes,
+        };
+        let modifier = VoxelModifier {
+            instance: self.instance,
+            meshes,
+            region,
+            resize,
+            modify: Box::new(move |position, voxel, _, _| {
+                let local = position - origin;
+                // the region may have been clipped by the model shrinking since the edit was recorded
+                if local.cmplt(IVec3::ZERO).any() || local.cmpge(size).any() {
+                    return voxel.clone();
+                }
+                let index = (local.x * size.y + local.y) * size.z + local.z;
+                voxels[index as usize].into()
+            }),
+        };
+        modifier.run(models, contexts, sync, true)
+    }
+}
+
+/// The voxels of a region, run-length encoded in the order that [`VoxelModifier`] visits them
+pub(super) struct VoxelSnapshot {
+    origin: IVec3,
+    size: IVec3,
+    runs: Vec<(RawVoxel, u16)>,
+}
+
+impl VoxelSnapshot {
+    pub(super) fn new(region: &VoxelRegion) -> Self {
+        Self {
+            origin: region.origin,
+            size: region.size,
+            runs: Vec::new(),
+        }
+    }
+
+    /// Records every voxel of the model
+    pub(super) fn of_model(data: &VoxelData) -> Self {
+        let size = data.size();
+        let mut snapshot = Self::new(&VoxelRegion {
+            origin: IVec3::ZERO,
+            size,
+        });
+        let leading_padding = data.padding() / 2;
+        for x in 0..size.x as u32 {
+            for y in 0..size.y as u32 {
+                for z in 0..size.z as u32 {
+                    let point = [x, y, z].map(|v| v + leading_padding);
+                    snapshot.push(data.voxels.at(data.shape.linearize(point) as usize));
+                }
+            }
+        }
+        snapshot
+    }
+
+    pub(super) fn push(&mut self, voxel: RawVoxel) {
+        match self.runs.last_mut() {
+            Some((last, count)) if *last == voxel && *count < u16::MAX => *count += 1,
+            _ => self.runs.push((voxel, 1)),
+        }
+    }
+}
+
+/// System that undoes the most recent modification recorded in the [`VoxelEditHistory`], and remeshes the model.
+///
+/// Takes the id of the model whose last modification should be undone as its input, or `None` to undo the last
+/// modification of any model.
+///
+/// ### Example
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_vox_scene::undo_voxel_edit;
+/// # let mut commands: Commands = panic!();
+/// commands.run_system_cached_with(undo_voxel_edit, None);
+/// ```
+pub fn undo_voxel_edit(
+    In(model): In<Option<AssetId<VoxelModel>>>,
+    mut history: ResMut<VoxelEditHistory>,
+    mut models: ResMut<Assets<VoxelModel>>,
+    contexts: Res<Assets<VoxelContext>>,
+    mut sync: VoxelModelSync,
+) {
+    let history = history.as_mut();
+    let Some(edit) = VoxelEditHistory::pop(&mut history.undo, &mut history.memory_usage, model)
+    else {
+        return;
+    };
+    if let Some(redo) = edit.restore(&mut models, &contexts, &mut sync) {
+        history.shift(redo.instance.model.id(), redo.shift);
+        history.push_redo(redo);
+    }
+}
+
+/// System that redoes the most recent modification undone by [`undo_voxel_edit`], and remeshes the model.
+///
+/// Takes the id of the model whose last undone modification should be redone as its input, or `None` to redo the last
+/// undone modification of any model.
+pub fn redo_voxel_edit(
+    In(model): In<Option<AssetId<VoxelModel>>>,
+    mut history: ResMut<VoxelEditHistory>,
+    mut models: ResMut<Assets<VoxelModel>>,
+    contexts: Res<Assets<VoxelContext>>,
+    mut sync: VoxelModelSync,
+) {
+    let history = history.as_mut();
+    let Some(edit) = VoxelEditHistory::pop(&mut history.redo, &mut history.memory_usage, model)
+    else {
+        return;
+    };
+    if let Some(undo) = edit.restore(&mut models, &contexts, &mut sync) {
+        history.shift(undo.instance.model.id(), undo.shift);
+        history.push_undo(undo);
+    }
+}
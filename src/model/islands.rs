@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    asset::Assets,
+    ecs::{entity::Entity, system::In, world::World},
+    math::{IVec3, UVec3, Vec3},
+    scene::SceneRoot,
+    transform::components::{GlobalTransform, Transform},
+};
+use ndshape::Shape;
+
+use crate::{VoxelModelInstance, create_voxel_scene};
+
+use super::{RawVoxel, VoxelData, VoxelModel, VoxelQueryable};
+
+/// Which neighbouring voxels are connected to each other when finding the islands of a model
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VoxelConnectivity {
+    /// Voxels are connected if they share a face, so each voxel has 6 neighbours
+    #[default]
+    Faces,
+    /// Voxels are connected if they share a face, an edge, or a corner, so each voxel has 26 neighbours
+    Corners,
+}
+
+impl VoxelConnectivity {
+    fn neighbours(&self) -> Vec<IVec3> {
+        match self {
+            VoxelConnectivity::Faces => vec![
+                IVec3::X,
+                IVec3::NEG_X,
+                IVec3::Y,
+                IVec3::NEG_Y,
+                IVec3::Z,
+                IVec3::NEG_Z,
+            ],
+            VoxelConnectivity::Corners => (-1..=1)
+                .flat_map(|x| {
+                    (-1..=1).flat_map(move |y| (-1..=1).map(move |z| IVec3::new(x, y, z)))
+                })
+                .filter(|offset| *offset != IVec3::ZERO)
+                .collect(),
+        }
+    }
+}
+
+/// A group of connected voxels, found by [`VoxelData::islands`]
+#[derive(Clone, Debug)]
+pub struct VoxelIsland {
+    /// The voxels of the island, in a model just big enough to hold them
+    pub data: VoxelData,
+    /// The coordinate of the island's minimum corner in the voxel space of the model it was found in
+    pub origin: IVec3,
+    /// Where the island's model should be placed in the local space of the model it was found in, so that its voxels
+    /// stay in the same place
+    pub translation: Vec3,
+}
+
+impl VoxelData {
+    /// Finds the groups of connected voxels in the model, and copies each of them into a new [`VoxelData`].
+    ///
+    /// Every voxel that isn't [`crate::Voxel::EMPTY`] belongs to an island, including transmissive and cloud voxels.
+    /// The islands are ordered by their first voxel, scanning along the x axis first, then y, then z.
+    pub fn islands(&self, connectivity: VoxelConnectivity) -> Vec<VoxelIsland> {
+        let size = self.size();
+        if size.cmple(IVec3::ZERO).any() {
+            return Vec::new();
+        }
+        let leading_padding = IVec3::splat(self.padding() as i32 / 2);
+        let index_of = |point: IVec3| {
+            self.shape
+                .linearize((point + leading_padding).as_uvec3().into()) as usize
+        };
+        let neighbours = connectivity.neighbours();
+        let mut visited = vec![false; self.voxels.len()];
+        let mut islands = Vec::new();
+        for z in 0..size.z {
+            for y in 0..size.y {
+                for x in 0..size.x {
+                    let start = IVec3::new(x, y, z);
+                    let index = index_of(start);
+                    if visited[index] || self.voxels.at(index) == RawVoxel::EMPTY {
+                        continue;
+                    }
+                    // flood fill the island from its first voxel
+                    visited[index] = true;
+                    let mut queue = VecDeque::from([start]);
+                    let mut points = Vec::new();
+                    let (mut min, mut max) = (start, start);
+                    while let Some(point) = queue.pop_front() {
+                        points.push(point);
+                        min = min.min(point);
+                        max = max.max(point);
+                        for offset in neighbours.iter() {
+                            let neighbour = point + *offset;
+                            if neighbour.cmplt(IVec3::ZERO).any() || neighbour.cmpge(size).any() {
+                                continue;
+                            }
+                            let index = index_of(neighbour);
+                            if !visited[index] && self.voxels.at(index) != RawVoxel::EMPTY {
+                                visited[index] = true;
+                                queue.push_back(neighbour);
+                            }
+                        }
+                    }
+                    islands.push(self.extract_island(&points, min, max));
+                }
+            }
+        }
+        islands
+    }
+
+    fn extract_island(&self, points: &[IVec3], min: IVec3, max: IVec3) -> VoxelIsland {
+        let mut data = VoxelData::new((max - min + IVec3::ONE).as_uvec3(), self.settings.clone());
+        let leading_padding = UVec3::splat(self.padding() / 2);
+        for point in points {
+            let index = self
+                .shape
+                .linearize((point.as_uvec3() + leading_padding).into())
+                as usize;
+            data.set_voxel(self.voxels.at(index).into(), (*point - min).as_uvec3());
+        }
+        let translation =
+            self.voxel_coord_to_local_space(min) - data.voxel_coord_to_local_space(IVec3::ZERO);
+        VoxelIsland {
+            data,
+            origin: min,
+            translation,
+        }
+    }
+}
+
+/// System that splits a spawned voxel model into its disconnected islands, so that, for instance, the pieces of a
+/// model that has been cut in two can fall away as debris.
+///
+/// Takes the entity with the [`VoxelModelInstance`] to split, and the [`VoxelConnectivity`] used to find the islands.
+/// If the model has more than one island, each island is made into a new [`VoxelModel`] and scene with
+/// [`create_voxel_scene`], which is spawned in world space with a [`Transform`] that keeps it where it was, and the
+/// original entity is despawned.
+///
+/// ### Returns
+/// The [`SceneRoot`] entity of each island, or an empty list if the model wasn't split
+///
+/// ### Example
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_vox_scene::{VoxelConnectivity, split_voxel_islands};
+/// # let mut world: World = panic!();
+/// # let entity: Entity = panic!();
+/// let pieces = world
+///     .run_system_cached_with(split_voxel_islands, (entity, VoxelConnectivity::Faces))
+///     .expect("Split voxel model");
+/// ```
+pub fn split_voxel_islands(
+    In((entity, connectivity)): In<(Entity, VoxelConnectivity)>,
+    world: &mut World,
+) -> Vec<Entity> {
+    let Some(instance) = world.get::<VoxelModelInstance>(entity).cloned() else {
+        return Vec::new();
+    };
+    let global_transform = world
+        .get::<GlobalTransform>(entity)
+        .copied()
+        .unwrap_or_default()
+        .compute_transform();
+    let Some(model) = world.resource::<Assets<VoxelModel>>().get(&instance.model) else {
+        return Vec::new();
+    };
+    let name = model.name.clone();
+    let islands = model.data.islands(connectivity);
+    if islands.len() < 2 {
+        return Vec::new();
+    }
+    let mut pieces = Vec::with_capacity(islands.len());
+    for (index, island) in islands.into_iter().enumerate() {
+        let Ok(scene) = world.run_system_cached_with(
+            create_voxel_scene,
+            (
+                island.data,
+                format!("{name}-island{index}"),
+                instance.context.clone(),
+            ),
+        ) else {
+            continue;
+        };
+        let transform = global_transform * Transform::from_translation(island.translation);
+        pieces.push(world.spawn((SceneRoot(scene), transform)).id());
+    }
+    world.despawn(entity);
+    pieces
+}
@@ -0,0 +1,141 @@
+use bevy::{
+    asset::Handle,
+    ecs::{bundle::Bundle, component::Component, reflect::ReflectComponent, world::EntityWorldMut},
+    math::UVec3,
+    pbr::{MeshMaterial3d, StandardMaterial},
+    reflect::Reflect,
+    render::{
+        mesh::{Mesh, Mesh3d},
+        view::VisibilityRange,
+    },
+    transform::components::Transform,
+};
+use ndshape::Shape;
+
+use super::{RawVoxel, VoxelData, VoxelQueryable};
+
+impl VoxelData {
+    /// Returns a copy of the model at a lower resolution, where each block of `factor`³ voxels becomes a single voxel
+    /// of the most common palette index in the block. Cloud voxels are ignored, so that a block containing any solid
+    /// or transmissive voxels remains visible in the mesh.
+    pub(crate) fn downsampled(&self, factor: u32, density_for_voxel: &[Option<f32>]) -> VoxelData {
+        let size = self.size().as_uvec3();
+        let mut settings = self.settings.clone();
+        settings.voxel_size *= factor as f32;
+        settings.lod_distances.clear();
+        let mut downsampled = VoxelData::new((size + UVec3::splat(factor - 1)) / factor, settings);
+        let leading_padding = UVec3::splat(self.padding() / 2);
+        let mut block: Vec<u16> = Vec::with_capacity(factor.pow(3) as usize);
+        for z in 0..downsampled.size().z as u32 {
+            for y in 0..downsampled.size().y as u32 {
+                for x in 0..downsampled.size().x as u32 {
+                    let block_min = UVec3::new(x, y, z) * factor;
+                    let block_max = (block_min + UVec3::splat(factor)).min(size);
+                    block.clear();
+                    for bz in block_min.z..block_max.z {
+                        for by in block_min.y..block_max.y {
+                            for bx in block_min.x..block_max.x {
+                                let point = UVec3::new(bx, by, bz) + leading_padding;
+                                let voxel =
+                                    self.voxels.at(self.shape.linearize(point.into()) as usize);
+                                if voxel != RawVoxel::EMPTY
+                                    && voxel.lookup(density_for_voxel).is_none()
+                                {
+                                    block.push(voxel.0);
+                                }
+                            }
+                        }
+                    }
+                    let Some(index) = most_common(&mut block) else {
+                        continue;
+                    };
+                    let point = UVec3::new(x, y, z) + UVec3::splat(downsampled.padding() / 2);
+                    let linear_index = downsampled.shape.linearize(point.into()) as usize;
+                    downsampled.voxels.set(linear_index, RawVoxel(index));
+                }
+            }
+        }
+        downsampled
+    }
+
+    /// Creates a mesh for each level of detail in [`crate::VoxLoaderSettings::lod_distances`], with the model
+    /// downsampled by 2x, 4x, and so on.
+    pub(crate) fn lod_meshes(
+        &self,
+        ior_for_voxel: &[Option<f32>],
+        density_for_voxel: &[Option<f32>],
+    ) -> Vec<Mesh> {
+        (1..=self.settings.lod_distances.len())
+            .map(|level| {
+                let lod = self.downsampled(1 << level, density_for_voxel);
+                // the downsampled model can be larger than the original if its size isn't a multiple of the factor,
+                // so realign it with the origin of the full resolution mesh
+                let offset = (lod.model_size() - self.model_size()) * self.settings.mesh_offset.0;
+                lod.mesh(ior_for_voxel, density_for_voxel)
+                    .translated_by(offset)
+            })
+            .collect()
+    }
+}
+
+/// Marks the children of a model instance that hold its levels of detail, so that they can be replaced when the model
+/// is modified
+#[derive(Component, Reflect, Clone, Copy, Debug, Default)]
+#[reflect(Component)]
+pub(crate) struct LodMesh;
+
+/// The [`VisibilityRange`] of each level of detail, starting with the full resolution mesh
+pub(crate) fn lod_visibility_ranges(lod_distances: &[f32]) -> Vec<VisibilityRange> {
+    let mut distances = vec![0.0];
+    distances.extend_from_slice(lod_distances);
+    distances.push(f32::MAX);
+    distances
+        .windows(2)
+        .map(|range| VisibilityRange::abrupt(range[0], range[1]))
+        .collect()
+}
+
+fn most_common(indices: &mut [u16]) -> Option<u16> {
+    indices.sort_unstable();
+    indices
+        .chunk_by(|a, b| a == b)
+        .max_by_key(|run| run.len())
+        .map(|run| run[0])
+}
+
+/// Adds a [`VisibilityRange`] to the entity containing the full resolution mesh, and spawns a child for each level of
+/// detail, so that the meshes switch according to their distance from the camera.
+pub(crate) fn insert_lod_meshes(
+    entity: &mut EntityWorldMut,
+    lod_meshes: Vec<Handle<Mesh>>,
+    material: Handle<StandardMaterial>,
+    lod_distances: &[f32],
+) {
+    if lod_meshes.is_empty() {
+        return;
+    }
+    let mut ranges = lod_visibility_ranges(lod_distances).into_iter();
+    if let Some(range) = ranges.next() {
+        entity.insert(range);
+    }
+    entity.with_children(|spawner| {
+        for (mesh, range) in lod_meshes.into_iter().zip(ranges) {
+            spawner.spawn(lod_mesh_bundle(mesh, material.clone(), range));
+        }
+    });
+}
+
+/// The components of the child that holds one of the model's levels of detail
+pub(crate) fn lod_mesh_bundle(
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+    range: VisibilityRange,
+) -> impl Bundle {
+    (
+        Mesh3d(mesh),
+        MeshMaterial3d(material),
+        range,
+        Transform::IDENTITY,
+        LodMesh,
+    )
+}
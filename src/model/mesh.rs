@@ -1,38 +1,99 @@
 use bevy::{
     math::Vec3,
+    prelude::default,
     render::{
         mesh::{Indices, Mesh, VertexAttributeValues},
         render_asset::RenderAssetUsages,
         render_resource::PrimitiveTopology,
     },
 };
-use block_mesh::{GreedyQuadsBuffer, RIGHT_HANDED_Y_UP_CONFIG, greedy_quads};
+use block_mesh::{
+    GreedyQuadsBuffer, RIGHT_HANDED_Y_UP_CONFIG, UnorientedQuad, VoxelVisibility, greedy_quads,
+};
 use ndshape::Shape;
 
-use super::{VoxelData, VoxelQueryable, voxel::VisibleVoxel};
+use super::{
+    VoxelData, VoxelQueryable,
+    ambient_occlusion::{AO_BRIGHTNESS, AmbientOcclusion},
+    palette::palette_atlas_width,
+    voxel::VisibleVoxel,
+};
+
+/// Meshes the faces of all visible voxels. `palette_size` is the number of elements in the palette, which sets the
+/// layout of the palette texture that the UVs of the mesh point into.
+pub(crate) fn mesh_model(voxels: &[VisibleVoxel], data: &VoxelData, palette_size: usize) -> Mesh {
+    mesh_faces(voxels, data, palette_size, |_| true)
+}
+
+/// Meshes only the faces of translucent voxels, with opaque voxels hiding the faces they touch
+pub(crate) fn mesh_translucent_faces(
+    voxels: &[VisibleVoxel],
+    data: &VoxelData,
+    palette_size: usize,
+) -> Mesh {
+    mesh_faces(voxels, data, palette_size, |voxel| {
+        voxel.visibility == VoxelVisibility::Translucent
+    })
+}
 
-pub(crate) fn mesh_model(voxels: &[VisibleVoxel], data: &VoxelData) -> Mesh {
+fn mesh_faces(
+    voxels: &[VisibleVoxel],
+    data: &VoxelData,
+    palette_size: usize,
+    include_face: impl Fn(&VisibleVoxel) -> bool,
+) -> Mesh {
+    let atlas_width = palette_atlas_width(palette_size);
+    let texel_size = 1.0 / atlas_width as f32;
     let mut greedy_quads_buffer = GreedyQuadsBuffer::new(data.shape.size() as usize);
     let quads_config = RIGHT_HANDED_Y_UP_CONFIG;
-    greedy_quads(
-        voxels,
-        &data.shape,
-        [0; 3],
-        data.shape.as_array().map(|x| x - 1),
-        &quads_config.faces,
-        &mut greedy_quads_buffer,
-    );
+    let ambient_occlusion = data
+        .settings
+        .ambient_occlusion
+        .then(|| AmbientOcclusion::new(voxels, &data.shape));
+    let mut quads: [Vec<UnorientedQuad>; 6] = default();
+    if let Some(ambient_occlusion) = &ambient_occlusion {
+        // mesh each direction separately, so that coplanar faces are merged unless their own occlusion differs
+        let mut occluded = voxels.to_vec();
+        for (face_index, face) in quads_config.faces.iter().enumerate() {
+            ambient_occlusion.occlude_voxels(face, &mut occluded);
+            greedy_quads(
+                &occluded,
+                &data.shape,
+                [0; 3],
+                data.shape.as_array().map(|x| x - 1),
+                &quads_config.faces,
+                &mut greedy_quads_buffer,
+            );
+            quads[face_index] = std::mem::take(&mut greedy_quads_buffer.quads.groups[face_index]);
+        }
+    } else {
+        greedy_quads(
+            voxels,
+            &data.shape,
+            [0; 3],
+            data.shape.as_array().map(|x| x - 1),
+            &quads_config.faces,
+            &mut greedy_quads_buffer,
+        );
+        quads = greedy_quads_buffer.quads.groups;
+    }
     let offset = data.model_size() * data.settings.mesh_offset.0; // center the mesh
     let leading_padding = (data.padding() / 2) as f32 * data.settings.voxel_size; // corrects the 1 offset introduced by the meshing.
     let position_offset = offset + Vec3::splat(leading_padding);
 
-    let num_indices = greedy_quads_buffer.quads.num_quads() * 6;
-    let num_vertices = greedy_quads_buffer.quads.num_quads() * 4;
+    let num_quads: usize = quads.iter().map(Vec::len).sum();
+    let num_indices = num_quads * 6;
+    let num_vertices = num_quads * 4;
 
     let mut indices = Vec::with_capacity(num_indices);
     let mut positions = Vec::with_capacity(num_vertices);
     let mut normals = Vec::with_capacity(num_vertices);
     let mut uvs = Vec::with_capacity(num_vertices);
+    let mut colors = Vec::with_capacity(if ambient_occlusion.is_some() {
+        num_vertices
+    } else {
+        0
+    });
 
     let mut render_mesh = Mesh::new(
         PrimitiveTopology::TriangleList,
@@ -43,15 +104,32 @@ pub(crate) fn mesh_model(voxels: &[VisibleVoxel], data: &VoxelData) -> Mesh {
         },
     );
 
-    for (group, face) in greedy_quads_buffer
-        .quads
-        .groups
-        .iter()
-        .zip(quads_config.faces.as_ref())
-    {
+    for (group, face) in quads.iter().zip(quads_config.faces.as_ref()) {
         for quad in group.iter() {
-            let palette_index = voxels[data.shape.linearize(quad.minimum) as usize].index;
-            indices.extend_from_slice(&face.quad_mesh_indices(positions.len() as u32));
+            let voxel = &voxels[data.shape.linearize(quad.minimum) as usize];
+            if !include_face(voxel) {
+                continue;
+            }
+            let palette_index = voxel.index;
+            let start = positions.len() as u32;
+            let mut quad_indices = face.quad_mesh_indices(start);
+            if let Some(ambient_occlusion) = &ambient_occlusion {
+                let levels = ambient_occlusion.quad_levels(face, quad);
+                // split the quad along the diagonal between its more occluded corners, so that
+                // the occlusion is interpolated symmetrically
+                if levels[0] + levels[3] < levels[1] + levels[2] {
+                    quad_indices = if quad_indices[1] == start + 1 {
+                        [start, start + 1, start + 3, start, start + 3, start + 2]
+                    } else {
+                        [start, start + 3, start + 1, start, start + 2, start + 3]
+                    };
+                }
+                colors.extend(levels.map(|level| {
+                    let brightness = AO_BRIGHTNESS[level as usize];
+                    [brightness, brightness, brightness, 1.0]
+                }));
+            }
+            indices.extend_from_slice(&quad_indices);
             positions.extend_from_slice(
                 &face
                     .quad_mesh_positions(quad, data.settings.voxel_size)
@@ -63,8 +141,8 @@ pub(crate) fn mesh_model(voxels: &[VisibleVoxel], data: &VoxelData) -> Mesh {
                         ]
                     }),
             );
-            let u = ((palette_index % 16) as f32 + 0.5) / 16.0;
-            let v = ((palette_index / 16) as f32 + 0.5) / 16.0;
+            let u = ((palette_index as u32 % atlas_width) as f32 + 0.5) * texel_size;
+            let v = ((palette_index as u32 / atlas_width) as f32 + 0.5) * texel_size;
             uvs.extend_from_slice(&[[u, v], [u, v], [u, v], [u, v]]);
             normals.extend_from_slice(&face.quad_mesh_normals());
         }
@@ -80,6 +158,12 @@ pub(crate) fn mesh_model(voxels: &[VisibleVoxel], data: &VoxelData) -> Mesh {
         VertexAttributeValues::Float32x3(normals),
     );
     render_mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, VertexAttributeValues::Float32x2(uvs));
+    if ambient_occlusion.is_some() {
+        render_mesh.insert_attribute(
+            Mesh::ATTRIBUTE_COLOR,
+            VertexAttributeValues::Float32x4(colors),
+        );
+    }
 
     render_mesh.insert_indices(Indices::U32(indices.clone()));
 
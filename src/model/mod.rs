@@ -3,9 +3,10 @@ use bevy::{
     asset::{Asset, Assets, Handle},
     ecs::{
         system::{In, ResMut},
-        world::World,
+        world::{EntityWorldMut, World},
     },
     image::Image,
+    math::Vec3,
     pbr::{FogVolume, MeshMaterial3d, StandardMaterial},
     prelude::Res,
     reflect::TypePath,
@@ -17,23 +18,47 @@ use bevy::{
     transform::components::Transform,
 };
 
-pub use self::{data::VoxelData, voxel::Voxel};
+pub use self::{
+    chunk::VoxelChunks,
+    collider::{VoxelBox, VoxelCollider, VoxelColliderSettings},
+    data::VoxelData,
+    storage::VoxelStorage,
+    voxel::Voxel,
+};
 use crate::{VoxelAnimationPlayer, load::VoxelAnimationFrame};
 pub(crate) use palette::MaterialProperty;
 pub(crate) use voxel::RawVoxel;
+mod ambient_occlusion;
+pub(super) mod chunk;
+pub(super) mod collider;
 pub(super) mod data;
+#[cfg(feature = "modify_voxels")]
+pub(super) mod history;
+#[cfg(all(feature = "generate_voxels", feature = "modify_voxels"))]
+pub(super) mod islands;
+pub(super) mod lod;
 pub(super) mod mesh;
 #[cfg(feature = "modify_voxels")]
 pub(super) mod modify;
 #[cfg(feature = "modify_voxels")]
+pub(super) mod modify_async;
+#[cfg(feature = "modify_voxels")]
 pub(super) mod queryable;
+pub(super) mod recolor;
+#[cfg(feature = "modify_voxels")]
+pub(super) mod resize;
+#[cfg(feature = "modify_voxels")]
+pub(super) mod resync;
 #[cfg(feature = "generate_voxels")]
 pub(super) mod sdf;
+pub(super) mod transmissive;
 #[cfg(feature = "modify_voxels")]
 pub use self::queryable::VoxelQueryable;
 mod palette;
 pub use palette::{VoxelElement, VoxelPalette};
 pub(super) mod cloud;
+pub(super) mod palette_animation;
+pub(super) mod storage;
 mod voxel;
 
 /// Contains the voxel data for a model
@@ -43,7 +68,8 @@ pub struct VoxelModel {
     pub name: String,
     /// The voxel data used to generate the mesh
     pub(crate) data: VoxelData,
-    /// True if the model contains solid or transmissive voxels
+    /// True if the model contains solid or transmissive voxels. If the transmissive voxels are split into separate
+    /// meshes by [`crate::VoxLoaderSettings::ior_bucket_size`], only solid voxels are counted.
     pub has_mesh: bool,
     /// True if the model contains cloud voxels
     pub has_cloud: bool,
@@ -61,56 +87,27 @@ pub fn create_voxel_scene(
     contexts: Res<Assets<VoxelContext>>,
 ) -> Handle<Scene> {
     let context = contexts.get(&context_handle).expect("Voxel Context exists");
-    let (maybe_mesh, average_ior, maybe_cloud) = data.remesh(
-        &context.palette.indices_of_refraction,
-        &context.palette.density_for_voxel,
-    );
-    let maybe_mesh_handle = maybe_mesh.map(|mesh| meshes.add(mesh));
-    let cloud_image = maybe_cloud.map(|image| images.add(image));
-
-    let model = VoxelModel {
-        name: name.clone(),
-        data: data.clone(),
-        has_mesh: maybe_mesh_handle.is_some(),
-        has_cloud: cloud_image.is_some(),
+    let mut model = VoxelModel {
+        name,
+        data,
+        has_mesh: false,
+        has_cloud: false,
     };
-    let model_handle = models.add(model.clone());
-
     let mut world = World::new();
-    let mut root = world.spawn((
-        Transform::IDENTITY,
-        Visibility::Visible,
-        VoxelModelInstance {
-            model: model_handle,
-            context: context_handle,
-        },
-    ));
+    let mut root = world.spawn((Transform::IDENTITY, Visibility::Visible));
     // TODO boolean for "retain model data"
-    if let Some(mesh_handle) = maybe_mesh_handle {
-        root.insert(Mesh3d(mesh_handle));
-        if let Some(ior) = average_ior {
-            let mut transmissive_material = materials
-                .get(context.transmissive_material.id())
-                .expect("Transmissive materialo exists")
-                .clone();
-            transmissive_material.ior = ior;
-            transmissive_material.thickness = data.size().min_element() as f32;
-            let mat_handle = materials.add(transmissive_material);
-            root.insert(MeshMaterial3d(mat_handle));
-        } else {
-            root.insert(MeshMaterial3d(context.opaque_material.clone()));
-        }
-    }
-    if cloud_image.is_some() {
-        root.with_child((
-            FogVolume {
-                density_texture: cloud_image,
-                absorption: 0.1,
-                ..Default::default()
-            },
-            Transform::from_scale(model.model_size()),
-        ));
-    }
+    (model.has_mesh, model.has_cloud) = insert_model_meshes(
+        &mut root,
+        &model.data,
+        context,
+        &mut meshes,
+        &mut materials,
+        &mut images,
+    );
+    root.insert(VoxelModelInstance {
+        model: models.add(model),
+        context: context_handle,
+    });
     let scene = Scene::new(world);
     scenes.add(scene)
 }
@@ -131,25 +128,7 @@ pub fn create_voxel_animation(
     let mut root = world.spawn((Transform::IDENTITY, Visibility::Visible));
     root.with_children(|spawner| {
         for (index, data) in frames.iter().enumerate() {
-            let (maybe_mesh, average_ior, maybe_cloud) = data.remesh(
-                &context.palette.indices_of_refraction,
-                &context.palette.density_for_voxel,
-            );
-            let maybe_mesh_handle = maybe_mesh.map(|mesh| meshes.add(mesh));
-            let cloud_image = maybe_cloud.map(|image| images.add(image));
-
-            let model = VoxelModel {
-                name: format!("{}-{}", name, index),
-                data: data.clone(),
-                has_mesh: maybe_mesh_handle.is_some(),
-                has_cloud: cloud_image.is_some(),
-            };
-            let model_handle = models.add(model.clone());
             let mut frame = spawner.spawn((
-                VoxelModelInstance {
-                    model: model_handle,
-                    context: context_handle.clone(),
-                },
                 VoxelAnimationFrame(index),
                 Transform::IDENTITY,
                 if index == 0 {
@@ -158,31 +137,24 @@ pub fn create_voxel_animation(
                     Visibility::Hidden
                 },
             ));
-            if let Some(mesh_handle) = maybe_mesh_handle {
-                frame.insert(Mesh3d(mesh_handle));
-                if let Some(ior) = average_ior {
-                    let mut transmissive_material = materials
-                        .get(context.transmissive_material.id())
-                        .expect("Transmissive materialo exists")
-                        .clone();
-                    transmissive_material.ior = ior;
-                    transmissive_material.thickness = data.size().min_element() as f32;
-                    let mat_handle = materials.add(transmissive_material);
-                    frame.insert(MeshMaterial3d(mat_handle));
-                } else {
-                    frame.insert(MeshMaterial3d(context.opaque_material.clone()));
-                }
-            }
-            if cloud_image.is_some() {
-                frame.with_child((
-                    FogVolume {
-                        density_texture: cloud_image,
-                        absorption: 0.1,
-                        ..Default::default()
-                    },
-                    Transform::from_scale(model.model_size()),
-                ));
-            }
+            let (has_mesh, has_cloud) = insert_model_meshes(
+                &mut frame,
+                data,
+                context,
+                &mut meshes,
+                &mut materials,
+                &mut images,
+            );
+            let model = VoxelModel {
+                name: format!("{}-{}", name, index),
+                data: data.clone(),
+                has_mesh,
+                has_cloud,
+            };
+            frame.insert(VoxelModelInstance {
+                model: models.add(model),
+                context: context_handle.clone(),
+            });
         }
     });
     root.insert((VoxelAnimationPlayer {
@@ -193,6 +165,140 @@ pub fn create_voxel_animation(
     scenes.add(scene)
 }
 
+/// Meshes the voxel data, and adds the meshes, materials and cloud of a model to the entity. Returns whether the model
+/// has a mesh and a cloud.
+#[cfg(feature = "generate_voxels")]
+pub(crate) fn insert_model_meshes(
+    entity: &mut EntityWorldMut,
+    data: &VoxelData,
+    context: &VoxelContext,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    images: &mut Assets<Image>,
+) -> (bool, bool) {
+    let model_meshes = ModelMeshes::new(data, context, meshes, materials, images);
+    model_meshes.insert(entity);
+    (model_meshes.has_mesh(), model_meshes.has_cloud())
+}
+
+/// The meshes, materials and cloud of a model, added to the asset collections once so that they can be shared by each
+/// of its instances
+#[cfg(feature = "generate_voxels")]
+pub(crate) struct ModelMeshes {
+    /// The mesh of the whole model, and its levels of detail, or the mesh of each chunk, with their material
+    mesh: Option<(ModelMesh, Handle<StandardMaterial>)>,
+    transmissive_meshes: Vec<(Handle<Mesh>, Handle<StandardMaterial>)>,
+    cloud: Option<Handle<Image>>,
+    model_size: Vec3,
+    lod_distances: Vec<f32>,
+}
+
+#[cfg(feature = "generate_voxels")]
+enum ModelMesh {
+    Whole {
+        mesh: Handle<Mesh>,
+        lod_meshes: Vec<Handle<Mesh>>,
+    },
+    Chunks(Vec<Handle<Mesh>>),
+}
+
+#[cfg(feature = "generate_voxels")]
+impl ModelMeshes {
+    /// Meshes the voxel data, and adds the meshes, materials and cloud image to the asset collections
+    pub(crate) fn new(
+        data: &VoxelData,
+        context: &VoxelContext,
+        meshes: &mut Assets<Mesh>,
+        materials: &mut Assets<StandardMaterial>,
+        images: &mut Assets<Image>,
+    ) -> Self {
+        let ior_for_voxel = &context.palette.indices_of_refraction;
+        let density_for_voxel = &context.palette.density_for_voxel;
+        let (maybe_mesh, average_ior, maybe_cloud) = data.remesh(ior_for_voxel, density_for_voxel);
+        let mesh = maybe_mesh.map(|mesh| {
+            let material = if let Some(ior) = average_ior {
+                let mut transmissive_material = materials
+                    .get(context.transmissive_material.id())
+                    .expect("Transmissive material exists")
+                    .clone();
+                transmissive_material.ior = ior;
+                transmissive_material.thickness = data.size().min_element() as f32;
+                materials.add(transmissive_material)
+            } else {
+                context.opaque_material.clone()
+            };
+            let mesh = if data.settings.chunk_size.is_some() {
+                ModelMesh::Chunks(
+                    data.chunk_meshes(ior_for_voxel, density_for_voxel)
+                        .into_iter()
+                        .map(|mesh| meshes.add(mesh))
+                        .collect(),
+                )
+            } else {
+                ModelMesh::Whole {
+                    mesh: meshes.add(mesh),
+                    lod_meshes: data
+                        .lod_meshes(ior_for_voxel, density_for_voxel)
+                        .into_iter()
+                        .map(|mesh| meshes.add(mesh))
+                        .collect(),
+                }
+            };
+            (mesh, material)
+        });
+        Self {
+            mesh,
+            transmissive_meshes: transmissive::add_transmissive_meshes(
+                data, context, meshes, materials,
+            ),
+            cloud: maybe_cloud.map(|image| images.add(image)),
+            model_size: data.model_size(),
+            lod_distances: data.settings.lod_distances.clone(),
+        }
+    }
+
+    /// Whether the model contains solid or transmissive voxels
+    pub(crate) fn has_mesh(&self) -> bool {
+        self.mesh.is_some()
+    }
+
+    /// Whether the model contains cloud voxels
+    pub(crate) fn has_cloud(&self) -> bool {
+        self.cloud.is_some()
+    }
+
+    /// Adds the mesh and material to the entity, along with its levels of detail, or, if the mesh is split into
+    /// chunks, spawns a child for each chunk instead. The transmissive meshes and the cloud are spawned on children.
+    pub(crate) fn insert(&self, entity: &mut EntityWorldMut) {
+        match &self.mesh {
+            Some((ModelMesh::Whole { mesh, lod_meshes }, material)) => {
+                entity.insert((Mesh3d(mesh.clone()), MeshMaterial3d(material.clone())));
+                lod::insert_lod_meshes(
+                    entity,
+                    lod_meshes.clone(),
+                    material.clone(),
+                    &self.lod_distances,
+                );
+            }
+            Some((ModelMesh::Chunks(chunk_meshes), material)) => {
+                chunk::insert_chunk_meshes(entity, chunk_meshes.clone(), material.clone());
+            }
+            None => {}
+        }
+        transmissive::insert_transmissive_meshes(entity, self.transmissive_meshes.clone());
+        if let Some(image) = &self.cloud {
+            entity.with_child((
+                FogVolume {
+                    density_texture: Some(image.clone()),
+                    absorption: 0.1,
+                    ..Default::default()
+                },
+                Transform::from_scale(self.model_size),
+            ));
+        }
+    }
+}
+
 /// A [`VoxelPalette`] that can be shared by multiple models, and handles to the [`StandardMaterial`]s derived from the palette.
 #[derive(Asset, TypePath, Clone, Debug)]
 pub struct VoxelContext {
@@ -1,29 +1,58 @@
 use bevy::{
     asset::{Assets, Handle},
     ecs::system::{In, ResMut},
-    math::{IVec3, Vec3},
+    image::Image,
+    math::{IVec3, Vec2, Vec3},
     prelude::Res,
     render::mesh::Mesh,
 };
 use ndshape::Shape;
 
+#[cfg(feature = "generate_voxels")]
+use crate::SDF;
 use crate::VoxelModelInstance;
 
-use super::{RawVoxel, Voxel, VoxelContext, VoxelModel, VoxelQueryable};
+use super::{
+    RawVoxel, Voxel, VoxelChunks, VoxelContext, VoxelData, VoxelModel, VoxelQueryable,
+    cloud::create_cloud_image,
+    history::{VoxelEdit, VoxelEditHistory, VoxelSnapshot},
+    resize::VoxelResize,
+    resync::VoxelModelSync,
+};
 
 /// Data object passed into [`modify_voxel_model`] system
 pub struct VoxelModifier {
-    instance: VoxelModelInstance,
-    mesh: Handle<Mesh>,
-    region: VoxelRegionMode,
-    modify: Box<dyn Fn(IVec3, &Voxel, &dyn VoxelQueryable) -> Voxel + Send + Sync + 'static>,
+    pub(super) instance: VoxelModelInstance,
+    pub(super) meshes: ModifiedMeshes,
+    pub(super) region: VoxelRegionMode,
+    pub(super) resize: Option<VoxelResize>,
+    pub(super) modify: ModifyFn,
+}
+
+/// The closure run against each voxel, which is also passed the signed distance to the surface of the region
+pub(super) type ModifyFn =
+    Box<dyn Fn(IVec3, &Voxel, f32, &dyn VoxelQueryable) -> Voxel + Send + Sync + 'static>;
+
+/// The meshes to update once the voxels have been modified
+#[derive(Clone)]
+pub(super) enum ModifiedMeshes {
+    /// The mesh of the whole model
+    Model(Handle<Mesh>),
+    /// The mesh of each chunk of the model
+    Chunks(Vec<Handle<Mesh>>),
 }
 
 impl VoxelModifier {
     /// Run the `modify` closure against every voxel within the `region` of the `model`.
     ///
+    /// Every instance of the model is updated to match: the mesh is added to or removed from an instance if the model
+    /// gains or loses its visible voxels, the material follows the index of refraction of the transmissive voxels, and
+    /// the [`bevy::pbr::FogVolume`] of the cloud voxels is added, updated, or removed.
+    ///
     /// ### Arguments
-    /// * `model` - the id of the [`VoxelModel`] to be modified (you can obtain this by from the [`bevy::asset::Handle::id()`] method).
+    /// * `instance` - the instance of the [`VoxelModel`] to be modified.
+    /// * `mesh` - the handle of the model's mesh, taken from the instance's [`bevy::render::mesh::Mesh3d`]. If the model
+    ///   doesn't have a mesh yet, pass a handle from [`Assets::reserve_handle`] for the new mesh to be added to.
     /// * `region` - a [`VoxelRegion`] defining the area of the voxel model that the modifier will operate on.
     /// * `modify` - a closure that will run against every voxel within the `region`.
     ///
@@ -39,19 +68,101 @@ impl VoxelModifier {
         mesh: Handle<Mesh>,
         region: VoxelRegionMode,
         modify: F,
+    ) -> Self {
+        Self::new_with_distance(instance, mesh, region, move |position, voxel, _, model| {
+            modify(position, voxel, model)
+        })
+    }
+
+    /// Like [`VoxelModifier::new`], but the `modify` closure is also passed the signed distance from the voxel to the
+    /// surface of the `region`, which is negative inside the region. This can be used to make soft brushes, for
+    /// instance by only painting voxels near the center of a [`VoxelRegionMode::Sphere`].
+    ///
+    /// ### Arguments passed to the `modify` closure
+    /// * `position` - the position of the current voxel, in voxel space
+    /// * `voxel` - the index of the current voxel
+    /// * `distance` - the signed distance from the voxel to the surface of the `region`, in voxels
+    /// * `model` - a reference to the model
+    pub fn new_with_distance<
+        F: Fn(IVec3, &Voxel, f32, &dyn VoxelQueryable) -> Voxel + Send + Sync + 'static,
+    >(
+        instance: VoxelModelInstance,
+        mesh: Handle<Mesh>,
+        region: VoxelRegionMode,
+        modify: F,
+    ) -> Self {
+        VoxelModifier {
+            instance,
+            meshes: ModifiedMeshes::Model(mesh),
+            region,
+            resize: None,
+            modify: Box::new(modify),
+        }
+    }
+
+    /// Run the `modify` closure against every voxel within the `region` of a model whose mesh is split into chunks by
+    /// [`crate::VoxLoaderSettings::chunk_size`]. Only the chunks that overlap the `region`, and their neighbours that
+    /// border it, are remeshed.
+    ///
+    /// ### Arguments
+    /// * `instance` - the instance of the model to be modified.
+    /// * `chunks` - the [`VoxelChunks`] component of the instance.
+    /// * `region` - a [`VoxelRegion`] defining the area of the voxel model that the modifier will operate on.
+    /// * `modify` - a closure that will run against every voxel within the `region`. See [`VoxelModifier::new`].
+    pub fn for_chunks<
+        F: Fn(IVec3, &Voxel, &dyn VoxelQueryable) -> Voxel + Send + Sync + 'static,
+    >(
+        instance: VoxelModelInstance,
+        chunks: &VoxelChunks,
+        region: VoxelRegionMode,
+        modify: F,
+    ) -> Self {
+        Self::for_chunks_with_distance(
+            instance,
+            chunks,
+            region,
+            move |position, voxel, _, model| modify(position, voxel, model),
+        )
+    }
+
+    /// Like [`VoxelModifier::for_chunks`], but the `modify` closure is also passed the signed distance from the voxel
+    /// to the surface of the `region`. See [`VoxelModifier::new_with_distance`].
+    pub fn for_chunks_with_distance<
+        F: Fn(IVec3, &Voxel, f32, &dyn VoxelQueryable) -> Voxel + Send + Sync + 'static,
+    >(
+        instance: VoxelModelInstance,
+        chunks: &VoxelChunks,
+        region: VoxelRegionMode,
+        modify: F,
     ) -> Self {
         VoxelModifier {
             instance,
-            mesh,
+            meshes: ModifiedMeshes::Chunks(chunks.meshes.clone()),
             region,
+            resize: None,
             modify: Box::new(modify),
         }
     }
+
+    /// Resize the bounds of the model before modifying it, so that voxels can be added outside of its current bounds.
+    ///
+    /// The `region` is given in the voxel space of the model before it's resized. If the model grows below its
+    /// minimum corner, the coordinates of its existing voxels increase by the amount it grew, so the `region` is moved
+    /// by the same amount, and the positions passed to the `modify` closure are in the voxel space of the resized
+    /// model. With [`VoxelResize::ToFit`], that amount is how far the `region` extends below zero on each axis.
+    ///
+    /// Resizing a model whose mesh is split into chunks remeshes all of its chunks, and adds or removes chunks as the
+    /// number of chunks changes.
+    pub fn with_resize(mut self, resize: VoxelResize) -> Self {
+        self.resize = Some(resize);
+        self
+    }
 }
 
 /// System that programmatically modifies the voxels in a model.
 ///
-/// Takes a [`VoxelModifier`] as its input
+/// Takes a [`VoxelModifier`] as its input. If the [`VoxelEditHistory`] resource exists, the modification is recorded
+/// in it so that it can be undone.
 ///
 /// ### Example
 /// ```no_run
@@ -61,101 +172,321 @@ impl VoxelModifier {
 /// # let model_instance: VoxelModelInstance = panic!();
 /// # let mesh_handle: Handle<Mesh> = panic!();
 /// // cut a sphere-shaped hole out of the loaded model
-/// let sphere_center = IVec3::new(10, 10, 10);
-/// let radius = 10;
-/// let radius_squared = radius * radius;
-/// let region = VoxelRegion {
-///     origin: sphere_center - IVec3::splat(radius),
-///     size: IVec3::splat(1 + (radius * 2)),
-/// };
 /// let modifier = VoxelModifier::new(
 ///     model_instance.clone(),
 /// 	mesh_handle.clone(),
-///     VoxelRegionMode::Box(region),
-///     move | position, voxel, model | {
-///         // a signed-distance function for a sphere:
-///         if position.distance_squared(sphere_center) <= radius_squared {
-///             // inside of the sphere, return an empty cell
-///             Voxel::EMPTY
-///         } else {
-///             // outside the sphere, return the underlying voxel value from the model
-///             voxel.clone()
-///         }
+///     VoxelRegionMode::Sphere {
+///         center: Vec3::new(10.0, 10.0, 10.0),
+///         radius: 10.0,
 ///     },
+///     // only the voxels inside of the sphere are visited, so they can all be emptied
+///     |_position, _voxel, _model| Voxel::EMPTY,
 /// );
 /// commands.run_system_cached_with(modify_voxel_model, Some(modifier));
 /// ```
 pub fn modify_voxel_model(
     In(maybe_modifier): In<Option<VoxelModifier>>,
-    mut meshes: ResMut<Assets<Mesh>>,
     mut models: ResMut<Assets<VoxelModel>>,
     contexts: Res<Assets<VoxelContext>>,
+    mut sync: VoxelModelSync,
+    history: Option<ResMut<VoxelEditHistory>>,
 ) {
     let Some(modifier) = maybe_modifier else {
         return;
     };
-    let Some(context) = contexts.get(modifier.instance.context.id()) else {
-        return;
-    };
-    let Some(model) = models.get_mut(modifier.instance.model.id()) else {
-        return;
-    };
-    let refraction_indices = &context.palette.indices_of_refraction;
-    let density_for_voxel = &context.palette.density_for_voxel;
-    let leading_padding = IVec3::splat(model.data.padding() as i32 / 2);
-    let model_size = model.size();
-    let region = modifier.region.clamped(model_size);
-    let start = leading_padding + region.origin;
-    let end = start + region.size;
-    let mut updated: Vec<RawVoxel> = model.data.voxels.clone();
-    for x in start.x..end.x {
-        for y in start.y..end.y {
-            for z in start.z..end.z {
-                let index = model.data.shape.linearize([x as u32, y as u32, z as u32]) as usize;
-                let source: Voxel = model.data.voxels[index].clone().into();
-                updated[index] = RawVoxel::from((modifier.modify)(
-                    IVec3::new(x, y, z) - leading_padding,
-                    &source,
-                    model,
-                ));
+    let edit = modifier.run(&mut models, &contexts, &mut sync, history.is_some());
+    if let (Some(mut history), Some(edit)) = (history, edit) {
+        history.record(edit);
+    }
+}
+
+impl VoxelModifier {
+    /// Modifies the model and resyncs its instances, returning the edit that undoes the modification if `record` is
+    /// set
+    pub(super) fn run(
+        &self,
+        models: &mut Assets<VoxelModel>,
+        contexts: &Assets<VoxelContext>,
+        sync: &mut VoxelModelSync,
+        record: bool,
+    ) -> Option<VoxelEdit> {
+        let context = contexts.get(self.instance.context.id())?;
+        let model = models.get_mut(self.instance.model.id())?;
+        let (remeshed, edit) = self.apply(
+            &mut model.data,
+            &context.palette.indices_of_refraction,
+            &context.palette.density_for_voxel,
+            record,
+        );
+        sync.resync(self.instance.model.id(), model, context, remeshed);
+        edit
+    }
+
+    /// Runs the `modify` closure against the voxels in the region, and returns the remeshed meshes with the handles
+    /// that they replace, and the edit that undoes the modification if `record` is set
+    pub(super) fn apply(
+        &self,
+        data: &mut VoxelData,
+        refraction_indices: &[Option<f32>],
+        density_for_voxel: &[Option<f32>],
+        record: bool,
+    ) -> (Remeshed, Option<VoxelEdit>) {
+        let old_size = data.size();
+        let (size, shift) = match &self.resize {
+            Some(resize) => resize.size_and_shift(data, self.region.bounds().as_ref()),
+            None => (old_size, IVec3::ZERO),
+        };
+        let resized = size != old_size || shift != IVec3::ZERO;
+        // shrinking the model drops voxels outside of the region, so the whole model is recorded to undo a resize
+        let resized_from = (record && resized).then(|| VoxelSnapshot::of_model(data));
+        data.resize_with_shift(size, shift);
+        let leading_padding = IVec3::splat(data.padding() as i32 / 2);
+        let region = self.region.clamped(data.size(), shift);
+        let start = leading_padding + region.origin;
+        let end = start + region.size;
+        let mut previous = (record && !resized).then(|| VoxelSnapshot::new(&region));
+        // the closure reads the unmodified model, so the changes are collected before they're written
+        let mut updated: Vec<(usize, RawVoxel)> =
+            Vec::with_capacity(region.size.element_product() as usize);
+        for x in start.x..end.x {
+            for y in start.y..end.y {
+                for z in start.z..end.z {
+                    let index = data.shape.linearize([x as u32, y as u32, z as u32]) as usize;
+                    if let Some(previous) = &mut previous {
+                        previous.push(data.voxels.at(index));
+                    }
+                    let position = IVec3::new(x, y, z) - leading_padding;
+                    // shaped regions are only visited inside of the shape
+                    let distance = self.region.distance(position, &region, shift);
+                    if distance > 0.0 {
+                        continue;
+                    }
+                    let source: Voxel = data.voxels.at(index).into();
+                    updated.push((
+                        index,
+                        RawVoxel::from((self.modify)(position, &source, distance, data)),
+                    ));
+                }
             }
         }
+        for (index, voxel) in updated {
+            data.voxels.set(index, voxel);
+        }
+        data.mark_changed();
+        let edit = match resized_from {
+            Some(snapshot) => Some(VoxelEdit::new(
+                self,
+                snapshot,
+                Some((old_size, size)),
+                shift,
+            )),
+            None => previous.map(|snapshot| VoxelEdit::new(self, snapshot, None, IVec3::ZERO)),
+        };
+        let transmissive_meshes = data.transmissive_meshes(refraction_indices, density_for_voxel);
+        let remeshed = match &self.meshes {
+            ModifiedMeshes::Model(mesh_handle) => {
+                let (maybe_mesh, average_ior, cloud) =
+                    data.remesh(refraction_indices, density_for_voxel);
+                Remeshed {
+                    has_mesh: maybe_mesh.is_some(),
+                    added_chunks: Vec::new(),
+                    removed_chunks: Vec::new(),
+                    model_mesh: Some(mesh_handle.clone()),
+                    meshes: maybe_mesh
+                        .map(|mesh| (mesh_handle.clone(), mesh))
+                        .into_iter()
+                        .collect(),
+                    transmissive_meshes,
+                    lod_meshes: data.lod_meshes(refraction_indices, density_for_voxel),
+                    average_ior,
+                    cloud,
+                }
+            }
+            ModifiedMeshes::Chunks(chunk_handles) => {
+                // a resized model has a different grid of chunks, so all of them are remeshed
+                let chunks = if resized {
+                    (0..data.chunk_count()).collect()
+                } else {
+                    data.chunks_in_region(start, end)
+                };
+                let mut meshes = Vec::with_capacity(chunks.len());
+                let mut added_chunks = Vec::new();
+                for index in chunks {
+                    let mesh = data.chunk_mesh(index, refraction_indices, density_for_voxel);
+                    match chunk_handles.get(index) {
+                        Some(mesh_handle) => meshes.push((mesh_handle.clone(), mesh)),
+                        None => added_chunks.push(mesh),
+                    }
+                }
+                let removed_chunks = chunk_handles
+                    .get(data.chunk_count()..)
+                    .map(<[Handle<Mesh>]>::to_vec)
+                    .unwrap_or_default();
+                // the whole model is only scanned for clouds if the palette contains any
+                let cloud = if density_for_voxel.iter().any(Option::is_some) {
+                    let (cloud_voxels, has_cloud) = data.cloud_voxels(density_for_voxel);
+                    has_cloud.then(|| create_cloud_image(&cloud_voxels, data))
+                } else {
+                    None
+                };
+                let (average_ior, has_mesh) = data.surface(refraction_indices, density_for_voxel);
+                Remeshed {
+                    meshes,
+                    added_chunks,
+                    removed_chunks,
+                    model_mesh: None,
+                    has_mesh,
+                    transmissive_meshes,
+                    lod_meshes: Vec::new(),
+                    average_ior,
+                    cloud,
+                }
+            }
+        };
+        (remeshed, edit)
     }
-    model.data.voxels = updated;
-    let (maybe_mesh, _average_ior, _maybe_cloud) =
-        model.data.remesh(refraction_indices, &density_for_voxel);
+}
 
-    if let Some(mesh) = maybe_mesh {
-        meshes.insert(&modifier.mesh, mesh);
-    }
+/// The meshes, index of refraction, and cloud of a model after it has been modified
+pub(super) struct Remeshed {
+    /// The meshes that have been regenerated, with the handles that they replace
+    pub(super) meshes: Vec<(Handle<Mesh>, Mesh)>,
+    /// The meshes of the chunks added to the end of the model's [`VoxelChunks`] when it was resized
+    pub(super) added_chunks: Vec<Mesh>,
+    /// The meshes of the chunks that no longer exist after the model was resized
+    pub(super) removed_chunks: Vec<Handle<Mesh>>,
+    /// The handle of the mesh of the whole model, if it isn't split into chunks
+    pub(super) model_mesh: Option<Handle<Mesh>>,
+    /// Whether the model contains solid or transmissive voxels
+    pub(super) has_mesh: bool,
+    /// The meshes of the transmissive voxels with each index of refraction, if they're split by
+    /// [`crate::VoxLoaderSettings::ior_bucket_size`]
+    pub(super) transmissive_meshes: Vec<(f32, Mesh)>,
+    /// The mesh of each level of detail in [`crate::VoxLoaderSettings::lod_distances`], if the model isn't split into
+    /// chunks
+    pub(super) lod_meshes: Vec<Mesh>,
+    /// The average index of refraction of the model's transmissive voxels
+    pub(super) average_ior: Option<f32>,
+    /// The density image of the model's cloud voxels
+    pub(super) cloud: Option<Image>,
 }
 
-/// The region of the model to modify
+/// The region of the model to modify.
+///
+/// Positions and distances are in voxel space, measured from the coordinate of each voxel. Only the voxels inside of
+/// the region, where its signed distance is zero or less, are visited.
 pub enum VoxelRegionMode {
     /// The entire area of the model
     All,
     /// A box region within the model, expressed in voxel space
     Box(VoxelRegion),
+    /// A sphere
+    Sphere {
+        /// The center of the sphere
+        center: Vec3,
+        /// The radius of the sphere
+        radius: f32,
+    },
+    /// A line segment with rounded ends
+    Capsule {
+        /// The center of one end of the capsule
+        start: Vec3,
+        /// The center of the other end of the capsule
+        end: Vec3,
+        /// The radius of the capsule
+        radius: f32,
+    },
+    /// A cylinder with flat ends
+    Cylinder {
+        /// The center of one end of the cylinder
+        start: Vec3,
+        /// The center of the other end of the cylinder
+        end: Vec3,
+        /// The radius of the cylinder
+        radius: f32,
+    },
+    /// Any signed distance field, such as one built by combining the primitives of [`SDF`]
+    #[cfg(feature = "generate_voxels")]
+    Sdf {
+        /// The signed distance field, sampled in voxel space
+        sdf: SDF,
+        /// A box containing the whole of the shape, as the bounds of an arbitrary field can't be derived
+        bounds: VoxelRegion,
+    },
 }
 
 impl VoxelRegionMode {
-    fn clamped(&self, model_size: IVec3) -> VoxelRegion {
-        match self {
-            VoxelRegionMode::All => VoxelRegion {
+    /// The bounds of the region moved by `offset`, and clamped to the bounds of the model
+    fn clamped(&self, model_size: IVec3, offset: IVec3) -> VoxelRegion {
+        match self.bounds() {
+            None => VoxelRegion {
                 origin: IVec3::ZERO,
                 size: model_size,
             },
-            VoxelRegionMode::Box(region) => {
-                let origin = region.origin.clamp(IVec3::ZERO, model_size - IVec3::ONE);
+            Some(region) => {
+                let origin = (region.origin + offset).clamp(IVec3::ZERO, model_size - IVec3::ONE);
                 let max_size = model_size - origin;
                 let size = region.size.clamp(IVec3::ONE, max_size);
                 VoxelRegion { origin, size }
             }
         }
     }
+
+    /// The box containing the region, or `None` if the region is the whole model
+    pub fn bounds(&self) -> Option<VoxelRegion> {
+        match self {
+            VoxelRegionMode::All => None,
+            VoxelRegionMode::Box(region) => Some(region.clone()),
+            VoxelRegionMode::Sphere { center, radius } => {
+                Some(VoxelRegion::containing(center - radius, center + radius))
+            }
+            VoxelRegionMode::Capsule { start, end, radius }
+            | VoxelRegionMode::Cylinder { start, end, radius } => Some(VoxelRegion::containing(
+                start.min(*end) - radius,
+                start.max(*end) + radius,
+            )),
+            #[cfg(feature = "generate_voxels")]
+            VoxelRegionMode::Sdf { bounds, .. } => Some(bounds.clone()),
+        }
+    }
+
+    /// The signed distance from the voxel at `position` to the surface of the region. `bounds` is the clamped box of
+    /// the region, and `shift` is how far the model's voxels moved if the model was resized.
+    fn distance(&self, position: IVec3, bounds: &VoxelRegion, shift: IVec3) -> f32 {
+        let point = (position - shift).as_vec3();
+        match self {
+            VoxelRegionMode::All | VoxelRegionMode::Box(_) => {
+                // the box spans the whole of each voxel on its edges
+                let half_extent = bounds.size.as_vec3() * 0.5;
+                let center = bounds.origin.as_vec3() + half_extent - 0.5;
+                let q = (position.as_vec3() - center).abs() - half_extent;
+                q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+            }
+            VoxelRegionMode::Sphere { center, radius } => point.distance(*center) - radius,
+            VoxelRegionMode::Capsule { start, end, radius } => {
+                let axis = end - start;
+                let t = ((point - start).dot(axis) / axis.length_squared()).clamp(0.0, 1.0);
+                // a capsule with no length is a sphere
+                let t = if t.is_nan() { 0.0 } else { t };
+                point.distance(start + axis * t) - radius
+            }
+            VoxelRegionMode::Cylinder { start, end, radius } => {
+                let axis = end - start;
+                let height = axis.length();
+                let direction = axis.normalize_or_zero();
+                let along = (point - start).dot(direction);
+                let across = (point - start - direction * along).length();
+                let q = Vec2::new(across - radius, (along - height * 0.5).abs() - height * 0.5);
+                q.max(Vec2::ZERO).length() + q.max_element().min(0.0)
+            }
+            #[cfg(feature = "generate_voxels")]
+            VoxelRegionMode::Sdf { sdf, .. } => sdf.distance(point),
+        }
+    }
 }
 
 /// A box region within a model
+#[derive(Clone, Debug, PartialEq)]
 pub struct VoxelRegion {
     /// The lower-back-left corner of the region
     pub origin: IVec3,
@@ -171,6 +502,15 @@ impl VoxelRegion {
             size: half_size * 2,
         }
     }
+    /// The smallest region containing the voxels between `min` and `max`
+    pub fn containing(min: Vec3, max: Vec3) -> Self {
+        let origin = min.floor().as_ivec3();
+        Self {
+            origin,
+            size: max.ceil().as_ivec3() - origin + IVec3::ONE,
+        }
+    }
+
     /// Computes the center of the region
     pub fn center(&self) -> Vec3 {
         let origin = Vec3::new(
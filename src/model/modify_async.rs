@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+
+use bevy::{
+    asset::{AssetId, Assets, Handle},
+    ecs::{
+        event::{Event, EventWriter},
+        resource::Resource,
+        system::{In, Res, ResMut},
+    },
+    platform::collections::HashMap,
+    tasks::{AsyncComputeTaskPool, Task, block_on, poll_once},
+};
+
+use super::{
+    VoxelContext, VoxelData, VoxelModel,
+    history::{VoxelEdit, VoxelEditHistory},
+    modify::{Remeshed, VoxelModifier},
+    resync::VoxelModelSync,
+};
+
+/// Event sent by [`modify_voxel_model_async`] once a modification has been applied to a model, and its new meshes
+/// are in place
+#[derive(Event, Debug, Clone)]
+pub struct VoxelModelRemeshed {
+    /// The model that was modified
+    pub model: AssetId<VoxelModel>,
+}
+
+/// The modifications waiting to be applied to each model, and the task running the current one
+#[derive(Resource, Default)]
+pub struct VoxelModifierTasks(HashMap<AssetId<VoxelModel>, ModelTasks>);
+
+type ModifierTask = Task<(VoxelModifier, VoxelData, Remeshed, Option<VoxelEdit>)>;
+
+/// How many times a modification is run on the task pool before it is applied on the main thread instead, if the
+/// model keeps changing while its task runs
+const MAX_ASYNC_ATTEMPTS: u32 = 3;
+
+#[derive(Default)]
+struct ModelTasks {
+    running: Option<RunningTask>,
+    queued: VecDeque<VoxelModifier>,
+    /// How many times the modification at the front of the queue has already been run and discarded
+    attempts: u32,
+}
+
+/// The task applying the current modification to a model
+struct RunningTask {
+    task: ModifierTask,
+    /// The context of the model's instance
+    context: Handle<VoxelContext>,
+    /// The [`VoxelData::revision`] of the voxels that the task started from
+    revision: u64,
+    /// How many times the modification had been run before this task
+    attempts: u32,
+}
+
+/// System that modifies the voxels in a model and remeshes it on the [`AsyncComputeTaskPool`], so that large
+/// modifications don't stall the frame.
+///
+/// Takes a [`VoxelModifier`] as its input, like [`super::modify::modify_voxel_model`]. The model keeps its current
+/// mesh until the task finishes, when the new mesh is swapped in and a [`VoxelModelRemeshed`] event is sent.
+/// Modifications to the same model are applied one at a time, in the order that they were made. If the
+/// [`VoxelEditHistory`] resource exists, each modification is recorded in it when its task finishes.
+///
+/// ### Notes
+/// The task modifies a copy of the model's voxels, which is made when it starts. If the model is changed while the
+/// task is running, for instance by [`super::modify::modify_voxel_model`] or [`super::history::undo_voxel_edit`], the
+/// result is discarded and the modification is run again on the changed voxels, so that the other changes aren't
+/// overwritten. If the model is changed while the task runs a third time, the modification is applied to the
+/// model's current voxels on the main thread, so that a model that changes every frame doesn't hold it back forever.
+///
+/// ### Example
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_vox_scene::{VoxelModelInstance, VoxelRegionMode, Voxel, VoxelModifier, modify_voxel_model_async};
+/// # let mut commands: Commands = panic!();
+/// # let model_instance: VoxelModelInstance = panic!();
+/// # let mesh_handle: Handle<Mesh> = panic!();
+/// // clear the whole model without blocking the frame
+/// let modifier = VoxelModifier::new(
+///     model_instance.clone(),
+///     mesh_handle.clone(),
+///     VoxelRegionMode::All,
+///     |_position, _voxel, _model| Voxel::EMPTY,
+/// );
+/// commands.run_system_cached_with(modify_voxel_model_async, Some(modifier));
+/// ```
+pub fn modify_voxel_model_async(
+    In(maybe_modifier): In<Option<VoxelModifier>>,
+    mut tasks: ResMut<VoxelModifierTasks>,
+) {
+    let Some(modifier) = maybe_modifier else {
+        return;
+    };
+    tasks
+        .0
+        .entry(modifier.instance.model.id())
+        .or_default()
+        .queued
+        .push_back(modifier);
+}
+
+/// Swaps in the meshes of the finished tasks, and starts the next modification queued for each model
+pub(crate) fn update_voxel_modifier_tasks(
+    mut tasks: ResMut<VoxelModifierTasks>,
+    mut models: ResMut<Assets<VoxelModel>>,
+    contexts: Res<Assets<VoxelContext>>,
+    mut sync: VoxelModelSync,
+    mut remeshed: EventWriter<VoxelModelRemeshed>,
+    mut history: Option<ResMut<VoxelEditHistory>>,
+) {
+    let record = history.is_some();
+    let task_pool = AsyncComputeTaskPool::get();
+    tasks.0.retain(|model_id, model_tasks| {
+        if let Some(running) = &mut model_tasks.running {
+            let Some((modifier, data, new_meshes, edit)) = block_on(poll_once(&mut running.task))
+            else {
+                return true;
+            };
+            let is_stale = models
+                .get(*model_id)
+                .is_some_and(|model| model.data.revision != running.revision);
+            let attempts = running.attempts + 1;
+            if is_stale && attempts < MAX_ASYNC_ATTEMPTS {
+                model_tasks.queued.push_front(modifier);
+                model_tasks.attempts = attempts;
+            } else if is_stale {
+                let edit = modifier.run(&mut models, &contexts, &mut sync, record);
+                if let (Some(history), Some(edit)) = (&mut history, edit) {
+                    history.record(edit);
+                }
+                remeshed.write(VoxelModelRemeshed { model: *model_id });
+            } else if let (Some(model), Some(context)) = (
+                models.get_mut(*model_id),
+                contexts.get(running.context.id()),
+            ) {
+                model.data = data;
+                sync.resync(*model_id, model, context, new_meshes);
+                if let (Some(history), Some(edit)) = (&mut history, edit) {
+                    history.record(edit);
+                }
+                remeshed.write(VoxelModelRemeshed { model: *model_id });
+            }
+            model_tasks.running = None;
+        }
+        while let Some(modifier) = model_tasks.queued.pop_front() {
+            let attempts = std::mem::take(&mut model_tasks.attempts);
+            let (Some(model), Some(context)) = (
+                models.get(*model_id),
+                contexts.get(modifier.instance.context.id()),
+            ) else {
+                continue;
+            };
+            let mut data = model.data.clone();
+            let revision = data.revision;
+            let refraction_indices = context.palette.indices_of_refraction.clone();
+            let density_for_voxel = context.palette.density_for_voxel.clone();
+            let context_handle = modifier.instance.context.clone();
+            let task = task_pool.spawn(async move {
+                let (new_meshes, edit) =
+                    modifier.apply(&mut data, &refraction_indices, &density_for_voxel, record);
+                (modifier, data, new_meshes, edit)
+            });
+            model_tasks.running = Some(RunningTask {
+                task,
+                context: context_handle,
+                revision,
+                attempts,
+            });
+            return true;
+        }
+        false
+    });
+}
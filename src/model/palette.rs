@@ -11,6 +11,8 @@ use bevy::{
 };
 use dot_vox::DotVoxData;
 
+use super::{RawVoxel, Voxel};
+
 /// Container for all of the [`VoxelElement`]s that can be used in a [`super::VoxelModel`]
 #[derive(Clone, Debug)]
 pub struct VoxelPalette {
@@ -25,6 +27,20 @@ pub struct VoxelPalette {
     pub(crate) uses_srgb: bool,
 }
 
+/// The largest number of elements a palette can have, as [`crate::Voxel`] indices are 16 bit, with one value
+/// reserved for empty voxels
+pub(crate) const MAX_PALETTE_SIZE: usize = u16::MAX as usize;
+
+/// The width of the square texture that holds a palette with `len` elements. Palettes of up to 256 elements use a
+/// 16x16 texture, larger palettes use the smallest power of two that fits all of their elements.
+pub(crate) fn palette_atlas_width(len: usize) -> u32 {
+    let mut width = 16;
+    while width * width < len {
+        width *= 2;
+    }
+    width as u32
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub(crate) enum MaterialProperty {
     VariesPerElement,
@@ -74,6 +90,21 @@ impl Default for VoxelElement {
     }
 }
 
+impl VoxelElement {
+    /// Interpolates each of the properties of the element towards those of `rhs`, with `amount` from 0.0 to 1.0
+    pub fn lerp(&self, rhs: &VoxelElement, amount: f32) -> VoxelElement {
+        VoxelElement {
+            color: Color::LinearRgba(self.color.to_linear().lerp(rhs.color.to_linear(), amount)),
+            emission: self.emission.lerp(rhs.emission, amount),
+            roughness: self.roughness.lerp(rhs.roughness, amount),
+            metalness: self.metalness.lerp(rhs.metalness, amount),
+            translucency: self.translucency.lerp(rhs.translucency, amount),
+            refraction_index: self.refraction_index.lerp(rhs.refraction_index, amount),
+            density: self.density.lerp(rhs.density, amount),
+        }
+    }
+}
+
 impl VoxelPalette {
     /// Create a new [`VoxelPalette`] from the supplied [`VoxelElement`]s
     pub fn new(mut elements: Vec<VoxelElement>, uses_srgb: bool) -> Self {
@@ -82,8 +113,95 @@ impl VoxelPalette {
         let metalness_data: Vec<f32> = elements.iter().map(|e| e.metalness).collect();
         let translucency_data: Vec<f32> = elements.iter().map(|e| e.translucency).collect();
 
-        elements.resize_with(256, VoxelElement::default);
-        let indices_of_refraction: Vec<Option<f32>> = elements
+        elements.truncate(MAX_PALETTE_SIZE);
+        let atlas_width = palette_atlas_width(elements.len());
+        elements.resize_with((atlas_width * atlas_width) as usize, VoxelElement::default);
+        let mut palette = VoxelPalette {
+            elements,
+            emission: MaterialProperty::from_slice(&emission_data),
+            metalness: MaterialProperty::from_slice(&metalness_data),
+            roughness: MaterialProperty::from_slice(&roughness_data),
+            transmission: MaterialProperty::from_slice(&translucency_data),
+            indices_of_refraction: Vec::new(),
+            density_for_voxel: Vec::new(),
+            uses_srgb,
+        };
+        palette.update_lookups();
+        palette
+    }
+
+    /// The [`VoxelElement`] used by voxels with the value `voxel`, or `None` for [`Voxel::EMPTY`] and values outside
+    /// of the palette
+    pub fn element(&self, voxel: &Voxel) -> Option<&VoxelElement> {
+        if *voxel == Voxel::EMPTY {
+            return None;
+        }
+        self.elements.get(RawVoxel::from(voxel.clone()).0 as usize)
+    }
+
+    /// Replaces the [`VoxelElement`] used by voxels with the value `voxel`.
+    ///
+    /// This only changes the palette itself. To recolor the models using a [`super::VoxelContext`], pass the edits
+    /// to [`super::edit_voxel_palette`] instead.
+    ///
+    /// ### Returns
+    /// `false` if the palette was left unchanged because `voxel` is [`Voxel::EMPTY`] or outside of the palette
+    pub fn set_element(&mut self, voxel: &Voxel, element: VoxelElement) -> bool {
+        let replaced = self.replace_element(voxel, element);
+        if replaced {
+            self.update_properties();
+        }
+        replaced
+    }
+
+    /// Replaces several elements as [`VoxelPalette::set_element`] does, updating the material properties of the palette
+    /// once at the end
+    pub(crate) fn set_elements(
+        &mut self,
+        elements: impl IntoIterator<Item = (Voxel, VoxelElement)>,
+    ) {
+        for (voxel, element) in elements {
+            self.replace_element(&voxel, element);
+        }
+        self.update_properties();
+    }
+
+    fn replace_element(&mut self, voxel: &Voxel, element: VoxelElement) -> bool {
+        if *voxel == Voxel::EMPTY {
+            return false;
+        }
+        let Some(existing) = self
+            .elements
+            .get_mut(RawVoxel::from(voxel.clone()).0 as usize)
+        else {
+            return false;
+        };
+        *existing = element;
+        true
+    }
+
+    fn update_properties(&mut self) {
+        let property = |value: fn(&VoxelElement) -> f32| {
+            MaterialProperty::from_slice(&self.elements.iter().map(value).collect::<Vec<f32>>())
+        };
+        self.emission = property(|e| e.emission);
+        self.metalness = property(|e| e.metalness);
+        self.roughness = property(|e| e.roughness);
+        self.transmission = property(|e| e.translucency);
+        self.update_lookups();
+    }
+
+    /// Pads the palette with default elements up to `len`, so that its texture matches the size of a larger palette
+    pub(crate) fn pad(&mut self, len: usize) {
+        if self.elements.len() < len {
+            self.elements.resize_with(len, VoxelElement::default);
+            self.update_lookups();
+        }
+    }
+
+    fn update_lookups(&mut self) {
+        self.indices_of_refraction = self
+            .elements
             .iter()
             .map(|e| {
                 if e.translucency > 0.0 {
@@ -93,7 +211,8 @@ impl VoxelPalette {
                 }
             })
             .collect();
-        let density_for_voxel = elements
+        self.density_for_voxel = self
+            .elements
             .iter()
             .map(|e| {
                 if e.density > 0.0 {
@@ -103,16 +222,6 @@ impl VoxelPalette {
                 }
             })
             .collect();
-        VoxelPalette {
-            elements,
-            emission: MaterialProperty::from_slice(&emission_data),
-            metalness: MaterialProperty::from_slice(&metalness_data),
-            roughness: MaterialProperty::from_slice(&roughness_data),
-            transmission: MaterialProperty::from_slice(&translucency_data),
-            indices_of_refraction,
-            density_for_voxel,
-            uses_srgb,
-        }
     }
 
     /// Create a new [`VoxelPalette`] from the supplied [`Color`]s
@@ -138,24 +247,7 @@ impl VoxelPalette {
             let distance = (next_stop - stop) as f32;
             for i in *stop..*next_stop {
                 let fraction = (i - stop) as f32 / distance;
-                elements[i as usize] = VoxelElement {
-                    color: Color::LinearRgba(
-                        element
-                            .color
-                            .to_linear()
-                            .lerp(next_element.color.to_linear(), fraction),
-                    ),
-                    emission: element.emission.lerp(next_element.emission, fraction),
-                    roughness: element.roughness.lerp(next_element.roughness, fraction),
-                    metalness: element.metalness.lerp(next_element.metalness, fraction),
-                    translucency: element
-                        .translucency
-                        .lerp(next_element.translucency, fraction),
-                    refraction_index: element
-                        .refraction_index
-                        .lerp(next_element.refraction_index, fraction),
-                    density: element.density.lerp(next_element.density, fraction),
-                };
+                elements[i as usize] = element.lerp(next_element, fraction);
             }
         }
         VoxelPalette::new(elements, uses_srgb)
@@ -219,31 +311,47 @@ impl VoxelPalette {
         self._create_material(|_, image| images.add(image))
     }
 
+    /// Creates the material for the palette, writing its textures over the images of `material`, so that anything
+    /// using those image handles is updated. Textures that `material` doesn't have yet are added to `images`.
+    pub(crate) fn update_material(
+        &self,
+        material: &StandardMaterial,
+        images: &mut Assets<Image>,
+    ) -> StandardMaterial {
+        self._create_material(|name, image| {
+            let existing = match name {
+                "material_color" => material.base_color_texture.as_ref(),
+                "material_emission" => material.emissive_texture.as_ref(),
+                "material_metallic_roughness" => material.metallic_roughness_texture.as_ref(),
+                #[cfg(feature = "pbr_transmission_textures")]
+                "material_specular_transmission" => material.specular_transmission_texture.as_ref(),
+                _ => None,
+            };
+            match existing {
+                Some(handle) => {
+                    images.insert(handle, image);
+                    handle.clone()
+                }
+                None => images.add(image),
+            }
+        })
+    }
+
     fn _create_material(
         &self,
         mut get_handle: impl FnMut(&str, Image) -> Handle<Image>,
     ) -> StandardMaterial {
+        let atlas_width = palette_atlas_width(self.elements.len());
         let image_size = Extent3d {
-            width: 16,
-            height: 16,
+            width: atlas_width,
+            height: atlas_width,
             depth_or_array_layers: 1,
         };
         let color_data: Vec<u8> = self
             .elements
             .iter()
-            .flat_map(|e| {
-                if self.uses_srgb {
-                    e.color.to_srgba().to_u8_array()
-                } else {
-                    e.color.to_linear().to_u8_array()
-                }
-            })
+            .flat_map(|e| self.color_texel(e))
             .collect();
-        let emission_data: Vec<f32> = self.elements.iter().map(|e| e.emission).collect();
-        let roughness_data: Vec<f32> = self.elements.iter().map(|e| e.roughness).collect();
-        let metalness_data: Vec<f32> = self.elements.iter().map(|e| e.metalness).collect();
-        #[cfg(feature = "pbr_transmission_textures")]
-        let translucency_data: Vec<f32> = self.elements.iter().map(|e| e.translucency).collect();
 
         let has_emission = match self.emission {
             MaterialProperty::VariesPerElement => true,
@@ -254,6 +362,7 @@ impl VoxelPalette {
         let has_roughness_metalness = has_roughness || has_metalness;
         let has_translucency = self.transmission == MaterialProperty::VariesPerElement;
 
+        // the textures are kept in the main world as well, so that `VoxelPaletteAnimation` can rewrite single elements
         let base_color_texture = Some(get_handle(
             "material_color",
             Image::new(
@@ -265,22 +374,12 @@ impl VoxelPalette {
                 } else {
                     TextureFormat::Rgba8Unorm
                 },
-                RenderAssetUsages::RENDER_WORLD,
+                RenderAssetUsages::default(),
             ),
         ));
 
         let emissive_texture = if has_emission {
-            let emission_bytes: Vec<u8> = emission_data
-                .iter()
-                .zip(self.elements.iter().map(|e| e.color))
-                .flat_map(|(emission, color)| {
-                    (color.to_linear() * *emission)
-                        .to_f32_array()
-                        .iter()
-                        .flat_map(|c| c.to_le_bytes())
-                        .collect::<Vec<u8>>()
-                })
-                .collect();
+            let emission_bytes: Vec<u8> = self.elements.iter().flat_map(emission_texel).collect();
             Some(get_handle(
                 "material_emission",
                 Image::new(
@@ -288,7 +387,7 @@ impl VoxelPalette {
                     TextureDimension::D2,
                     emission_bytes,
                     TextureFormat::Rgba32Float,
-                    RenderAssetUsages::RENDER_WORLD,
+                    RenderAssetUsages::default(),
                 ),
             ))
         } else {
@@ -296,16 +395,10 @@ impl VoxelPalette {
         };
 
         let metallic_roughness_texture: Option<Handle<Image>> = if has_roughness_metalness {
-            let raw: Vec<u8> = roughness_data
+            let raw: Vec<u8> = self
+                .elements
                 .iter()
-                .zip(metalness_data.iter())
-                .flat_map(|(rough, metal)| {
-                    let output: Vec<u8> = [0.0, *rough, *metal, 0.0]
-                        .iter()
-                        .flat_map(|b| ((b * u16::MAX as f32) as u16).to_le_bytes())
-                        .collect();
-                    output
-                })
+                .flat_map(metallic_roughness_texel)
                 .collect();
             let handle = get_handle(
                 "material_metallic_roughness",
@@ -314,7 +407,7 @@ impl VoxelPalette {
                     TextureDimension::D2,
                     raw,
                     TextureFormat::Rgba16Unorm,
-                    RenderAssetUsages::RENDER_WORLD,
+                    RenderAssetUsages::default(),
                 ),
             );
             Some(handle)
@@ -324,9 +417,10 @@ impl VoxelPalette {
 
         #[cfg(feature = "pbr_transmission_textures")]
         let specular_transmission_texture: Option<Handle<Image>> = if has_translucency {
-            let raw: Vec<u8> = translucency_data
+            let raw: Vec<u8> = self
+                .elements
                 .iter()
-                .flat_map(|t| ((t * u16::MAX as f32) as u16).to_le_bytes())
+                .flat_map(specular_transmission_texel)
                 .collect();
             let handle = get_handle(
                 "material_specular_transmission",
@@ -335,7 +429,7 @@ impl VoxelPalette {
                     TextureDimension::D2,
                     raw,
                     TextureFormat::R16Unorm,
-                    RenderAssetUsages::RENDER_WORLD,
+                    RenderAssetUsages::default(),
                 ),
             );
             Some(handle)
@@ -369,6 +463,98 @@ impl VoxelPalette {
             ..Default::default()
         }
     }
+
+    /// The texel of the element in the base color texture
+    fn color_texel(&self, element: &VoxelElement) -> [u8; 4] {
+        if self.uses_srgb {
+            element.color.to_srgba().to_u8_array()
+        } else {
+            element.color.to_linear().to_u8_array()
+        }
+    }
+
+    /// Writes the texels of `elements`, given by their index in the palette, into the textures of `material` made by
+    /// [`VoxelPalette::create_material`], without rebuilding the rest of the textures
+    pub(crate) fn write_texels(
+        &self,
+        material: &StandardMaterial,
+        elements: &[(usize, VoxelElement)],
+        images: &mut Assets<Image>,
+    ) {
+        let mut write = |texture: &Option<Handle<Image>>,
+                         texel: &dyn Fn(&VoxelElement) -> Vec<u8>| {
+            let Some(data) = texture
+                .as_ref()
+                .and_then(|handle| images.get_mut(handle))
+                .and_then(|image| image.data.as_mut())
+            else {
+                return;
+            };
+            for (index, element) in elements {
+                let texel = texel(element);
+                let start = index * texel.len();
+                if let Some(destination) = data.get_mut(start..start + texel.len()) {
+                    destination.copy_from_slice(&texel);
+                }
+            }
+        };
+        write(&material.base_color_texture, &|e| {
+            self.color_texel(e).to_vec()
+        });
+        write(&material.emissive_texture, &|e| emission_texel(e).to_vec());
+        write(&material.metallic_roughness_texture, &|e| {
+            metallic_roughness_texel(e).to_vec()
+        });
+        #[cfg(feature = "pbr_transmission_textures")]
+        write(&material.specular_transmission_texture, &|e| {
+            specular_transmission_texel(e).to_vec()
+        });
+    }
+}
+
+/// Whether `material` can show the element by writing its texels, because it either has a texture for each of the
+/// element's properties, or the property matches the constant that the material uses instead
+pub(crate) fn material_can_show(material: &StandardMaterial, element: &VoxelElement) -> bool {
+    let matches = |constant: f32, value: f32| (constant - value).abs() < 0.001;
+    let emission = material.emissive_texture.is_some() || element.emission <= 0.0;
+    let metallic_roughness = material.metallic_roughness_texture.is_some()
+        || (matches(material.perceptual_roughness, element.roughness)
+            && matches(material.metallic, element.metalness));
+    #[cfg(feature = "pbr_transmission_textures")]
+    let transmission = material.specular_transmission_texture.is_some()
+        || matches(material.specular_transmission, element.translucency);
+    #[cfg(not(feature = "pbr_transmission_textures"))]
+    let transmission = matches(material.specular_transmission, element.translucency);
+    emission && metallic_roughness && transmission
+}
+
+/// The texel of the element in the emission texture
+fn emission_texel(element: &VoxelElement) -> [u8; 16] {
+    let mut texel = [0; 16];
+    let emission = (element.color.to_linear() * element.emission).to_f32_array();
+    for (bytes, channel) in texel.chunks_exact_mut(4).zip(emission) {
+        bytes.copy_from_slice(&channel.to_le_bytes());
+    }
+    texel
+}
+
+/// The texel of the element in the metallic-roughness texture
+fn metallic_roughness_texel(element: &VoxelElement) -> [u8; 8] {
+    let mut texel = [0; 8];
+    for (bytes, channel) in
+        texel
+            .chunks_exact_mut(2)
+            .zip([0.0, element.roughness, element.metalness, 0.0])
+    {
+        bytes.copy_from_slice(&((channel * u16::MAX as f32) as u16).to_le_bytes());
+    }
+    texel
+}
+
+/// The texel of the element in the specular transmission texture
+#[cfg(feature = "pbr_transmission_textures")]
+fn specular_transmission_texel(element: &VoxelElement) -> [u8; 2] {
+    ((element.translucency * u16::MAX as f32) as u16).to_le_bytes()
 }
 
 trait VecComparable<T> {
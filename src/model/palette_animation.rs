@@ -0,0 +1,280 @@
+use std::{f32::consts::TAU, time::Duration};
+
+use bevy::{
+    asset::{Assets, Handle},
+    ecs::{
+        component::Component,
+        observer::Trigger,
+        system::{Query, Res, ResMut},
+        world::OnRemove,
+    },
+    image::Image,
+    pbr::StandardMaterial,
+    platform::collections::HashMap,
+    time::Time,
+};
+
+use super::{
+    RawVoxel, Voxel, VoxelContext, VoxelElement, VoxelPalette, palette::material_can_show,
+};
+
+/// Animates elements of the palette of a [`VoxelContext`] over time, for lava flows, blinking lights or shimmering
+/// water, without remeshing the models that use the palette.
+///
+/// Every frame, the [`VoxelPaletteEffect`]s are applied in order on top of the context's current palette, and only the
+/// texels of the animated elements are written into the textures of the context's materials. The palette stored in
+/// the context isn't changed, so edits made with [`crate::edit_voxel_palette`] or [`crate::set_voxel_palette`] while
+/// the animation plays are kept, with the effects applied on top of them from the next frame. If an animated element
+/// needs a texture that the materials don't have yet, such as the emission texture of a palette without any emissive
+/// elements, the materials are rebuilt as [`crate::set_voxel_palette`] does. When the component is removed, or its
+/// entity despawned, the materials show the context's palette again.
+///
+/// Each context should be animated by a single [`VoxelPaletteAnimation`], with as many effects as it needs, as
+/// animations of the same context overwrite each other.
+///
+/// ### Example
+/// ```no_run
+/// # use std::time::Duration;
+/// # use bevy::prelude::*;
+/// # use bevy_vox_scene::{Voxel, VoxelContext, VoxelPaletteAnimation, VoxelPaletteEffect};
+/// # let mut commands: Commands = panic!();
+/// # let context: Handle<VoxelContext> = panic!();
+/// commands.spawn(VoxelPaletteAnimation::new(
+///     context,
+///     vec![
+///         VoxelPaletteEffect::Cycle {
+///             first: Voxel(32),
+///             last: Voxel(39),
+///             period: Duration::from_secs(2),
+///         },
+///         VoxelPaletteEffect::Pulse {
+///             voxel: Voxel(12),
+///             min: 0.0,
+///             max: 5.0,
+///             period: Duration::from_secs(1),
+///         },
+///     ],
+/// ));
+/// ```
+#[derive(Component, Clone, Debug)]
+pub struct VoxelPaletteAnimation {
+    /// The context whose palette is animated
+    pub context: Handle<VoxelContext>,
+    /// The effects to apply to the palette, in order
+    pub effects: Vec<VoxelPaletteEffect>,
+    /// If true, playback is paused
+    pub is_paused: bool,
+    /// How long the animation has been playing for
+    pub elapsed: Duration,
+}
+
+impl VoxelPaletteAnimation {
+    /// Create a new animation of the palette of `context`
+    pub fn new(context: Handle<VoxelContext>, effects: Vec<VoxelPaletteEffect>) -> Self {
+        Self {
+            context,
+            effects,
+            is_paused: false,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// The elements that the effects set at the current time, by their index in `palette`
+    fn elements(&self, palette: &VoxelPalette) -> Vec<(usize, VoxelElement)> {
+        let mut animated = AnimatedPalette {
+            palette,
+            elements: HashMap::default(),
+        };
+        for effect in self.effects.iter() {
+            let elements = effect.elements(&animated, self.elapsed);
+            animated.elements.extend(elements);
+        }
+        animated.elements.into_iter().collect()
+    }
+}
+
+/// The palette of a context with the effects applied so far, storing only the elements that the effects have changed
+struct AnimatedPalette<'a> {
+    palette: &'a VoxelPalette,
+    elements: HashMap<usize, VoxelElement>,
+}
+
+impl AnimatedPalette<'_> {
+    fn len(&self) -> usize {
+        self.palette.elements.len()
+    }
+
+    fn element(&self, index: usize) -> Option<&VoxelElement> {
+        self.elements
+            .get(&index)
+            .or_else(|| self.palette.elements.get(index))
+    }
+}
+
+/// The index of the voxel's element in the palette, or `None` for [`Voxel::EMPTY`]
+fn element_index(voxel: &Voxel) -> Option<usize> {
+    (*voxel != Voxel::EMPTY).then(|| RawVoxel::from(voxel.clone()).0 as usize)
+}
+
+/// An effect animating some of the elements of a palette, played by a [`VoxelPaletteAnimation`]
+#[derive(Clone, Debug)]
+pub enum VoxelPaletteEffect {
+    /// Cycles the elements from `first` to `last` inclusive, moving each of them one place up the range in equal
+    /// steps, and wrapping the last element round to `first`, so that the range has cycled once every `period`
+    Cycle {
+        /// The first voxel of the range
+        first: Voxel,
+        /// The last voxel of the range
+        last: Voxel,
+        /// The time taken for every element to move all the way round the range
+        period: Duration,
+    },
+    /// Pulses the emission of the element of `voxel` smoothly between `min` and `max`, starting at `min`
+    Pulse {
+        /// The voxel whose element pulses
+        voxel: Voxel,
+        /// The lowest emission of the pulse
+        min: f32,
+        /// The highest emission of the pulse
+        max: f32,
+        /// The time between each peak of emission
+        period: Duration,
+    },
+    /// Blends the element of `voxel` smoothly from `from` to `to` and back again
+    Lerp {
+        /// The voxel whose element is blended
+        voxel: Voxel,
+        /// The element at the start of each period
+        from: VoxelElement,
+        /// The element half way through each period
+        to: VoxelElement,
+        /// The time taken to blend to `to` and back
+        period: Duration,
+    },
+}
+
+impl VoxelPaletteEffect {
+    /// The elements that the effect sets at `elapsed`, by their index in the palette it's applied to
+    fn elements(&self, palette: &AnimatedPalette, elapsed: Duration) -> Vec<(usize, VoxelElement)> {
+        match self {
+            VoxelPaletteEffect::Cycle {
+                first,
+                last,
+                period,
+            } => {
+                let (Some(first), Some(last)) = (element_index(first), element_index(last)) else {
+                    return Vec::new();
+                };
+                let last = last.min(palette.len().saturating_sub(1));
+                if first > last {
+                    return Vec::new();
+                }
+                let count = last - first + 1;
+                let steps = (phase(elapsed, *period) * count as f32) as usize % count;
+                (0..count)
+                    .filter_map(|offset| {
+                        let source = first + (offset + count - steps) % count;
+                        Some((first + offset, palette.element(source)?.clone()))
+                    })
+                    .collect()
+            }
+            VoxelPaletteEffect::Pulse {
+                voxel,
+                min,
+                max,
+                period,
+            } => {
+                let Some(index) = element_index(voxel) else {
+                    return Vec::new();
+                };
+                let Some(element) = palette.element(index) else {
+                    return Vec::new();
+                };
+                let element = VoxelElement {
+                    emission: min + (max - min) * wave(elapsed, *period),
+                    ..element.clone()
+                };
+                vec![(index, element)]
+            }
+            VoxelPaletteEffect::Lerp {
+                voxel,
+                from,
+                to,
+                period,
+            } => match element_index(voxel) {
+                Some(index) if index < palette.len() => {
+                    vec![(index, from.lerp(to, wave(elapsed, *period)))]
+                }
+                _ => Vec::new(),
+            },
+        }
+    }
+}
+
+/// How far through the current period `elapsed` is, from 0.0 to 1.0
+fn phase(elapsed: Duration, period: Duration) -> f32 {
+    if period.is_zero() {
+        return 0.0;
+    }
+    (elapsed.as_secs_f64() / period.as_secs_f64()).fract() as f32
+}
+
+/// A wave that rises smoothly from 0.0 to 1.0 half way through each period, and falls back to 0.0
+fn wave(elapsed: Duration, period: Duration) -> f32 {
+    0.5 - 0.5 * (phase(elapsed, period) * TAU).cos()
+}
+
+pub(crate) fn animate_voxel_palettes(
+    mut animations: Query<&mut VoxelPaletteAnimation>,
+    contexts: Res<Assets<VoxelContext>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    time: Res<Time>,
+) {
+    for mut animation in animations.iter_mut() {
+        if animation.is_paused {
+            continue;
+        }
+        let Some(context) = contexts.get(&animation.context) else {
+            continue;
+        };
+        let elements = animation.elements(&context.palette);
+        if let Some(material) = materials.get(&context.transmissive_material) {
+            // the context's materials share the textures of its transmissive material
+            if elements
+                .iter()
+                .all(|(_, element)| material_can_show(material, element))
+            {
+                context
+                    .palette
+                    .write_texels(material, &elements, &mut images);
+            } else {
+                let mut palette = context.palette.clone();
+                palette.set_elements(
+                    elements
+                        .into_iter()
+                        .map(|(index, element)| (RawVoxel(index as u16).into(), element)),
+                );
+                context.update_materials(&palette, &mut materials, &mut images);
+            }
+        }
+        animation.elapsed += time.delta();
+    }
+}
+
+/// Shows the context's palette again when a [`VoxelPaletteAnimation`] is removed
+pub(crate) fn on_remove_palette_animation(
+    trigger: Trigger<OnRemove, VoxelPaletteAnimation>,
+    animations: Query<&VoxelPaletteAnimation>,
+    contexts: Res<Assets<VoxelContext>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Ok(animation) = animations.get(trigger.target()) else {
+        return;
+    };
+    let Some(context) = contexts.get(&animation.context) else {
+        return;
+    };
+    context.update_materials(&context.palette, &mut materials, &mut images);
+}
@@ -1,6 +1,6 @@
 use super::{RawVoxel, Voxel, VoxelData, VoxelModel};
 use bevy::{
-    math::{BVec3, IVec3, UVec3, Vec3},
+    math::{BVec3, IVec3, Ray3d, UVec3, Vec3},
     transform::components::GlobalTransform,
 };
 use ndshape::Shape;
@@ -69,6 +69,101 @@ pub trait VoxelQueryable {
     /// ### Returns
     /// the voxel at this point. If the point lies outside the bounds of the model, it will return [`OutOfBoundsError`].
     fn get_voxel_at_point(&self, position: IVec3) -> Result<Voxel, OutOfBoundsError>;
+
+    /// Casts a ray through the model, stepping from voxel to voxel with a digital differential analyzer, and returns the
+    /// first voxel that isn't [`Voxel::EMPTY`]. As there is no palette to consult, cloud and transmissive voxels are hit
+    /// like any other.
+    ///
+    /// ### Arguments
+    /// * `ray` - the ray in global space
+    /// * `global_xform` - the [`bevy::transform::components::GlobalTransform`] of the entity that owns this [`crate::VoxelModelInstance`]
+    ///
+    /// ### Returns
+    /// the voxel that was hit, or `None` if the ray misses all of the model's voxels
+    fn raycast(&self, ray: Ray3d, global_xform: &GlobalTransform) -> Option<VoxelRayHit> {
+        // convert the ray to voxel space, using the same conversion as `global_point_to_voxel_space`, but without
+        // truncating to whole voxels
+        let inverse = global_xform.affine().inverse();
+        let voxel_zero = self.voxel_coord_to_local_space(IVec3::ZERO);
+        let voxel_scale = self.voxel_coord_to_local_space(IVec3::ONE) - voxel_zero;
+        let origin = (inverse.transform_point3(ray.origin) - voxel_zero) / voxel_scale;
+        let direction = inverse.transform_vector3(*ray.direction) / voxel_scale;
+        let size = self.size();
+
+        // find where the ray enters the bounds of the model
+        let mut entry_distance = 0.0_f32;
+        let mut exit_distance = f32::INFINITY;
+        let mut normal = IVec3::ZERO;
+        for axis in 0..3 {
+            if direction[axis] == 0.0 {
+                if origin[axis] < 0.0 || origin[axis] > size[axis] as f32 {
+                    return None;
+                }
+                continue;
+            }
+            let near = (0.0 - origin[axis]) / direction[axis];
+            let far = (size[axis] as f32 - origin[axis]) / direction[axis];
+            let (near, far) = (near.min(far), near.max(far));
+            if near > entry_distance {
+                entry_distance = near;
+                normal = IVec3::ZERO;
+                normal[axis] = -direction[axis].signum() as i32;
+            }
+            exit_distance = exit_distance.min(far);
+        }
+        if entry_distance > exit_distance {
+            return None;
+        }
+
+        let entry = origin + direction * entry_distance;
+        let mut voxel_coord = entry.floor().as_ivec3().clamp(IVec3::ZERO, size - 1);
+        let step = direction.signum().as_ivec3();
+        let mut next_boundary = Vec3::ZERO;
+        let mut boundary_spacing = Vec3::ZERO;
+        for axis in 0..3 {
+            if direction[axis] == 0.0 {
+                next_boundary[axis] = f32::INFINITY;
+                continue;
+            }
+            let boundary = voxel_coord[axis] + if step[axis] > 0 { 1 } else { 0 };
+            next_boundary[axis] = (boundary as f32 - origin[axis]) / direction[axis];
+            boundary_spacing[axis] = (1.0 / direction[axis]).abs();
+        }
+        let mut distance = entry_distance;
+        loop {
+            let voxel = self.get_voxel_at_point(voxel_coord).ok()?;
+            if voxel != Voxel::EMPTY {
+                return Some(VoxelRayHit {
+                    voxel_coord,
+                    normal,
+                    distance,
+                    voxel,
+                });
+            }
+            let axis = (0..3)
+                .min_by(|a, b| next_boundary[*a].total_cmp(&next_boundary[*b]))
+                .unwrap_or(0);
+            distance = next_boundary[axis];
+            voxel_coord[axis] += step[axis];
+            next_boundary[axis] += boundary_spacing[axis];
+            normal = IVec3::ZERO;
+            normal[axis] = -step[axis];
+        }
+    }
+}
+
+/// A voxel hit by [`VoxelQueryable::raycast`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct VoxelRayHit {
+    /// The coordinate of the voxel, in voxel space
+    pub voxel_coord: IVec3,
+    /// The normal of the face of the voxel that the ray entered through, in voxel space, so that `voxel_coord + normal`
+    /// is the empty space in front of the face. It is zero if the ray started inside the voxel.
+    pub normal: IVec3,
+    /// The distance along the ray to the point where it hits the voxel, in global space
+    pub distance: f32,
+    /// The voxel that was hit
+    pub voxel: Voxel,
 }
 
 impl VoxelQueryable for VoxelModel {
@@ -104,14 +199,14 @@ impl VoxelQueryable for VoxelData {
     }
 
     fn local_point_to_voxel_space(&self, local_point: Vec3) -> IVec3 {
-        let half_extents = self.size().as_vec3() * 0.5;
-        let voxel_postition = (local_point / self.settings.voxel_size) + half_extents;
+        let offset = self.size().as_vec3() * self.settings.mesh_offset.0;
+        let voxel_postition = (local_point / self.settings.voxel_size) + offset;
         voxel_postition.as_ivec3()
     }
 
     fn voxel_coord_to_local_space(&self, voxel_coord: IVec3) -> Vec3 {
-        let half_extents = self.size().as_vec3() * 0.5;
-        (voxel_coord.as_vec3() - half_extents) * self.settings.voxel_size
+        let offset = self.size().as_vec3() * self.settings.mesh_offset.0;
+        (voxel_coord.as_vec3() - offset) * self.settings.voxel_size
     }
 
     fn get_voxel_at_point(&self, position: IVec3) -> Result<Voxel, OutOfBoundsError> {
@@ -119,7 +214,7 @@ impl VoxelQueryable for VoxelData {
         let leading_padding = UVec3::splat(self.padding() / 2);
         let index = self.shape.linearize((position + leading_padding).into()) as usize;
         let raw_voxel = self.voxels.get(index).ok_or(OutOfBoundsError)?;
-        let voxel: Voxel = raw_voxel.clone().into();
+        let voxel: Voxel = raw_voxel.into();
         Ok(voxel)
     }
 }
@@ -134,7 +229,8 @@ impl VoxelData {
         let leading_padding = UVec3::splat(self.padding() / 2);
         let index = self.shape.linearize((point + leading_padding).into()) as usize;
         let raw_voxel: RawVoxel = voxel.into();
-        self.voxels[index] = raw_voxel;
+        self.voxels.set(index, raw_voxel);
+        self.mark_changed();
     }
 }
 trait BitwiseComparable {
@@ -0,0 +1,150 @@
+use bevy::{
+    asset::{Assets, Handle},
+    ecs::system::{In, ResMut},
+    image::Image,
+    log::warn,
+    pbr::StandardMaterial,
+};
+
+use super::{Voxel, VoxelContext, VoxelElement, VoxelPalette, palette::palette_atlas_width};
+
+impl VoxelContext {
+    /// Replaces the palette of the context, and rewrites the textures behind the handles of its materials, so that
+    /// every model using the context is recolored without being remeshed.
+    ///
+    /// The materials of transmissive models, which are copies of the context's transmissive material, share its
+    /// textures and are updated too, keeping their own index of refraction and thickness.
+    pub(crate) fn set_palette(
+        &mut self,
+        mut palette: VoxelPalette,
+        materials: &mut Assets<StandardMaterial>,
+        images: &mut Assets<Image>,
+    ) {
+        // keep the size of the palette texture, so the UVs of existing meshes still point at the right elements
+        let previous_len = self.palette.elements.len();
+        palette.pad(previous_len);
+        if palette_atlas_width(palette.elements.len()) != palette_atlas_width(previous_len) {
+            warn!(
+                "The palette has grown from {} to {} elements, models using it must be remeshed to show the new elements correctly",
+                previous_len,
+                palette.elements.len()
+            );
+        }
+        self.update_materials(&palette, materials, images);
+        self.palette = palette;
+    }
+
+    /// Rewrites the textures behind the handles of the context's materials, and the materials sharing them, to show
+    /// `palette`, without storing it in the context
+    pub(crate) fn update_materials(
+        &self,
+        palette: &VoxelPalette,
+        materials: &mut Assets<StandardMaterial>,
+        images: &mut Assets<Image>,
+    ) {
+        let Some(transmissive_material) = materials.get(&self.transmissive_material) else {
+            return;
+        };
+        let color_texture = transmissive_material.base_color_texture.clone();
+        let had_emission = transmissive_material.emissive_texture.is_some();
+        let updated = palette.update_material(transmissive_material, images);
+        let shared: Vec<_> = materials
+            .iter()
+            .filter(|(_, material)| material.base_color_texture == color_texture)
+            .map(|(id, _)| id)
+            .collect();
+        for id in shared {
+            let Some(material) = materials.get_mut(id) else {
+                continue;
+            };
+            material.base_color_texture = updated.base_color_texture.clone();
+            // leave materials that were made without the emission texture, such as the loader's
+            // `material-no-emission`, unlit
+            if id == self.transmissive_material.id()
+                || id == self.opaque_material.id()
+                || material.emissive_texture.is_some()
+                || !had_emission
+            {
+                material.emissive = updated.emissive;
+                material.emissive_texture = updated.emissive_texture.clone();
+            }
+            material.perceptual_roughness = updated.perceptual_roughness;
+            material.metallic = updated.metallic;
+            material.metallic_roughness_texture = updated.metallic_roughness_texture.clone();
+            if id != self.opaque_material.id() {
+                material.specular_transmission = updated.specular_transmission;
+                #[cfg(feature = "pbr_transmission_textures")]
+                {
+                    material.specular_transmission_texture =
+                        updated.specular_transmission_texture.clone();
+                }
+            }
+        }
+    }
+}
+
+/// System that replaces the palette of a [`VoxelContext`], recoloring every model that uses it.
+///
+/// The textures of the context's materials are rewritten in place, so the models aren't remeshed. This means that
+/// elements becoming translucent or clouds, or stopping being so, only changes how a model is meshed the next time it
+/// is remeshed, for instance when it's modified.
+///
+/// A smaller palette is padded to the size of the current one. A palette needing a larger texture than the current
+/// one, because it has grown past a power of two squared, such as 256 elements, needs its models to be remeshed.
+///
+/// ### Example
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_vox_scene::{VoxelContext, VoxelPalette, set_voxel_palette};
+/// # let mut world: World = panic!();
+/// # let context: Handle<VoxelContext> = panic!();
+/// let night = VoxelPalette::from_colors(vec![Color::srgb(0.1, 0.1, 0.3)], true);
+/// world
+///     .run_system_cached_with(set_voxel_palette, (context, night))
+///     .expect("Replace palette");
+/// ```
+pub fn set_voxel_palette(
+    In((context, palette)): In<(Handle<VoxelContext>, VoxelPalette)>,
+    mut contexts: ResMut<Assets<VoxelContext>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(context) = contexts.get_mut(&context) else {
+        return;
+    };
+    context.set_palette(palette, &mut materials, &mut images);
+}
+
+/// System that replaces some of the [`VoxelElement`]s in the palette of a [`VoxelContext`], recoloring every model
+/// that uses it, for instance to give a model a team color.
+///
+/// Takes the context, and the elements to use for each [`Voxel`] value. Edits to [`Voxel::EMPTY`] or values outside
+/// of the palette are ignored. As with [`set_voxel_palette`], the models aren't remeshed.
+///
+/// ### Example
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_vox_scene::{Voxel, VoxelContext, VoxelElement, edit_voxel_palette};
+/// # let mut world: World = panic!();
+/// # let context: Handle<VoxelContext> = panic!();
+/// let team_color = VoxelElement {
+///     color: Color::srgb(0.8, 0.1, 0.1),
+///     ..default()
+/// };
+/// world
+///     .run_system_cached_with(edit_voxel_palette, (context, vec![(Voxel(12), team_color)]))
+///     .expect("Edit palette");
+/// ```
+pub fn edit_voxel_palette(
+    In((context, edits)): In<(Handle<VoxelContext>, Vec<(Voxel, VoxelElement)>)>,
+    mut contexts: ResMut<Assets<VoxelContext>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    let Some(context) = contexts.get_mut(&context) else {
+        return;
+    };
+    let mut palette = context.palette.clone();
+    palette.set_elements(edits);
+    context.set_palette(palette, &mut materials, &mut images);
+}
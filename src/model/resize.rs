@@ -0,0 +1,97 @@
+use bevy::math::{IVec3, UVec3};
+
+use crate::UnitOffset;
+
+use super::{VoxelData, VoxelQueryable, modify::VoxelRegion};
+
+/// How a [`crate::VoxelModifier`] resizes the bounds of the model before modifying it.
+///
+/// The existing voxels stay where they are, and the mesh offset of the model is adjusted so that the model doesn't
+/// move. If the model grows below its minimum corner, the voxel coordinates of its existing voxels increase by the
+/// amount that it grew.
+#[derive(Clone, Debug)]
+pub enum VoxelResize {
+    /// Grow the model just enough to fit the region of the modifier, so that voxels can be added outside its
+    /// original bounds. A model is never shrunk to fit.
+    ToFit,
+    /// Grow or shrink the model to `size`. The `anchor` is the point of the model that stays fixed as it is resized:
+    /// [`UnitOffset::ZERO`] keeps the minimum corner in place, and grows or shrinks the model along the positive axes,
+    /// while [`UnitOffset::CENTER`] grows or shrinks it equally on each side. Voxels outside of the new bounds are
+    /// dropped.
+    To {
+        /// The new size of the model, in voxels
+        size: UVec3,
+        /// The point of the model that stays in place, relative to its size
+        anchor: UnitOffset,
+    },
+}
+
+impl VoxelResize {
+    /// The new size of the model, and how far its existing voxels move in voxel space
+    pub(super) fn size_and_shift(
+        &self,
+        data: &VoxelData,
+        region: Option<&VoxelRegion>,
+    ) -> (IVec3, IVec3) {
+        let model_size = data.size();
+        match self {
+            VoxelResize::ToFit => {
+                let Some(region) = region else {
+                    return (model_size, IVec3::ZERO);
+                };
+                let min = region.origin.min(IVec3::ZERO);
+                let max = (region.origin + region.size).max(model_size);
+                (max - min, -min)
+            }
+            VoxelResize::To { size, anchor } => {
+                let size = size.max(UVec3::ONE).as_ivec3();
+                let shift = ((size - model_size).as_vec3() * anchor.0)
+                    .round()
+                    .as_ivec3();
+                (size, shift)
+            }
+        }
+    }
+}
+
+impl VoxelData {
+    /// Grows or shrinks the model to `size`, keeping the point at `anchor` fixed, as described by
+    /// [`VoxelResize::To`].
+    ///
+    /// ### Returns
+    /// The amount that the voxel coordinates of the existing voxels have moved by
+    pub fn resize(&mut self, size: UVec3, anchor: UnitOffset) -> IVec3 {
+        let (size, shift) = VoxelResize::To { size, anchor }.size_and_shift(self, None);
+        self.resize_with_shift(size, shift);
+        shift
+    }
+
+    /// Resizes the model to `size`, moving the existing voxels by `shift`, and adjusts the mesh offset so that the
+    /// voxels stay in the same place in the mesh
+    pub(super) fn resize_with_shift(&mut self, size: IVec3, shift: IVec3) {
+        let old_size = self.size();
+        if size == old_size && shift == IVec3::ZERO {
+            return;
+        }
+        let mut resized = VoxelData::new(size.as_uvec3(), self.settings.clone());
+        let leading_padding = IVec3::splat(self.padding() as i32 / 2);
+        let source_min = (-shift).max(IVec3::ZERO);
+        let destination_min = shift.max(IVec3::ZERO);
+        let extent = (old_size - source_min).min(size - destination_min);
+        if extent.cmpgt(IVec3::ZERO).all() {
+            resized.voxels.copy_from(
+                &resized.shape,
+                (destination_min + leading_padding).as_uvec3(),
+                &self.voxels,
+                &self.shape,
+                (source_min + leading_padding).as_uvec3(),
+                extent.as_uvec3(),
+            );
+        }
+        // the mesh is offset by a fraction of the model's size, which is recalculated so the voxels don't move
+        let old_offset = old_size.as_vec3() * self.settings.mesh_offset.0;
+        let new_offset = (old_offset + shift.as_vec3()) / size.as_vec3();
+        resized.settings.mesh_offset = UnitOffset(new_offset);
+        *self = resized;
+    }
+}
@@ -0,0 +1,365 @@
+use bevy::{
+    asset::{AssetId, Assets, Handle},
+    ecs::{
+        entity::Entity,
+        hierarchy::Children,
+        query::Has,
+        system::{Commands, Query, ResMut, SystemParam},
+    },
+    image::Image,
+    math::Vec3,
+    pbr::{FogVolume, MeshMaterial3d, StandardMaterial},
+    platform::collections::{HashMap, HashSet},
+    render::mesh::{Mesh, Mesh3d},
+    transform::components::Transform,
+};
+
+use crate::{VoxelModelInstance, material::SourceMaterial};
+
+use super::{
+    VoxelChunks, VoxelContext, VoxelModel, VoxelQueryable,
+    lod::{LodMesh, lod_mesh_bundle, lod_visibility_ranges},
+    modify::Remeshed,
+    transmissive::{TransmissiveMesh, add_transmissive_assets, transmissive_mesh_bundle},
+};
+
+/// The instances of voxel models, and the assets that are updated when one of the models is modified
+#[derive(SystemParam)]
+pub struct VoxelModelSync<'w, 's> {
+    commands: Commands<'w, 's>,
+    instances: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static VoxelModelInstance,
+            Has<Mesh3d>,
+            Option<&'static MeshMaterial3d<StandardMaterial>>,
+            Option<&'static SourceMaterial>,
+            Option<&'static Children>,
+            Option<&'static VoxelChunks>,
+        ),
+    >,
+    children: Query<
+        'w,
+        's,
+        (
+            Option<&'static Mesh3d>,
+            Option<&'static MeshMaterial3d<StandardMaterial>>,
+            Option<&'static SourceMaterial>,
+            Option<&'static FogVolume>,
+            Has<TransmissiveMesh>,
+            Has<LodMesh>,
+        ),
+    >,
+    meshes: ResMut<'w, Assets<Mesh>>,
+    materials: ResMut<'w, Assets<StandardMaterial>>,
+    images: ResMut<'w, Assets<Image>>,
+}
+
+impl VoxelModelSync<'_, '_> {
+    /// The meshes of the chunks shared by the instances of the model, if its mesh is split into chunks
+    pub(super) fn chunks(&self, model_id: AssetId<VoxelModel>) -> Option<Vec<Handle<Mesh>>> {
+        self.instances
+            .iter()
+            .find_map(|(_, instance, _, _, _, _, chunks)| {
+                (instance.model.id() == model_id)
+                    .then_some(chunks)
+                    .flatten()
+            })
+            .map(|chunks| chunks.meshes.clone())
+    }
+
+    /// Updates the model's flags and meshes, and each of its instances: adding or removing the mesh if the model has
+    /// gained or lost its visible voxels, updating the index of refraction of the material, replacing the levels of
+    /// detail and the meshes of its transmissive voxels if they're split by index of refraction, and adding, updating,
+    /// or removing the
+    /// [`FogVolume`] of its cloud voxels.
+    pub(super) fn resync(
+        &mut self,
+        model_id: AssetId<VoxelModel>,
+        model: &mut VoxelModel,
+        context: &VoxelContext,
+        remeshed: Remeshed,
+    ) {
+        let Self {
+            commands,
+            instances,
+            children,
+            meshes,
+            materials,
+            images,
+        } = self;
+        model.has_mesh = remeshed.has_mesh;
+        model.has_cloud = remeshed.cloud.is_some();
+        for (mesh_handle, mesh) in remeshed.meshes {
+            meshes.insert(&mesh_handle, mesh);
+        }
+        // the chunks of a resized model, which are shared by all of its instances
+        let added_chunks: Vec<Handle<Mesh>> = remeshed
+            .added_chunks
+            .into_iter()
+            .map(|mesh| meshes.add(mesh))
+            .collect();
+        for mesh_handle in remeshed.removed_chunks.iter() {
+            meshes.remove(mesh_handle);
+        }
+        // the transmissive meshes of a model split by index of refraction, which are shared by all of its instances
+        let transmissive_meshes = add_transmissive_assets(
+            remeshed.transmissive_meshes,
+            model.size().min_element() as f32,
+            context,
+            meshes,
+            materials,
+        );
+        // the levels of detail of the model, which are shared by all of its instances
+        let lod_meshes: Vec<Handle<Mesh>> = remeshed
+            .lod_meshes
+            .into_iter()
+            .map(|mesh| meshes.add(mesh))
+            .collect();
+        let lod_ranges = lod_visibility_ranges(&model.data.settings.lod_distances);
+        let cloud_transform = Transform::from_translation(
+            model.model_size() * (Vec3::splat(0.5) - model.data.settings.mesh_offset.0),
+        )
+        .with_scale(model.model_size());
+        let mut materials = MaterialResync {
+            materials,
+            context,
+            average_ior: remeshed.average_ior,
+            thickness: model.size().min_element() as f32,
+            replacements: HashMap::default(),
+            new_material: None,
+        };
+        let mut updated_images: HashSet<AssetId<Image>> = HashSet::default();
+        let mut new_image: Option<Handle<Image>> = None;
+        for (entity, instance, has_mesh, material, source, instance_children, chunks) in
+            instances.iter()
+        {
+            if instance.model.id() != model_id {
+                continue;
+            }
+            let material = standard_material(material, source);
+            // the whole mesh of the model appears or disappears
+            let mesh_material = match (&remeshed.model_mesh, material) {
+                (Some(mesh), _) if remeshed.has_mesh && !has_mesh => {
+                    let material = materials.for_new_mesh();
+                    commands
+                        .entity(entity)
+                        .insert((Mesh3d(mesh.clone()), MeshMaterial3d(material.clone())));
+                    Some(material)
+                }
+                (Some(_), _) if !remeshed.has_mesh && has_mesh => {
+                    commands.entity(entity).remove::<(
+                        Mesh3d,
+                        MeshMaterial3d<StandardMaterial>,
+                        SourceMaterial,
+                    )>();
+                    None
+                }
+                (_, Some(material)) if has_mesh => match materials.resync(material) {
+                    Some(replacement) => {
+                        commands
+                            .entity(entity)
+                            .insert(MeshMaterial3d(replacement.clone()));
+                        Some(replacement)
+                    }
+                    None => Some(material.clone()),
+                },
+                _ => None,
+            };
+            let mut fog_volume: Option<(Entity, &FogVolume)> = None;
+            let mut chunk_material: Option<Handle<StandardMaterial>> = None;
+            for child in instance_children.into_iter().flatten() {
+                let Ok((mesh, material, source, fog, is_transmissive_mesh, is_lod_mesh)) =
+                    children.get(*child)
+                else {
+                    continue;
+                };
+                if let Some(fog) = fog {
+                    fog_volume = Some((*child, fog));
+                }
+                if is_transmissive_mesh || (is_lod_mesh && remeshed.model_mesh.is_some()) {
+                    commands.entity(*child).despawn();
+                    continue;
+                }
+                // the material of each chunk
+                let (Some(mesh), Some(material), Some(chunks)) =
+                    (mesh, standard_material(material, source), chunks)
+                else {
+                    continue;
+                };
+                if !chunks.meshes.contains(&mesh.0) {
+                    continue;
+                }
+                if remeshed.removed_chunks.contains(&mesh.0) {
+                    commands.entity(*child).despawn();
+                    continue;
+                }
+                let material = match materials.resync(material) {
+                    Some(replacement) => {
+                        commands
+                            .entity(*child)
+                            .insert(MeshMaterial3d(replacement.clone()));
+                        replacement
+                    }
+                    None => material.clone(),
+                };
+                chunk_material = Some(material);
+            }
+            if let Some(chunks) = chunks
+                && (!added_chunks.is_empty() || !remeshed.removed_chunks.is_empty())
+            {
+                let material = chunk_material.unwrap_or_else(|| materials.for_new_mesh());
+                let mut chunk_meshes: Vec<Handle<Mesh>> = chunks
+                    .meshes
+                    .iter()
+                    .filter(|mesh| !remeshed.removed_chunks.contains(mesh))
+                    .cloned()
+                    .collect();
+                commands.entity(entity).with_children(|spawner| {
+                    for mesh in added_chunks.iter() {
+                        spawner.spawn((
+                            Mesh3d(mesh.clone()),
+                            MeshMaterial3d(material.clone()),
+                            Transform::IDENTITY,
+                        ));
+                    }
+                });
+                chunk_meshes.extend(added_chunks.iter().cloned());
+                commands.entity(entity).insert(VoxelChunks {
+                    meshes: chunk_meshes,
+                });
+            }
+            if let Some(material) = mesh_material
+                && remeshed.model_mesh.is_some()
+                && !lod_meshes.is_empty()
+            {
+                let mut ranges = lod_ranges.iter().cloned();
+                if let Some(range) = ranges.next() {
+                    commands.entity(entity).insert(range);
+                }
+                commands.entity(entity).with_children(|spawner| {
+                    for (mesh, range) in lod_meshes.iter().zip(ranges) {
+                        spawner.spawn(lod_mesh_bundle(mesh.clone(), material.clone(), range));
+                    }
+                });
+            }
+            if !transmissive_meshes.is_empty() {
+                commands.entity(entity).with_children(|spawner| {
+                    for (mesh, material) in transmissive_meshes.iter() {
+                        spawner.spawn(transmissive_mesh_bundle(mesh.clone(), material.clone()));
+                    }
+                });
+            }
+            match (fog_volume, &remeshed.cloud) {
+                (Some((child, fog)), Some(image)) if fog.density_texture.is_some() => {
+                    let texture = fog
+                        .density_texture
+                        .as_ref()
+                        .expect("Fog has a density texture");
+                    if updated_images.insert(texture.id()) {
+                        images.insert(texture, image.clone());
+                    }
+                    commands.entity(child).insert(cloud_transform);
+                }
+                (Some((child, _)), None) => {
+                    commands.entity(child).despawn();
+                }
+                (fog_volume, Some(image)) => {
+                    let texture = new_image
+                        .get_or_insert_with(|| images.add(image.clone()))
+                        .clone();
+                    let fog = FogVolume {
+                        density_texture: Some(texture),
+                        absorption: 0.1,
+                        ..Default::default()
+                    };
+                    match fog_volume {
+                        Some((child, _)) => {
+                            commands.entity(child).insert((fog, cloud_transform));
+                        }
+                        None => {
+                            commands.entity(entity).with_child((fog, cloud_transform));
+                        }
+                    }
+                }
+                (None, None) => {}
+            }
+        }
+    }
+}
+
+/// The [`StandardMaterial`] of a mesh, which is kept in a [`SourceMaterial`] once a [`crate::VoxelMaterialPlugin`] has
+/// replaced it
+fn standard_material<'a>(
+    material: Option<&'a MeshMaterial3d<StandardMaterial>>,
+    source: Option<&'a SourceMaterial>,
+) -> Option<&'a Handle<StandardMaterial>> {
+    material
+        .map(|material| &material.0)
+        .or(source.map(|source| &source.0))
+}
+
+/// Keeps the materials of a modified model's instances in step with its index of refraction
+struct MaterialResync<'a> {
+    materials: &'a mut Assets<StandardMaterial>,
+    context: &'a VoxelContext,
+    average_ior: Option<f32>,
+    thickness: f32,
+    /// The material that each material has been replaced with, so that instances sharing a material still share it
+    replacements: HashMap<AssetId<StandardMaterial>, Handle<StandardMaterial>>,
+    /// The transmissive material created for the model, shared by all of its instances
+    new_material: Option<Handle<StandardMaterial>>,
+}
+
+impl MaterialResync<'_> {
+    /// Updates the index of refraction of the material, or returns the material that should replace it if the model
+    /// has become transmissive or opaque
+    fn resync(&mut self, material: &Handle<StandardMaterial>) -> Option<Handle<StandardMaterial>> {
+        if let Some(replacement) = self.replacements.get(&material.id()) {
+            return (replacement != material).then(|| replacement.clone());
+        }
+        let is_transmissive = self
+            .materials
+            .get(material)
+            .is_some_and(|material| material.specular_transmission > 0.0);
+        // the context's materials are shared by all of its models, so they're never changed
+        let is_shared = *material == self.context.opaque_material
+            || *material == self.context.transmissive_material;
+        let replacement = match self.average_ior {
+            Some(ior) if is_transmissive && !is_shared => {
+                if let Some(material) = self.materials.get_mut(material) {
+                    material.ior = ior;
+                    material.thickness = self.thickness;
+                }
+                material.clone()
+            }
+            Some(_) => self.for_new_mesh(),
+            None if is_transmissive => self.context.opaque_material.clone(),
+            None => material.clone(),
+        };
+        self.replacements.insert(material.id(), replacement.clone());
+        (replacement != *material).then_some(replacement)
+    }
+
+    /// The material for an instance that didn't have a mesh before the model was modified
+    fn for_new_mesh(&mut self) -> Handle<StandardMaterial> {
+        let Some(ior) = self.average_ior else {
+            return self.context.opaque_material.clone();
+        };
+        if let Some(material) = &self.new_material {
+            return material.clone();
+        }
+        let mut material = self
+            .materials
+            .get(self.context.transmissive_material.id())
+            .expect("Transmissive material exists")
+            .clone();
+        material.ior = ior;
+        material.thickness = self.thickness;
+        let handle = self.materials.add(material);
+        self.new_material = Some(handle.clone());
+        handle
+    }
+}
@@ -28,7 +28,7 @@ impl SDF {
         })
     }
 
-    fn distance(&self, point: Vec3) -> f32 {
+    pub(crate) fn distance(&self, point: Vec3) -> f32 {
         (self.distance)(point)
     }
 
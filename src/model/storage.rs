@@ -0,0 +1,487 @@
+use std::fmt::Debug;
+
+use bevy::math::UVec3;
+use ndshape::{RuntimeShape, Shape};
+use serde::{Deserialize, Serialize};
+
+use super::RawVoxel;
+
+/// How the voxels of a [`super::VoxelData`] are stored in memory, chosen when it is created with
+/// [`crate::VoxLoaderSettings::storage`]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VoxelStorage {
+    /// Every voxel is stored, including the empty ones, in a single buffer. This is the fastest to read and write.
+    #[default]
+    Dense,
+    /// The model is divided into bricks of 8x8x8 voxels, and only the bricks containing at least one voxel are
+    /// stored, so large models that are mostly empty take up a fraction of the memory. Bricks are freed again when all
+    /// of their voxels are removed.
+    Sparse,
+}
+
+/// The length of each side of a brick of a sparse model
+pub(super) const BRICK_SIZE: u32 = 8;
+
+/// The number of voxels in each brick of a sparse model
+const BRICK_VOLUME: usize = (BRICK_SIZE * BRICK_SIZE * BRICK_SIZE) as usize;
+
+/// The voxels of a model, indexed by their position in the model's padded shape.
+///
+/// Voxels are stored as a single byte until one is set to a palette index that doesn't fit, at which point the
+/// whole model is widened to two bytes per voxel, so that only models using wide palettes pay for them.
+#[derive(Clone, Debug)]
+pub(crate) enum Voxels {
+    Narrow(Store<u8>),
+    Wide(Store<u16>),
+}
+
+impl Voxels {
+    /// Empty voxels filling `shape`, stored as `storage`
+    pub(crate) fn new(shape: &RuntimeShape<u32, 3>, storage: VoxelStorage) -> Self {
+        Voxels::Narrow(Store::new(shape, storage))
+    }
+
+    /// Empty dense voxels, one byte per voxel
+    pub(crate) fn dense(len: usize) -> Self {
+        Voxels::Narrow(Store::Dense(vec![u8::EMPTY; len]))
+    }
+
+    pub(crate) fn storage(&self) -> VoxelStorage {
+        match self {
+            Voxels::Narrow(store) => store.storage(),
+            Voxels::Wide(store) => store.storage(),
+        }
+    }
+
+    /// Whether the voxels are stored with two bytes each, for palettes of more than 255 elements
+    pub(crate) fn is_wide(&self) -> bool {
+        matches!(self, Voxels::Wide(_))
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Voxels::Narrow(store) => store.len(),
+            Voxels::Wide(store) => store.len(),
+        }
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<RawVoxel> {
+        match self {
+            Voxels::Narrow(store) => store.get(index).map(VoxelIndex::to_raw),
+            Voxels::Wide(store) => store.get(index).map(VoxelIndex::to_raw),
+        }
+    }
+
+    /// The voxel at `index`, which must be within the model
+    pub(crate) fn at(&self, index: usize) -> RawVoxel {
+        self.get(index).expect("Voxel index is within the model")
+    }
+
+    pub(crate) fn set(&mut self, index: usize, voxel: RawVoxel) {
+        match self {
+            Voxels::Narrow(store) => match u8::from_raw(voxel) {
+                Some(narrow) => store.set(index, narrow),
+                None => {
+                    self.widen();
+                    self.set(index, voxel);
+                }
+            },
+            Voxels::Wide(store) => store.set(index, voxel.0),
+        }
+    }
+
+    /// Converts the voxels to two bytes each, so that they can hold any palette index
+    pub(crate) fn widen(&mut self) {
+        if let Voxels::Narrow(store) = self {
+            *self = Voxels::Wide(store.map(|voxel| voxel.to_raw().0));
+        }
+    }
+
+    /// Every voxel, in the order of their indices. The storage is matched once, so that dense voxels are read straight
+    /// from their buffer.
+    pub(crate) fn iter(&self) -> Box<dyn Iterator<Item = RawVoxel> + '_> {
+        match self {
+            Voxels::Narrow(Store::Dense(voxels)) => {
+                Box::new(voxels.iter().map(|voxel| voxel.to_raw()))
+            }
+            Voxels::Wide(Store::Dense(voxels)) => {
+                Box::new(voxels.iter().map(|voxel| RawVoxel(*voxel)))
+            }
+            Voxels::Narrow(Store::Sparse(bricks)) => Box::new(bricks.iter().map(u8::to_raw)),
+            Voxels::Wide(Store::Sparse(bricks)) => Box::new(bricks.iter().map(RawVoxel)),
+        }
+    }
+
+    /// The voxels that aren't empty, with their indices. The empty bricks of sparse voxels are skipped.
+    pub(crate) fn filled(&self) -> Box<dyn Iterator<Item = (usize, RawVoxel)> + '_> {
+        match self {
+            Voxels::Narrow(store) => {
+                Box::new(store.filled().map(|(index, voxel)| (index, voxel.to_raw())))
+            }
+            Voxels::Wide(store) => Box::new(
+                store
+                    .filled()
+                    .map(|(index, voxel)| (index, RawVoxel(voxel))),
+            ),
+        }
+    }
+
+    /// The position of the first voxel of each brick that contains voxels, or `None` if the voxels aren't sparse
+    pub(crate) fn filled_bricks(&self) -> Option<Vec<UVec3>> {
+        match self {
+            Voxels::Narrow(Store::Sparse(bricks)) => Some(bricks.filled_bricks()),
+            Voxels::Wide(Store::Sparse(bricks)) => Some(bricks.filled_bricks()),
+            _ => None,
+        }
+    }
+
+    /// The approximate number of bytes used to store the voxels
+    pub(crate) fn memory_usage(&self) -> usize {
+        match self {
+            Voxels::Narrow(store) => store.memory_usage(),
+            Voxels::Wide(store) => store.memory_usage(),
+        }
+    }
+
+    /// Copies a box of voxels of size `extent` from `source` at `source_min`, to `self` at `destination_min`, in the
+    /// coordinates of their shapes
+    pub(crate) fn copy_from(
+        &mut self,
+        shape: &RuntimeShape<u32, 3>,
+        destination_min: UVec3,
+        source: &Voxels,
+        source_shape: &RuntimeShape<u32, 3>,
+        source_min: UVec3,
+        extent: UVec3,
+    ) {
+        if source.is_wide() {
+            self.widen();
+        }
+        match (&mut *self, source) {
+            (Voxels::Narrow(Store::Dense(destination)), Voxels::Narrow(Store::Dense(source))) => {
+                ndcopy::copy3(
+                    extent.into(),
+                    source,
+                    source_shape,
+                    source_min.into(),
+                    destination,
+                    shape,
+                    destination_min.into(),
+                );
+                return;
+            }
+            (Voxels::Wide(Store::Dense(destination)), Voxels::Wide(Store::Dense(source))) => {
+                ndcopy::copy3(
+                    extent.into(),
+                    source,
+                    source_shape,
+                    source_min.into(),
+                    destination,
+                    shape,
+                    destination_min.into(),
+                );
+                return;
+            }
+            _ => {}
+        }
+        let is_sparse = self.storage() == VoxelStorage::Sparse;
+        for z in 0..extent.z {
+            for y in 0..extent.y {
+                for x in 0..extent.x {
+                    let offset = UVec3::new(x, y, z);
+                    let voxel =
+                        source.at(source_shape.linearize((source_min + offset).into()) as usize);
+                    if voxel == RawVoxel::EMPTY && is_sparse {
+                        // the voxels of a new sparse model are already empty
+                        continue;
+                    }
+                    let index = shape.linearize((destination_min + offset).into()) as usize;
+                    self.set(index, voxel);
+                }
+            }
+        }
+    }
+}
+
+impl PartialEq for Voxels {
+    /// Voxels are equal if they have the same contents, however they are stored
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Voxels::Narrow(Store::Dense(voxels)), Voxels::Narrow(Store::Dense(other))) => {
+                voxels == other
+            }
+            _ => self.len() == other.len() && self.iter().eq(other.iter()),
+        }
+    }
+}
+
+/// A palette index as it is stored in a model
+pub(crate) trait VoxelIndex: Copy + Eq + Debug {
+    /// The value stored for an empty space
+    const EMPTY: Self;
+
+    fn to_raw(self) -> RawVoxel;
+
+    /// Converts the voxel, or returns `None` if it can't be stored in this type
+    fn from_raw(voxel: RawVoxel) -> Option<Self>;
+}
+
+impl VoxelIndex for u8 {
+    const EMPTY: Self = u8::MAX;
+
+    fn to_raw(self) -> RawVoxel {
+        match self {
+            u8::MAX => RawVoxel::EMPTY,
+            index => RawVoxel(index as u16),
+        }
+    }
+
+    fn from_raw(voxel: RawVoxel) -> Option<Self> {
+        match voxel {
+            RawVoxel::EMPTY => Some(u8::EMPTY),
+            RawVoxel(index) => u8::try_from(index).ok().filter(|index| *index != u8::EMPTY),
+        }
+    }
+}
+
+impl VoxelIndex for u16 {
+    const EMPTY: Self = RawVoxel::EMPTY.0;
+
+    fn to_raw(self) -> RawVoxel {
+        RawVoxel(self)
+    }
+
+    fn from_raw(voxel: RawVoxel) -> Option<Self> {
+        Some(voxel.0)
+    }
+}
+
+/// Voxels of one width, stored either densely or sparsely
+#[derive(Clone, Debug)]
+pub(crate) enum Store<T: VoxelIndex> {
+    Dense(Vec<T>),
+    Sparse(BrickMap<T>),
+}
+
+impl<T: VoxelIndex> Store<T> {
+    fn new(shape: &RuntimeShape<u32, 3>, storage: VoxelStorage) -> Self {
+        match storage {
+            VoxelStorage::Dense => Store::Dense(vec![T::EMPTY; shape.size() as usize]),
+            VoxelStorage::Sparse => Store::Sparse(BrickMap::new(shape.clone())),
+        }
+    }
+
+    fn storage(&self) -> VoxelStorage {
+        match self {
+            Store::Dense(_) => VoxelStorage::Dense,
+            Store::Sparse(_) => VoxelStorage::Sparse,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Store::Dense(voxels) => voxels.len(),
+            Store::Sparse(bricks) => bricks.shape.size() as usize,
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<T> {
+        match self {
+            Store::Dense(voxels) => voxels.get(index).copied(),
+            Store::Sparse(bricks) => bricks.get(index),
+        }
+    }
+
+    fn filled(&self) -> Box<dyn Iterator<Item = (usize, T)> + '_> {
+        match self {
+            Store::Dense(voxels) => Box::new(
+                voxels
+                    .iter()
+                    .copied()
+                    .enumerate()
+                    .filter(|(_, voxel)| *voxel != T::EMPTY),
+            ),
+            Store::Sparse(bricks) => Box::new(bricks.filled()),
+        }
+    }
+
+    fn set(&mut self, index: usize, voxel: T) {
+        match self {
+            Store::Dense(voxels) => voxels[index] = voxel,
+            Store::Sparse(bricks) => bricks.set(index, voxel),
+        }
+    }
+
+    fn map<U: VoxelIndex>(&self, convert: impl Fn(T) -> U) -> Store<U> {
+        match self {
+            Store::Dense(voxels) => {
+                Store::Dense(voxels.iter().map(|voxel| convert(*voxel)).collect())
+            }
+            Store::Sparse(bricks) => Store::Sparse(BrickMap {
+                shape: bricks.shape.clone(),
+                grid: bricks.grid.clone(),
+                bricks: bricks
+                    .bricks
+                    .iter()
+                    .map(|brick| {
+                        brick.as_ref().map(|brick| Brick {
+                            voxels: Box::new(brick.voxels.map(&convert)),
+                            filled: brick.filled,
+                        })
+                    })
+                    .collect(),
+            }),
+        }
+    }
+
+    fn memory_usage(&self) -> usize {
+        match self {
+            Store::Dense(voxels) => voxels.len() * size_of::<T>(),
+            Store::Sparse(bricks) => {
+                bricks.bricks.len() * size_of::<Option<Brick<T>>>()
+                    + bricks.bricks.iter().flatten().count() * BRICK_VOLUME * size_of::<T>()
+            }
+        }
+    }
+}
+
+/// Voxels divided into bricks, where only the bricks containing a voxel are allocated
+#[derive(Clone)]
+pub(crate) struct BrickMap<T: VoxelIndex> {
+    shape: RuntimeShape<u32, 3>,
+    /// The shape of the grid of bricks covering the model
+    grid: RuntimeShape<u32, 3>,
+    bricks: Vec<Option<Brick<T>>>,
+}
+
+#[derive(Clone, Debug)]
+struct Brick<T: VoxelIndex> {
+    voxels: Box<[T; BRICK_VOLUME]>,
+    /// The number of voxels in the brick that aren't empty, so that it can be freed when there are none left
+    filled: u16,
+}
+
+impl<T: VoxelIndex> Debug for BrickMap<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BrickMap")
+            .field("shape", &self.shape.as_array())
+            .field("bricks", &self.bricks.len())
+            .field("allocated", &self.bricks.iter().flatten().count())
+            .finish()
+    }
+}
+
+impl<T: VoxelIndex> BrickMap<T> {
+    fn new(shape: RuntimeShape<u32, 3>) -> Self {
+        let size = UVec3::from(shape.as_array());
+        let grid = RuntimeShape::<u32, 3>::new(
+            ((size + UVec3::splat(BRICK_SIZE - 1)) / BRICK_SIZE).into(),
+        );
+        Self {
+            bricks: vec![None; grid.size() as usize],
+            shape,
+            grid,
+        }
+    }
+
+    /// The index of the brick containing the voxel, and of the voxel within the brick
+    fn locate(&self, index: usize) -> Option<(usize, usize)> {
+        if index >= self.shape.size() as usize {
+            return None;
+        }
+        let point = UVec3::from(self.shape.delinearize(index as u32));
+        let brick = self.grid.linearize((point / BRICK_SIZE).into()) as usize;
+        Some((brick, brick_offset(point % BRICK_SIZE)))
+    }
+
+    /// Every voxel, in the order of their indices
+    fn iter(&self) -> impl Iterator<Item = T> {
+        let [width, height, depth] = self.shape.as_array();
+        (0..depth).flat_map(move |z| {
+            (0..height).flat_map(move |y| {
+                (0..width).map(move |x| {
+                    let point = UVec3::new(x, y, z);
+                    let brick = self.grid.linearize((point / BRICK_SIZE).into()) as usize;
+                    self.bricks[brick].as_ref().map_or(T::EMPTY, |brick| {
+                        brick.voxels[brick_offset(point % BRICK_SIZE)]
+                    })
+                })
+            })
+        })
+    }
+
+    /// The voxels that aren't empty, with their indices, skipping the empty bricks
+    fn filled(&self) -> impl Iterator<Item = (usize, T)> {
+        self.bricks
+            .iter()
+            .enumerate()
+            .filter_map(|(index, brick)| Some((self.brick_origin(index), brick.as_ref()?)))
+            .flat_map(move |(origin, brick)| {
+                brick
+                    .voxels
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, voxel)| **voxel != T::EMPTY)
+                    .map(move |(offset, voxel)| {
+                        let offset = offset as u32;
+                        let local = UVec3::new(
+                            offset % BRICK_SIZE,
+                            offset / BRICK_SIZE % BRICK_SIZE,
+                            offset / (BRICK_SIZE * BRICK_SIZE),
+                        );
+                        let index = self.shape.linearize((origin + local).into()) as usize;
+                        (index, *voxel)
+                    })
+            })
+    }
+
+    fn filled_bricks(&self) -> Vec<UVec3> {
+        self.bricks
+            .iter()
+            .enumerate()
+            .filter(|(_, brick)| brick.is_some())
+            .map(|(index, _)| self.brick_origin(index))
+            .collect()
+    }
+
+    /// The position of the first voxel of the brick
+    fn brick_origin(&self, brick: usize) -> UVec3 {
+        UVec3::from(self.grid.delinearize(brick as u32)) * BRICK_SIZE
+    }
+
+    fn get(&self, index: usize) -> Option<T> {
+        let (brick, offset) = self.locate(index)?;
+        match &self.bricks[brick] {
+            Some(brick) => Some(brick.voxels[offset]),
+            None => Some(T::EMPTY),
+        }
+    }
+
+    fn set(&mut self, index: usize, voxel: T) {
+        let (brick_index, offset) = self.locate(index).expect("Voxel index is within the model");
+        let is_empty = voxel == T::EMPTY;
+        let brick = match &mut self.bricks[brick_index] {
+            Some(brick) => brick,
+            None if is_empty => return,
+            slot => slot.insert(Brick {
+                voxels: Box::new([T::EMPTY; BRICK_VOLUME]),
+                filled: 0,
+            }),
+        };
+        let was_empty = brick.voxels[offset] == T::EMPTY;
+        brick.voxels[offset] = voxel;
+        match (was_empty, is_empty) {
+            (true, false) => brick.filled += 1,
+            (false, true) => brick.filled -= 1,
+            _ => {}
+        }
+        if brick.filled == 0 {
+            self.bricks[brick_index] = None;
+        }
+    }
+}
+
+/// The index of a voxel within its brick, from its position in the brick
+fn brick_offset(local: UVec3) -> usize {
+    (local.x + (local.y + local.z * BRICK_SIZE) * BRICK_SIZE) as usize
+}
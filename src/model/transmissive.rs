@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+
+use bevy::{
+    asset::{Assets, Handle},
+    ecs::{bundle::Bundle, component::Component, reflect::ReflectComponent, world::EntityWorldMut},
+    pbr::{MeshMaterial3d, StandardMaterial},
+    reflect::Reflect,
+    render::mesh::{Mesh, Mesh3d},
+    transform::components::Transform,
+};
+use block_mesh::VoxelVisibility;
+
+use super::{
+    RawVoxel, VoxelContext, VoxelData, VoxelQueryable, mesh::mesh_translucent_faces,
+    voxel::VisibleVoxel,
+};
+
+impl VoxelData {
+    /// If [`crate::VoxLoaderSettings::ior_bucket_size`] is set, creates a separate mesh of the transmissive voxels
+    /// for each group of similar indices of refraction, along with the average index of refraction of the group.
+    pub(crate) fn transmissive_meshes(
+        &self,
+        ior_for_voxel: &[Option<f32>],
+        density_for_voxel: &[Option<f32>],
+    ) -> Vec<(f32, Mesh)> {
+        let Some(bucket_size) = self.settings.ior_bucket_size else {
+            return Vec::new();
+        };
+        let mut buckets: BTreeMap<i64, (f32, usize)> = BTreeMap::new();
+        for (_, voxel) in self.voxels.filled() {
+            if let Some(ior) = voxel.lookup(ior_for_voxel) {
+                let (total, count) = buckets.entry(ior_bucket(ior, bucket_size)).or_default();
+                *total += ior;
+                *count += 1;
+            }
+        }
+        buckets
+            .into_iter()
+            .map(|(bucket, (total, count))| {
+                let voxels: Vec<VisibleVoxel> = self
+                    .voxels
+                    .iter()
+                    .map(|v| VisibleVoxel {
+                        index: v.0,
+                        visibility: if v == RawVoxel::EMPTY {
+                            VoxelVisibility::Empty
+                        } else if let Some(ior) = v.lookup(ior_for_voxel) {
+                            // the faces between different transmissive media are meshed by both of them
+                            if ior_bucket(ior, bucket_size) == bucket {
+                                VoxelVisibility::Translucent
+                            } else {
+                                VoxelVisibility::Empty
+                            }
+                        } else if v.lookup(density_for_voxel).is_some() {
+                            VoxelVisibility::Empty
+                        } else {
+                            VoxelVisibility::Opaque
+                        },
+                        ambient_occlusion: 0,
+                    })
+                    .collect();
+                (
+                    total / count as f32,
+                    mesh_translucent_faces(&voxels, self, ior_for_voxel.len()),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Indices of refraction in the same bucket share a mesh. A bucket size of 0 gives each distinct index its own bucket.
+fn ior_bucket(ior: f32, bucket_size: f32) -> i64 {
+    if bucket_size > 0.0 {
+        (ior / bucket_size).floor() as i64
+    } else {
+        ior.to_bits() as i64
+    }
+}
+
+/// Marks the children of a model instance that hold its transmissive meshes, so that they can be replaced when the
+/// model is modified
+#[derive(Component, Reflect, Clone, Copy, Debug, Default)]
+#[reflect(Component)]
+pub(crate) struct TransmissiveMesh;
+
+/// Adds the model's transmissive meshes to the asset collections, each with a material using its index of refraction
+pub(crate) fn add_transmissive_meshes(
+    data: &VoxelData,
+    context: &VoxelContext,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) -> Vec<(Handle<Mesh>, Handle<StandardMaterial>)> {
+    let transmissive_meshes = data.transmissive_meshes(
+        &context.palette.indices_of_refraction,
+        &context.palette.density_for_voxel,
+    );
+    add_transmissive_assets(
+        transmissive_meshes,
+        data.size().min_element() as f32,
+        context,
+        meshes,
+        materials,
+    )
+}
+
+/// Adds meshes made by [`VoxelData::transmissive_meshes`] to the asset collections, each with a copy of the context's
+/// transmissive material using its index of refraction
+pub(crate) fn add_transmissive_assets(
+    transmissive_meshes: Vec<(f32, Mesh)>,
+    thickness: f32,
+    context: &VoxelContext,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) -> Vec<(Handle<Mesh>, Handle<StandardMaterial>)> {
+    transmissive_meshes
+        .into_iter()
+        .map(|(ior, mesh)| {
+            let mut transmissive_material = materials
+                .get(context.transmissive_material.id())
+                .expect("Transmissive material exists")
+                .clone();
+            transmissive_material.ior = ior;
+            transmissive_material.thickness = thickness;
+            (meshes.add(mesh), materials.add(transmissive_material))
+        })
+        .collect()
+}
+
+/// The components of the child that holds one of the model's transmissive meshes
+pub(crate) fn transmissive_mesh_bundle(
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+) -> impl Bundle {
+    (
+        Mesh3d(mesh),
+        MeshMaterial3d(material),
+        Transform::IDENTITY,
+        TransmissiveMesh,
+    )
+}
+
+/// Spawns a child of the entity for each of the model's transmissive meshes, so that each can have a material with
+/// its own index of refraction.
+pub(crate) fn insert_transmissive_meshes(
+    entity: &mut EntityWorldMut,
+    transmissive_meshes: Vec<(Handle<Mesh>, Handle<StandardMaterial>)>,
+) {
+    if transmissive_meshes.is_empty() {
+        return;
+    }
+    entity.with_children(|spawner| {
+        for (mesh, material) in transmissive_meshes {
+            spawner.spawn(transmissive_mesh_bundle(mesh, material));
+        }
+    });
+}
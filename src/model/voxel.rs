@@ -1,39 +1,51 @@
 use block_mesh::{MergeVoxel, Voxel as BlockyVoxel, VoxelVisibility};
 
 /// A Voxel. The value is its index in the Magica Voxel palette (1-255), with 0 reserved for [`Voxel::EMPTY`].
+/// Palettes with more than 255 elements can be indexed up to 65535. Voxels with an index beyond the end of their
+/// palette are meshed as opaque, but have no element to take their color from.
 #[derive(Clone, PartialEq, Debug)]
-pub struct Voxel(pub u8);
+pub struct Voxel(pub u16);
 
 impl Voxel {
     /// The value reserved for an empty space.
     pub const EMPTY: Voxel = Voxel(0);
 }
 
-/// A Voxel. Material indices run 0-254, with 255 reserved for [`RawVoxel::EMPTY`].
-#[derive(Clone, PartialEq, Debug)]
-pub(crate) struct RawVoxel(pub u8);
+/// A Voxel. Material indices run 0-65534, with 65535 reserved for [`RawVoxel::EMPTY`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct RawVoxel(pub u16);
 
 impl RawVoxel {
     /// The value reserved for an empty space.
-    pub const EMPTY: RawVoxel = RawVoxel(255);
+    pub const EMPTY: RawVoxel = RawVoxel(u16::MAX);
+
+    /// The entry for this voxel in a table of per-element palette properties, such as the indices of refraction.
+    /// `None` for empty voxels and voxels outside of the palette, which are meshed as opaque.
+    pub(crate) fn lookup<T: Copy>(self, table: &[Option<T>]) -> Option<T> {
+        table.get(self.0 as usize).copied().flatten()
+    }
 }
 
 impl From<Voxel> for RawVoxel {
     fn from(value: Voxel) -> Self {
-        Self(((value.0 as i16 - 1) % 256_i16) as u8)
+        Self(value.0.wrapping_sub(1))
     }
 }
 
 impl From<RawVoxel> for Voxel {
     fn from(value: RawVoxel) -> Voxel {
-        Voxel(((value.0 as i16 + 1) % 256_i16) as u8)
+        Voxel(value.0.wrapping_add(1))
     }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub(crate) struct VisibleVoxel {
-    pub index: u8,
+    pub index: u16,
     pub visibility: VoxelVisibility,
+    /// The ambient occlusion of the four corners of the face being meshed, packed into 2 bits per corner, so that
+    /// faces are only merged if their occlusion matches. Zero unless [`crate::VoxLoaderSettings::ambient_occlusion`]
+    /// is set.
+    pub ambient_occlusion: u8,
 }
 
 impl BlockyVoxel for VisibleVoxel {
@@ -4,6 +4,11 @@ use bevy::{
     scene::SceneInstanceReady,
 };
 
+#[cfg(feature = "generate_voxels")]
+use bevy::prelude::OnAdd;
+
+#[cfg(feature = "generate_voxels")]
+use crate::VoxelModelRestored;
 use crate::{VoxelLayer, VoxelModelInstance};
 
 /// An Event triggered once for each [`VoxelModelInstance`] in a scene, triggered after the scene is spawned and ready,
@@ -107,3 +112,23 @@ fn seek_model_instance_recursive(
         }
     }
 }
+
+/// Marks instances of models loaded from `.voxm` files with [`VoxelModelRestored`], as they're spawned without meshes
+#[cfg(feature = "generate_voxels")]
+pub(crate) fn on_add_model_instance(
+    trigger: Trigger<OnAdd, VoxelModelInstance>,
+    query: Query<&VoxelModelInstance>,
+    mut commands: Commands,
+) {
+    let Ok(instance) = query.get(trigger.target()) else {
+        return;
+    };
+    let is_voxm = instance.model.path().is_some_and(|path| {
+        path.path()
+            .extension()
+            .is_some_and(|extension| extension == "voxm")
+    });
+    if is_voxm {
+        commands.entity(trigger.target()).insert(VoxelModelRestored);
+    }
+}
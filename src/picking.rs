@@ -0,0 +1,187 @@
+use bevy::{
+    app::{App, Plugin, PreUpdate},
+    asset::Assets,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        event::EventWriter,
+        query::Has,
+        schedule::IntoScheduleConfigs,
+        system::{Commands, Query, Res},
+    },
+    math::{IVec3, Vec3, Vec3A},
+    picking::{
+        PickSet, Pickable,
+        backend::{HitData, PointerHits, ray::RayMap},
+        pointer::PointerId,
+    },
+    platform::collections::HashMap,
+    render::{
+        camera::Camera,
+        view::{InheritedVisibility, RenderLayers},
+    },
+    transform::components::GlobalTransform,
+};
+
+use crate::{
+    VoxelContext, VoxelElement, VoxelModel, VoxelModelInstance,
+    model::queryable::{VoxelQueryable, VoxelRayHit},
+};
+
+/// Plugin adding a [`bevy::picking`] backend that raycasts the voxels of each [`VoxelModelInstance`] directly,
+/// instead of its mesh.
+///
+/// The [`HitData`] of each hit reports the point where the ray entered the voxel, and the normal of the face it
+/// entered through, in global space. The exact voxel under each pointer is recorded in the [`VoxelPointerHits`] of
+/// the instance, so that observers of pointer events such as [`bevy::picking::events::Pointer<Click>`] can find out
+/// which voxel they were triggered by.
+///
+/// Instances with [`Pickable::is_hoverable`] set to false are ignored, as are hidden instances, and instances on
+/// [`RenderLayers`] that the camera does not render. Because this backend and the
+/// [`bevy::picking::mesh_picking::MeshPickingPlugin`] would both report hits on voxel models, add only one of them,
+/// or set [`bevy::picking::mesh_picking::MeshPickingSettings::require_markers`].
+///
+/// ### Example
+/// ```no_run
+/// # use bevy::prelude::*;
+/// # use bevy_vox_scene::{VoxScenePlugin, VoxelPickingPlugin, VoxelPointerHits};
+/// App::new()
+///     .add_plugins((
+///         DefaultPlugins,
+///         VoxScenePlugin::default(),
+///         VoxelPickingPlugin,
+///     ))
+///     .add_observer(on_click);
+///
+/// fn on_click(trigger: Trigger<Pointer<Click>>, hits: Query<&VoxelPointerHits>) {
+///     let Some(pick) = hits
+///         .get(trigger.target())
+///         .ok()
+///         .and_then(|hits| hits.get(&trigger.pointer_id))
+///     else {
+///         return;
+///     };
+///     info!("Clicked voxel {} with color {:?}", pick.hit.voxel_coord, pick.element.color);
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct VoxelPickingPlugin;
+
+impl Plugin for VoxelPickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PreUpdate, update_voxel_hits.in_set(PickSet::Backend));
+    }
+}
+
+/// A voxel under a pointer, found by the [`VoxelPickingPlugin`]
+#[derive(Debug, Clone)]
+pub struct VoxelPick {
+    /// The voxel that the pointer's ray hit, including its coordinate and the normal of the face it hit, in voxel space
+    pub hit: VoxelRayHit,
+    /// The palette element of the voxel
+    pub element: VoxelElement,
+}
+
+/// The voxels of a [`VoxelModelInstance`] under each of the pointers hovering over it, updated every frame by the
+/// [`VoxelPickingPlugin`]
+#[derive(Component, Debug, Clone, Default)]
+pub struct VoxelPointerHits(HashMap<PointerId, VoxelPick>);
+
+impl VoxelPointerHits {
+    /// The voxel under the pointer, if it is over the instance
+    pub fn get(&self, pointer: &PointerId) -> Option<&VoxelPick> {
+        self.0.get(pointer)
+    }
+
+    /// Iterates over the pointers hovering over the instance, and the voxel under each of them
+    pub fn iter(&self) -> impl Iterator<Item = (&PointerId, &VoxelPick)> {
+        self.0.iter()
+    }
+}
+
+/// Casts the ray of each pointer through the voxel model instances, and sends [`PointerHits`] events
+#[allow(clippy::too_many_arguments)]
+fn update_voxel_hits(
+    mut commands: Commands,
+    ray_map: Res<RayMap>,
+    cameras: Query<(&Camera, Option<&RenderLayers>)>,
+    instances: Query<(
+        Entity,
+        &VoxelModelInstance,
+        &GlobalTransform,
+        &InheritedVisibility,
+        Option<&RenderLayers>,
+        Option<&Pickable>,
+        Has<VoxelPointerHits>,
+    )>,
+    mut pointer_hits: Query<&mut VoxelPointerHits>,
+    models: Res<Assets<VoxelModel>>,
+    contexts: Res<Assets<VoxelContext>>,
+    mut output: EventWriter<PointerHits>,
+) {
+    let mut picks_by_entity: HashMap<Entity, HashMap<PointerId, VoxelPick>> = HashMap::default();
+    for (&ray_id, &ray) in ray_map.iter() {
+        let Ok((camera, camera_layers)) = cameras.get(ray_id.camera) else {
+            continue;
+        };
+        let camera_layers = camera_layers.cloned().unwrap_or_default();
+        let mut picks: Vec<(Entity, HitData)> = Vec::new();
+        for (entity, instance, global_xform, visibility, layers, pickable, _) in instances.iter() {
+            if !visibility.get()
+                || !camera_layers.intersects(&layers.cloned().unwrap_or_default())
+                || pickable.is_some_and(|pickable| !pickable.is_hoverable)
+            {
+                continue;
+            }
+            let (Some(model), Some(context)) =
+                (models.get(&instance.model), contexts.get(&instance.context))
+            else {
+                continue;
+            };
+            let Some(hit) = model.raycast(ray, global_xform) else {
+                continue;
+            };
+            let normal = (hit.normal != IVec3::ZERO).then(|| {
+                // normals are transformed by the inverse transpose, to stay perpendicular under non-uniform scale
+                let normal_matrix = global_xform.affine().matrix3.inverse().transpose();
+                Vec3::from(normal_matrix * Vec3A::from(hit.normal.as_vec3())).normalize()
+            });
+            picks.push((
+                entity,
+                HitData::new(
+                    ray_id.camera,
+                    hit.distance,
+                    Some(ray.get_point(hit.distance)),
+                    normal,
+                ),
+            ));
+            let element = context
+                .palette
+                .element(&hit.voxel)
+                .cloned()
+                .unwrap_or_default();
+            picks_by_entity
+                .entry(entity)
+                .or_default()
+                .insert(ray_id.pointer, VoxelPick { hit, element });
+        }
+        if !picks.is_empty() {
+            picks.sort_by(|a, b| a.1.depth.total_cmp(&b.1.depth));
+            output.write(PointerHits::new(ray_id.pointer, picks, camera.order as f32));
+        }
+    }
+    for (entity, .., has_pointer_hits) in instances.iter() {
+        let picks = picks_by_entity.remove(&entity).unwrap_or_default();
+        if has_pointer_hits {
+            let Ok(mut pointer_hits) = pointer_hits.get_mut(entity) else {
+                continue;
+            };
+            // avoid triggering change detection on instances that nothing is hovering over
+            if !(picks.is_empty() && pointer_hits.0.is_empty()) {
+                pointer_hits.0 = picks;
+            }
+        } else if !picks.is_empty() {
+            commands.entity(entity).insert(VoxelPointerHits(picks));
+        }
+    }
+}
@@ -0,0 +1,274 @@
+mod voxm;
+mod write_model;
+mod write_palette;
+mod write_scene;
+
+use std::io::{self, Write};
+
+use bevy::{
+    asset::{
+        Asset, AssetId, Assets, AsyncWriteExt, Handle,
+        io::Writer,
+        saver::{AssetSaver, SavedAsset},
+    },
+    ecs::{
+        entity::Entity,
+        hierarchy::Children,
+        name::Name,
+        query::QueryData,
+        system::{In, Query, Res},
+    },
+    log::warn,
+    math::UVec3,
+    platform::collections::HashMap,
+    reflect::TypePath,
+    render::view::Visibility,
+    transform::components::Transform,
+};
+use dot_vox::DotVoxData;
+use thiserror::Error;
+pub use voxm::{VoxelModelLoader, VoxelModelLoaderError, VoxelModelRestored, VoxelModelSaver};
+use write_scene::{write_layers, write_scene_graph};
+
+use crate::{
+    VoxLoaderSettings, VoxSceneLoader, VoxelAnimationPlayer, VoxelContext, VoxelData, VoxelLayer,
+    VoxelModel, VoxelModelInstance, VoxelPalette, load::VoxelAnimationFrame,
+};
+
+/// The version of the `.vox` format that is written
+const VOX_VERSION: u32 = 150;
+
+/// Voxel models and the palette they use, along with the scene graph arranging them, that can be written to a `.vox` file.
+///
+/// It converts bevy's right-handed Y-up space back to Magica Voxel's left-handed Z-up space, so that a file saved from a
+/// loaded scene can be loaded again. Create one from a spawned voxel scene with [`create_vox_file`], or from some voxel
+/// data with [`VoxFile::new`]. Write it with [`VoxFile::write`], or with the [`VoxSaver`] asset saver.
+#[derive(Asset, TypePath, Clone, Debug)]
+pub struct VoxFile {
+    /// The palette used by all of the models
+    pub palette: VoxelPalette,
+    /// The voxel data for each model
+    pub models: Vec<VoxelData>,
+    /// The nodes at the top of the scene graph
+    pub nodes: Vec<VoxFileNode>,
+    /// The settings the models were loaded or generated with, used to convert the voxel size and emission strength
+    /// back into Magica Voxel's units
+    pub settings: VoxLoaderSettings,
+}
+
+/// A node in the scene graph of a [`VoxFile`]
+#[derive(Clone, Debug, Default)]
+pub struct VoxFileNode {
+    /// The name of the node. Unlike the names of loaded entities, it doesn't include the names of its ancestors.
+    pub name: Option<String>,
+    /// The transform of the node relative to its parent. Magica Voxel only supports whole-voxel translations,
+    /// and rotations and reflections that are aligned to the axes.
+    pub transform: Transform,
+    /// Whether the node is hidden
+    pub is_hidden: bool,
+    /// The layer the node is assigned to
+    pub layer: Option<VoxelLayer>,
+    /// Indices of the models in [`VoxFile::models`] that this node is an instance of. If there is more than one,
+    /// the models are the frames of a voxel animation.
+    pub models: Vec<usize>,
+    /// For a voxel animation, the index in [`VoxFileNode::models`] of the model shown on each frame, as in
+    /// [`VoxelAnimationPlayer::frames`]. If empty, each model is shown for one frame in turn.
+    pub frames: Vec<usize>,
+    /// The child nodes
+    pub children: Vec<VoxFileNode>,
+}
+
+impl VoxFile {
+    /// Create a file containing a single model, placed at the origin
+    pub fn new(data: VoxelData, palette: VoxelPalette) -> Self {
+        Self {
+            palette,
+            settings: data.settings.clone(),
+            models: vec![data],
+            nodes: vec![VoxFileNode {
+                models: vec![0],
+                ..Default::default()
+            }],
+        }
+    }
+
+    /// Writes the file in the `.vox` format. Fails if a model is larger than the 256 voxels along each axis that
+    /// Magica Voxel supports.
+    pub fn write(&self, writer: &mut impl Write) -> Result<(), VoxSaverError> {
+        let (palette, materials) = self.palette.to_data(&self.settings);
+        DotVoxData {
+            version: VOX_VERSION,
+            index_map: Vec::new(),
+            models: self
+                .models
+                .iter()
+                .map(VoxelData::to_model)
+                .collect::<Result<_, _>>()?,
+            palette,
+            materials,
+            scenes: write_scene_graph(&self.nodes, self.settings.voxel_size),
+            layers: write_layers(&self.nodes),
+        }
+        .write_vox(writer)?;
+        Ok(())
+    }
+}
+
+/// An [`AssetSaver`] that writes a [`VoxFile`] in the `.vox` format, which can then be loaded as a [`bevy::scene::Scene`]
+pub struct VoxSaver;
+
+/// Errors encountered when saving a [`VoxFile`] or a [`VoxelModel`]
+#[derive(Error, Debug)]
+pub enum VoxSaverError {
+    /// An error writing the file
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// A model is larger than the 256 voxels along each axis that a `.vox` file can store
+    #[error(
+        "model of size {0} is too large for a .vox file, which supports up to 256 voxels along each axis"
+    )]
+    ModelTooLarge(UVec3),
+}
+
+impl AssetSaver for VoxSaver {
+    type Asset = VoxFile;
+    type Settings = ();
+    type OutputLoader = VoxSceneLoader;
+    type Error = VoxSaverError;
+
+    async fn save(
+        &self,
+        writer: &mut Writer,
+        asset: SavedAsset<'_, Self::Asset>,
+        _settings: &Self::Settings,
+    ) -> Result<VoxLoaderSettings, Self::Error> {
+        let mut bytes = Vec::new();
+        asset.write(&mut bytes)?;
+        writer.write_all(&bytes).await?;
+        Ok(asset.settings.clone())
+    }
+}
+
+/// The components of a node in a spawned voxel scene
+#[derive(QueryData)]
+pub struct VoxNode {
+    name: Option<&'static Name>,
+    transform: &'static Transform,
+    visibility: Option<&'static Visibility>,
+    layer: Option<&'static VoxelLayer>,
+    instance: Option<&'static VoxelModelInstance>,
+    animation: Option<&'static VoxelAnimationPlayer>,
+    frame: Option<&'static VoxelAnimationFrame>,
+    children: Option<&'static Children>,
+}
+
+/// Create a [`VoxFile`] from a spawned voxel scene, such as the root of a loaded `.vox` file, or a scene created with
+/// [`crate::create_voxel_scene`]. The input entity becomes the top node of the file's scene graph, and its descendants
+/// that contain [`VoxelModelInstance`]s become its children. Returns `None` if there are no model instances, or their
+/// assets are not loaded.
+pub fn create_vox_file(
+    In(root): In<Entity>,
+    nodes: Query<VoxNode>,
+    models: Res<Assets<VoxelModel>>,
+    contexts: Res<Assets<VoxelContext>>,
+) -> Option<VoxFile> {
+    let mut collector = ModelCollector {
+        models: &models,
+        indices: HashMap::default(),
+        data: Vec::new(),
+        context: None,
+    };
+    let mut node = collector.collect_node(root, None, &nodes)?;
+    // the transform of the root node is ignored by the loader
+    node.transform = Transform::IDENTITY;
+    let context = contexts.get(collector.context?.id())?;
+    Some(VoxFile {
+        palette: context.palette.clone(),
+        settings: collector.data.first()?.settings.clone(),
+        models: collector.data,
+        nodes: vec![node],
+    })
+}
+
+struct ModelCollector<'a> {
+    models: &'a Assets<VoxelModel>,
+    indices: HashMap<AssetId<VoxelModel>, usize>,
+    data: Vec<VoxelData>,
+    context: Option<Handle<VoxelContext>>,
+}
+
+impl ModelCollector<'_> {
+    /// Returns the node for this entity, or `None` if neither it nor its descendants contain a model
+    fn collect_node(
+        &mut self,
+        entity: Entity,
+        parent_name: Option<&str>,
+        nodes: &Query<VoxNode>,
+    ) -> Option<VoxFileNode> {
+        let node = nodes.get(entity).ok()?;
+        let full_name = node.name.map(|name| name.as_str());
+        // the loader prefixes the names of entities with the names of their ancestors
+        let name = full_name.map(|name| {
+            parent_name
+                .and_then(|parent| name.strip_prefix(parent)?.strip_prefix('/'))
+                .unwrap_or(name)
+                .to_string()
+        });
+        let mut file_node = VoxFileNode {
+            name,
+            transform: *node.transform,
+            is_hidden: node.visibility == Some(&Visibility::Hidden),
+            layer: node.layer.cloned(),
+            ..Default::default()
+        };
+        if let Some(instance) = node.instance {
+            file_node.models.push(self.add_instance(instance)?);
+        }
+        let children: Vec<Entity> = node
+            .children
+            .map(|children| children.iter().copied().collect())
+            .unwrap_or_default();
+        if let Some(animation) = node.animation {
+            let mut frames: Vec<(usize, usize)> = children
+                .iter()
+                .filter_map(|child| {
+                    let frame = nodes.get(*child).ok()?;
+                    Some((frame.frame?.0, self.add_instance(frame.instance?)?))
+                })
+                .collect();
+            frames.sort_by_key(|(index, _)| *index);
+            file_node.models = frames.into_iter().map(|(_, model)| model).collect();
+            file_node.frames = animation.frames.clone();
+        }
+        file_node.children = children
+            .into_iter()
+            .filter(|child| nodes.get(*child).is_ok_and(|child| child.frame.is_none()))
+            .filter_map(|child| self.collect_node(child, full_name.or(parent_name), nodes))
+            .collect();
+        if file_node.models.is_empty() && file_node.children.is_empty() {
+            return None;
+        }
+        Some(file_node)
+    }
+
+    /// Returns the index of the instance's model in the file
+    fn add_instance(&mut self, instance: &VoxelModelInstance) -> Option<usize> {
+        match &self.context {
+            Some(context) if *context != instance.context => {
+                warn!(
+                    "Voxel models using different contexts are saved with the palette of the first model"
+                );
+            }
+            Some(_) => {}
+            None => self.context = Some(instance.context.clone()),
+        }
+        if let Some(index) = self.indices.get(&instance.model.id()) {
+            return Some(*index);
+        }
+        let model = self.models.get(instance.model.id())?;
+        self.data.push(model.data.clone());
+        let index = self.data.len() - 1;
+        self.indices.insert(instance.model.id(), index);
+        Some(index)
+    }
+}
@@ -0,0 +1,420 @@
+use std::io::{self, Write};
+
+use bevy::{
+    asset::{
+        AssetLoader, AsyncWriteExt, LoadContext,
+        io::{Reader, Writer},
+        saver::{AssetSaver, SavedAsset},
+    },
+    ecs::{component::Component, reflect::ReflectComponent},
+    math::{UVec3, Vec3},
+    reflect::Reflect,
+};
+use ndshape::Shape;
+use thiserror::Error;
+
+use crate::{
+    UnitOffset, VoxLoaderSettings, Voxel, VoxelColliderSettings, VoxelData, VoxelModel,
+    VoxelStorage, model::RawVoxel,
+};
+
+use super::VoxSaverError;
+
+/// The first bytes of every `.voxm` file
+const VOXM_MAGIC: &[u8; 4] = b"VOXM";
+
+/// The version of the `.voxm` format that is written. Version 1 stored each voxel of a run as a single byte, before
+/// palettes could have more than 256 elements.
+const VOXM_VERSION: u32 = 2;
+
+/// The most voxels that a densely stored model read from a `.voxm` file may have, including its padding, so that a
+/// corrupt size can't allocate gigabytes
+const MAX_DENSE_VOXELS: u64 = 1 << 28;
+
+/// An [`AssetLoader`] that loads a single [`VoxelModel`] from a `.voxm` file, written with [`VoxelModel::write`] or
+/// the [`VoxelModelSaver`].
+///
+/// The file only contains the voxels of the model and the settings used to mesh them, not the palette, so no meshes
+/// are generated when it is loaded. Instead, [`crate::VoxelModelInstance`]s of models loaded from a `.voxm` file are
+/// marked with [`VoxelModelRestored`], and their meshes, materials and cloud are rebuilt from the palette of the
+/// instance's [`crate::VoxelContext`] once both assets have loaded.
+pub struct VoxelModelLoader;
+
+/// Marks a [`crate::VoxelModelInstance`] that was spawned without its meshes, such as an instance in a restored saved
+/// game. Once its model and context have loaded, its meshes, materials and cloud are rebuilt from the palette of the
+/// context, and the marker is removed. If the instance already has a mesh by then, the marker is just removed. The
+/// instances of a model that are rebuilt together share its meshes, so modifying the model updates all of them.
+///
+/// Added automatically to instances of models loaded from a `.voxm` file. Add it yourself to rebuild an instance of
+/// a model created some other way.
+#[derive(Component, Reflect, Clone, Copy, Debug, Default)]
+#[reflect(Component)]
+pub struct VoxelModelRestored;
+
+/// Errors encountered when reading a `.voxm` file
+#[derive(Error, Debug)]
+pub enum VoxelModelLoaderError {
+    /// An error reading the file
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// The file doesn't begin with the `.voxm` header
+    #[error("not a .voxm file")]
+    InvalidHeader,
+    /// The file was written by a newer version of the format
+    #[error("unsupported .voxm version {0}")]
+    UnsupportedVersion(u32),
+    /// The file ends early, or its contents are inconsistent
+    #[error("invalid .voxm data: {0}")]
+    InvalidData(&'static str),
+}
+
+impl AssetLoader for VoxelModelLoader {
+    type Asset = VoxelModel;
+    type Settings = ();
+    type Error = VoxelModelLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        VoxelModel::read(&bytes)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["voxm"]
+    }
+}
+
+/// An [`AssetSaver`] that writes a [`VoxelModel`] in the `.voxm` format, which can then be loaded with the
+/// [`VoxelModelLoader`]
+pub struct VoxelModelSaver;
+
+impl AssetSaver for VoxelModelSaver {
+    type Asset = VoxelModel;
+    type Settings = ();
+    type OutputLoader = VoxelModelLoader;
+    type Error = VoxSaverError;
+
+    async fn save(
+        &self,
+        writer: &mut Writer,
+        asset: SavedAsset<'_, Self::Asset>,
+        _settings: &Self::Settings,
+    ) -> Result<(), Self::Error> {
+        let mut bytes = Vec::new();
+        asset.write(&mut bytes)?;
+        writer.write_all(&bytes).await?;
+        Ok(())
+    }
+}
+
+impl VoxelModel {
+    /// Writes the model in the `.voxm` format: its name, the settings its mesh is generated with, and its voxels,
+    /// run-length encoded
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(VOXM_MAGIC)?;
+        writer.write_all(&VOXM_VERSION.to_le_bytes())?;
+        write_varint(writer, self.name.len() as u32)?;
+        writer.write_all(self.name.as_bytes())?;
+        writer.write_all(&[self.has_mesh as u8, self.has_cloud as u8])?;
+        self.data.write(writer)
+    }
+
+    /// Reads a model written in the `.voxm` format by [`VoxelModel::write`]
+    pub fn read(bytes: &[u8]) -> Result<Self, VoxelModelLoaderError> {
+        let mut bytes = ByteReader(bytes);
+        if bytes.take(VOXM_MAGIC.len())? != VOXM_MAGIC {
+            return Err(VoxelModelLoaderError::InvalidHeader);
+        }
+        let version = bytes.u32()?;
+        if version > VOXM_VERSION {
+            return Err(VoxelModelLoaderError::UnsupportedVersion(version));
+        }
+        let name_length = bytes.varint()? as usize;
+        let name = String::from_utf8(bytes.take(name_length)?.to_vec())
+            .map_err(|_| VoxelModelLoaderError::InvalidData("model name is not valid UTF-8"))?;
+        let has_mesh = bytes.bool()?;
+        let has_cloud = bytes.bool()?;
+        let data = VoxelData::read_from(&mut bytes, version)?;
+        Ok(VoxelModel {
+            name,
+            data,
+            has_mesh,
+            has_cloud,
+        })
+    }
+}
+
+impl VoxelData {
+    /// Writes the size, settings and voxels of the model in the format used by `.voxm` files. The voxels are stored as
+    /// runs of the same voxel, so large empty or solid areas take up only a few bytes.
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let size = self._size().as_uvec3();
+        for axis in size.to_array() {
+            write_varint(writer, axis)?;
+        }
+        write_settings(writer, &self.settings)?;
+        let leading_padding = UVec3::splat(self.padding() / 2);
+        let mut run: Option<(RawVoxel, u32)> = None;
+        for z in 0..size.z {
+            for y in 0..size.y {
+                for x in 0..size.x {
+                    let index = self
+                        .shape
+                        .linearize((UVec3::new(x, y, z) + leading_padding).into());
+                    let voxel = self.voxels.at(index as usize);
+                    match &mut run {
+                        Some((last, count)) if *last == voxel => *count += 1,
+                        _ => {
+                            if let Some((last, count)) = run {
+                                write_run(writer, last, count)?;
+                            }
+                            run = Some((voxel, 1));
+                        }
+                    }
+                }
+            }
+        }
+        if let Some((last, count)) = run {
+            write_run(writer, last, count)?;
+        }
+        Ok(())
+    }
+
+    /// Reads voxel data written by [`VoxelData::write`] in the current version of the format
+    pub fn read(bytes: &[u8]) -> Result<Self, VoxelModelLoaderError> {
+        Self::read_from(&mut ByteReader(bytes), VOXM_VERSION)
+    }
+
+    fn read_from(bytes: &mut ByteReader, version: u32) -> Result<Self, VoxelModelLoaderError> {
+        let size = UVec3::new(bytes.varint()?, bytes.varint()?, bytes.varint()?);
+        let settings = read_settings(bytes)?;
+        if size.cmpeq(UVec3::ZERO).any() {
+            return Err(VoxelModelLoaderError::InvalidData("model size is empty"));
+        }
+        // the size is checked before the voxels are allocated, and must fit the u32 indices of the padded shape
+        let padding = if settings.mesh_outer_faces { 2 } else { 0 };
+        let max_count = match settings.storage {
+            VoxelStorage::Dense => MAX_DENSE_VOXELS,
+            VoxelStorage::Sparse => u32::MAX as u64,
+        };
+        let padded_count = size.to_array().into_iter().try_fold(1u64, |count, axis| {
+            axis.checked_add(padding)
+                .and_then(|axis| count.checked_mul(axis as u64))
+        });
+        if padded_count.is_none_or(|count| count > max_count) {
+            return Err(VoxelModelLoaderError::InvalidData(
+                "model size is too large",
+            ));
+        }
+        let mut data = VoxelData::new(size, settings);
+        let leading_padding = UVec3::splat(data.padding() / 2);
+        let (width, depth) = (size.x as u64, size.y as u64);
+        let total = width * depth * size.z as u64;
+        let mut position: u64 = 0;
+        while position < total {
+            // version 1 wrote the raw index of each voxel as a byte, with 255 for empty voxels
+            let voxel = if version < 2 {
+                match bytes.u8()? {
+                    u8::MAX => RawVoxel::EMPTY,
+                    index => RawVoxel(index as u16),
+                }
+            } else {
+                RawVoxel::from(Voxel(u16::try_from(bytes.varint()?).map_err(|_| {
+                    VoxelModelLoaderError::InvalidData("voxel index is too large")
+                })?))
+            };
+            let count = bytes.varint()? as u64;
+            let Some(end) = position
+                .checked_add(count)
+                .filter(|end| count > 0 && *end <= total)
+            else {
+                return Err(VoxelModelLoaderError::InvalidData(
+                    "voxel runs don't match the model size",
+                ));
+            };
+            for index in position..end {
+                let point = UVec3::new(
+                    (index % width) as u32,
+                    (index / width % depth) as u32,
+                    (index / (width * depth)) as u32,
+                );
+                let index = data.shape.linearize((point + leading_padding).into());
+                data.voxels.set(index as usize, voxel);
+            }
+            position = end;
+        }
+        Ok(data)
+    }
+}
+
+/// Writes a run of the same voxel, with the voxel stored as its palette index, so that empty runs and the first 127
+/// elements take a single byte
+fn write_run(writer: &mut impl Write, voxel: RawVoxel, count: u32) -> io::Result<()> {
+    write_varint(writer, Voxel::from(voxel).0 as u32)?;
+    write_varint(writer, count)
+}
+
+fn write_settings(writer: &mut impl Write, settings: &VoxLoaderSettings) -> io::Result<()> {
+    let mut flags: u8 = 0;
+    for (bit, flag) in [
+        settings.mesh_outer_faces,
+        settings.uses_srgb,
+        settings.supports_remeshing,
+        settings.import_cameras,
+        settings.import_render_settings,
+        settings.ambient_occlusion,
+        settings.storage == VoxelStorage::Sparse,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        flags |= (flag as u8) << bit;
+    }
+    writer.write_all(&[flags])?;
+    for value in [
+        settings.voxel_size,
+        settings.emission_strength,
+        settings.diffuse_roughness,
+        settings.animation_frame_rate,
+    ]
+    .into_iter()
+    .chain(settings.mesh_offset.0.to_array())
+    {
+        writer.write_all(&value.to_le_bytes())?;
+    }
+    write_varint(writer, settings.lod_distances.len() as u32)?;
+    for distance in settings.lod_distances.iter() {
+        writer.write_all(&distance.to_le_bytes())?;
+    }
+    match settings.ior_bucket_size {
+        Some(size) => {
+            writer.write_all(&[1])?;
+            writer.write_all(&size.to_le_bytes())?;
+        }
+        None => writer.write_all(&[0])?,
+    }
+    match &settings.colliders {
+        Some(colliders) => writer.write_all(&[
+            1,
+            colliders.exclude_transmissive as u8,
+            colliders.exclude_cloud as u8,
+        ])?,
+        None => writer.write_all(&[0])?,
+    }
+    match settings.chunk_size {
+        Some(size) => {
+            writer.write_all(&[1])?;
+            write_varint(writer, size)
+        }
+        None => writer.write_all(&[0]),
+    }
+}
+
+fn read_settings(bytes: &mut ByteReader) -> Result<VoxLoaderSettings, VoxelModelLoaderError> {
+    let flags = bytes.u8()?;
+    let flag = |bit: u8| flags & (1 << bit) != 0;
+    let voxel_size = bytes.f32()?;
+    let emission_strength = bytes.f32()?;
+    let diffuse_roughness = bytes.f32()?;
+    let animation_frame_rate = bytes.f32()?;
+    let mesh_offset = UnitOffset(Vec3::new(bytes.f32()?, bytes.f32()?, bytes.f32()?));
+    let lod_distances = (0..bytes.varint()?)
+        .map(|_| bytes.f32())
+        .collect::<Result<Vec<f32>, _>>()?;
+    let ior_bucket_size = match bytes.bool()? {
+        true => Some(bytes.f32()?),
+        false => None,
+    };
+    let colliders = match bytes.bool()? {
+        true => Some(VoxelColliderSettings {
+            exclude_transmissive: bytes.bool()?,
+            exclude_cloud: bytes.bool()?,
+        }),
+        false => None,
+    };
+    let chunk_size = match bytes.bool()? {
+        true => Some(bytes.varint()?),
+        false => None,
+    };
+    Ok(VoxLoaderSettings {
+        voxel_size,
+        mesh_outer_faces: flag(0),
+        mesh_offset,
+        emission_strength,
+        uses_srgb: flag(1),
+        diffuse_roughness,
+        supports_remeshing: flag(2),
+        animation_frame_rate,
+        import_cameras: flag(3),
+        import_render_settings: flag(4),
+        lod_distances,
+        ambient_occlusion: flag(5),
+        ior_bucket_size,
+        colliders,
+        chunk_size,
+        storage: match flag(6) {
+            true => VoxelStorage::Sparse,
+            false => VoxelStorage::Dense,
+        },
+    })
+}
+
+/// Writes an unsigned integer in 7-bit groups, so that small numbers take a single byte
+fn write_varint(writer: &mut impl Write, mut value: u32) -> io::Result<()> {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+/// Reads the values written to a `.voxm` file from the front of a slice
+struct ByteReader<'a>(&'a [u8]);
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], VoxelModelLoaderError> {
+        if self.0.len() < count {
+            return Err(VoxelModelLoaderError::InvalidData("unexpected end of file"));
+        }
+        let (taken, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, VoxelModelLoaderError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, VoxelModelLoaderError> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u32(&mut self) -> Result<u32, VoxelModelLoaderError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn f32(&mut self) -> Result<f32, VoxelModelLoaderError> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    fn varint(&mut self) -> Result<u32, VoxelModelLoaderError> {
+        let mut value: u32 = 0;
+        for shift in (0..32).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(VoxelModelLoaderError::InvalidData("integer is too long"))
+    }
+}
@@ -0,0 +1,64 @@
+use bevy::{log::warn, math::UVec3};
+use dot_vox::{Model, Size, Voxel};
+use ndshape::Shape;
+
+use crate::model::{RawVoxel, VoxelData};
+
+use super::VoxSaverError;
+
+/// The largest size of a model along each axis, as Magica Voxel stores each coordinate in a byte
+const MAX_MODEL_SIZE: u32 = 256;
+
+impl VoxelData {
+    /// Export to Magica Voxel data, undoing the coordinate conversion performed by [`VoxelData::from_model`]
+    pub(super) fn to_model(&self) -> Result<Model, VoxSaverError> {
+        let leading_padding = UVec3::splat(self.padding() / 2);
+        let size: UVec3 = self.shape.as_array().map(|v| v - self.padding()).into();
+        if size.cmpgt(UVec3::splat(MAX_MODEL_SIZE)).any() {
+            return Err(VoxSaverError::ModelTooLarge(size));
+        }
+        let mut skipped = 0;
+        let voxels: Vec<Voxel> = self
+            .voxels
+            .iter()
+            .enumerate()
+            .filter(|(_, voxel)| *voxel != RawVoxel::EMPTY)
+            .filter_map(|(index, voxel)| {
+                let point: UVec3 = self.shape.delinearize(index as u32).into();
+                // remove the outer layer of voxels that the loader adds
+                if point.cmplt(leading_padding).any() || point.cmpge(size + leading_padding).any() {
+                    return None;
+                }
+                let point = point - leading_padding;
+                // Magica Voxel palettes only have 255 elements, as each voxel's index plus one is stored in a byte
+                let index = match u8::try_from(voxel.0) {
+                    Ok(index) if index < u8::MAX => index,
+                    _ => {
+                        skipped += 1;
+                        return None;
+                    }
+                };
+                Some(Voxel {
+                    x: ((size.x - 1) - point.x) as u8,
+                    y: point.z as u8,
+                    z: point.y as u8,
+                    i: index,
+                })
+            })
+            .collect();
+        if skipped > 0 {
+            warn!(
+                "Magica Voxel palettes have 255 elements, {} voxels with higher palette indices will not be saved",
+                skipped
+            );
+        }
+        Ok(Model {
+            size: Size {
+                x: size.x,
+                y: size.z,
+                z: size.y,
+            },
+            voxels,
+        })
+    }
+}
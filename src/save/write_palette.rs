@@ -0,0 +1,80 @@
+use bevy::{
+    color::{ColorToPacked, LinearRgba},
+    log::warn,
+};
+use dot_vox::{Color, Dict, Material};
+
+use crate::{VoxLoaderSettings, model::VoxelPalette};
+
+/// The highest radiant flux (or "power") that Magica Voxel allows for an emissive material
+const MAX_RADIANT_FLUX: f32 = 4.0;
+
+impl VoxelPalette {
+    /// Export to a Magica Voxel palette and materials, undoing the conversions performed by [`VoxelPalette::from_data`]
+    pub(super) fn to_data(&self, settings: &VoxLoaderSettings) -> (Vec<Color>, Vec<Material>) {
+        if self.elements.len() > 256 {
+            warn!(
+                "Magica Voxel palettes have 256 elements, the remaining {} elements will not be saved",
+                self.elements.len() - 256
+            );
+        }
+        let palette = (0..256)
+            .map(|index| {
+                let [r, g, b, a] = match self.elements.get(index) {
+                    Some(element) if self.uses_srgb => element.color.to_srgba().to_u8_array(),
+                    Some(element) => element.color.to_linear().to_u8_array(),
+                    None => LinearRgba::BLACK.to_u8_array(),
+                };
+                Color { r, g, b, a }
+            })
+            .collect();
+        let materials = (0..256)
+            .map(|index| {
+                let mut properties = Dict::new();
+                let mut set = |key: &str, value: String| {
+                    properties.insert(key.to_string(), value);
+                };
+                let Some(element) = self.elements.get(index) else {
+                    set("_type", "_diffuse".to_string());
+                    return Material {
+                        id: index as u32 + 1,
+                        properties,
+                    };
+                };
+                if element.density > 0.0 {
+                    set("_type", "_media".to_string());
+                    set("_d", (element.density / 10.0).to_string());
+                } else if element.refraction_index > 0.0 {
+                    set("_type", "_glass".to_string());
+                    set("_ior", (element.refraction_index - 1.0).to_string());
+                    set("_alpha", element.translucency.to_string());
+                    set("_trans", element.translucency.to_string());
+                    set("_rough", element.roughness.to_string());
+                } else if element.metalness > 0.0
+                    || (element.roughness - settings.diffuse_roughness).abs() > 0.001
+                {
+                    // Magica Voxel's diffuse material has no roughness, so a metal with no metalness is used instead
+                    set("_type", "_metal".to_string());
+                    set("_metal", element.metalness.to_string());
+                    set("_rough", element.roughness.to_string());
+                } else if element.emission > 0.0 {
+                    set("_type", "_emit".to_string());
+                } else {
+                    set("_type", "_diffuse".to_string());
+                }
+                if element.emission > 0.0 {
+                    // The loader multiplies the emission by the radiant flux, which is 0 through 4
+                    let emission = element.emission / settings.emission_strength;
+                    let flux = (emission.ceil() - 1.0).clamp(0.0, MAX_RADIANT_FLUX);
+                    set("_emit", (emission / (flux + 1.0)).to_string());
+                    set("_flux", flux.to_string());
+                }
+                Material {
+                    id: index as u32 + 1,
+                    properties,
+                }
+            })
+            .collect();
+        (palette, materials)
+    }
+}
@@ -0,0 +1,208 @@
+use bevy::{
+    log::warn,
+    math::{Mat3, Vec3},
+    transform::components::Transform,
+};
+use dot_vox::{Dict, Frame, Layer, SceneNode, ShapeModel};
+
+use super::VoxFileNode;
+
+/// Converts the nodes into a Magica Voxel scene graph. The first node in the graph is the root, which has a group
+/// node containing `nodes` as its child.
+pub(super) fn write_scene_graph(nodes: &[VoxFileNode], voxel_size: f32) -> Vec<SceneNode> {
+    let mut graph = vec![
+        SceneNode::Transform {
+            attributes: Dict::new(),
+            frames: vec![Frame::new(Dict::new())],
+            child: 1,
+            layer_id: u32::MAX,
+        },
+        SceneNode::Group {
+            attributes: Dict::new(),
+            children: vec![],
+        },
+    ];
+    let children = nodes
+        .iter()
+        .map(|node| write_xform_node(&mut graph, node, voxel_size))
+        .collect();
+    graph[1] = SceneNode::Group {
+        attributes: Dict::new(),
+        children,
+    };
+    graph
+}
+
+/// Returns a layer for each of the [`crate::VoxelLayer`]s that the nodes are assigned to. There is always at least one layer.
+pub(super) fn write_layers(nodes: &[VoxFileNode]) -> Vec<Layer> {
+    let mut layers = vec![Layer {
+        attributes: Dict::new(),
+    }];
+    let mut stack: Vec<&VoxFileNode> = nodes.iter().collect();
+    while let Some(node) = stack.pop() {
+        stack.extend(node.children.iter());
+        let Some(layer) = &node.layer else {
+            continue;
+        };
+        let index = layer.id as usize;
+        if layers.len() <= index {
+            layers.resize_with(index + 1, || Layer {
+                attributes: Dict::new(),
+            });
+        }
+        if let Some(name) = &layer.name {
+            layers[index]
+                .attributes
+                .insert("_name".to_string(), name.clone());
+        }
+    }
+    layers
+}
+
+fn write_xform_node(graph: &mut Vec<SceneNode>, node: &VoxFileNode, voxel_size: f32) -> u32 {
+    let index = push_placeholder(graph);
+    let child = if node.children.is_empty() && !node.models.is_empty() {
+        write_shape_node(graph, node)
+    } else {
+        let group = push_placeholder(graph);
+        let mut children = Vec::new();
+        if !node.models.is_empty() {
+            // a transform node has either a group or a shape as its child, so the models need a transform of their own
+            let shape_xform = push_placeholder(graph);
+            let shape = write_shape_node(graph, node);
+            graph[shape_xform as usize] = SceneNode::Transform {
+                attributes: Dict::new(),
+                frames: vec![Frame::new(Dict::new())],
+                child: shape,
+                layer_id: layer_id(node),
+            };
+            children.push(shape_xform);
+        }
+        children.extend(
+            node.children
+                .iter()
+                .map(|child| write_xform_node(graph, child, voxel_size)),
+        );
+        graph[group as usize] = SceneNode::Group {
+            attributes: Dict::new(),
+            children,
+        };
+        group
+    };
+    let mut attributes = Dict::new();
+    if let Some(name) = &node.name {
+        attributes.insert("_name".to_string(), name.clone());
+    }
+    if node.is_hidden {
+        attributes.insert("_hidden".to_string(), "1".to_string());
+    }
+    graph[index as usize] = SceneNode::Transform {
+        attributes,
+        frames: vec![frame_from_transform(&node.transform, voxel_size)],
+        child,
+        layer_id: layer_id(node),
+    };
+    index
+}
+
+fn write_shape_node(graph: &mut Vec<SceneNode>, node: &VoxFileNode) -> u32 {
+    let models = if node.models.len() == 1 {
+        vec![ShapeModel {
+            model_id: node.models[0] as u32,
+            attributes: Dict::new(),
+        }]
+    } else if node.frames.is_empty() {
+        node.models
+            .iter()
+            .enumerate()
+            .map(|(frame, model)| shape_model_keyframe(*model, frame))
+            .collect()
+    } else {
+        // a model only needs a keyframe when it replaces a different model on the timeline
+        node.frames
+            .iter()
+            .enumerate()
+            .filter(|(frame, index)| *frame == 0 || node.frames[frame - 1] != **index)
+            .filter_map(|(frame, index)| {
+                Some(shape_model_keyframe(*node.models.get(*index)?, frame))
+            })
+            .collect()
+    };
+    graph.push(SceneNode::Shape {
+        attributes: Dict::new(),
+        models,
+    });
+    graph.len() as u32 - 1
+}
+
+fn shape_model_keyframe(model: usize, frame: usize) -> ShapeModel {
+    let mut attributes = Dict::new();
+    attributes.insert("_f".to_string(), frame.to_string());
+    ShapeModel {
+        model_id: model as u32,
+        attributes,
+    }
+}
+
+fn push_placeholder(graph: &mut Vec<SceneNode>) -> u32 {
+    graph.push(SceneNode::Group {
+        attributes: Dict::new(),
+        children: vec![],
+    });
+    graph.len() as u32 - 1
+}
+
+fn layer_id(node: &VoxFileNode) -> u32 {
+    node.layer.as_ref().map_or(0, |layer| layer.id)
+}
+
+/// Converts a [`Transform`] to a Magica Voxel frame, undoing the conversion performed by `transform_from_frame`.
+/// Magica Voxel only supports whole-voxel translations, and rotations and reflections that are aligned to the axes.
+fn frame_from_transform(transform: &Transform, voxel_size: f32) -> Frame {
+    let mut attributes = Dict::new();
+    let position = (y_up_to_z_up(transform.translation) / voxel_size)
+        .round()
+        .as_ivec3();
+    attributes.insert(
+        "_t".to_string(),
+        format!("{} {} {}", position.x, position.y, position.z),
+    );
+    let (axis, angle) = transform.rotation.to_axis_angle();
+    let rotation = Mat3::from_axis_angle(y_up_to_z_up(axis), angle)
+        * Mat3::from_diagonal(transform.scale.signum());
+    match rotation_byte(rotation) {
+        Some(byte) => {
+            attributes.insert("_r".to_string(), byte.to_string());
+        }
+        None => warn!("Transform {:?} is not aligned to the axes", transform),
+    }
+    Frame::new(attributes)
+}
+
+/// Packs a signed permutation matrix into Magica Voxel's byte representation. The bits record the column of the
+/// non-zero entry in the first and second rows, followed by the signs of the entries in each row.
+fn rotation_byte(matrix: Mat3) -> Option<u8> {
+    let mut columns = [0_u8; 3];
+    let mut byte = 0_u8;
+    for (row, column_for_row) in columns.iter_mut().enumerate() {
+        let (column, value) = (0..3)
+            .map(|column| (column, matrix.col(column)[row]))
+            .max_by(|(_, a), (_, b)| a.abs().total_cmp(&b.abs()))?;
+        if (value.abs() - 1.0).abs() > 0.001 {
+            return None;
+        }
+        *column_for_row = column as u8;
+        if value < 0.0 {
+            byte |= 1 << (4 + row);
+        }
+    }
+    if columns[0] == columns[1] || columns[1] == columns[2] || columns[0] == columns[2] {
+        return None;
+    }
+    Some(byte | columns[0] | (columns[1] << 2))
+}
+
+/// Converts a vector from bevy's right-handed Y-up space to Magica Voxel's left-handed Z-up space
+fn y_up_to_z_up(vector: Vec3) -> Vec3 {
+    Vec3::new(-vector.x, vector.z, vector.y)
+}
@@ -1,3 +1,16 @@
+#[cfg(feature = "generate_voxels")]
+use bevy::{
+    asset::{AssetId, Assets},
+    ecs::{
+        query::{Has, With},
+        world::World,
+    },
+    image::Image,
+    pbr::{FogVolume, StandardMaterial},
+    platform::collections::HashMap,
+    prelude::{Mut, QueryState},
+    render::mesh::{Mesh, Mesh3d},
+};
 use bevy::{
     prelude::{Children, Commands, Entity, Query, Res, Visibility},
     time::Time,
@@ -7,6 +20,11 @@ use crate::{
     VoxelAnimationPlayer,
     load::{AnimationUpdate, VoxelAnimationFrame},
 };
+#[cfg(feature = "generate_voxels")]
+use crate::{
+    VoxelChunks, VoxelContext, VoxelModel, VoxelModelInstance, VoxelModelRestored,
+    model::ModelMeshes,
+};
 
 pub(super) fn update_animations(
     mut commands: Commands,
@@ -38,3 +56,89 @@ pub(super) fn update_animations(
         };
     }
 }
+
+/// Rebuilds the meshes, materials and cloud of model instances marked with [`VoxelModelRestored`], such as the
+/// instances in a restored saved game of a model loaded from a `.voxm` file. Waits until the model and context have
+/// loaded, then removes the marker.
+#[cfg(feature = "generate_voxels")]
+pub(super) fn mesh_restored_instances(
+    world: &mut World,
+    instances: &mut QueryState<
+        (
+            Entity,
+            &VoxelModelInstance,
+            Has<Mesh3d>,
+            Has<VoxelChunks>,
+            Option<&Children>,
+        ),
+        With<VoxelModelRestored>,
+    >,
+) {
+    let models = world.resource::<Assets<VoxelModel>>();
+    let contexts = world.resource::<Assets<VoxelContext>>();
+    let mut restored = Vec::new();
+    let mut unmeshed = Vec::new();
+    for (entity, instance, has_mesh, has_chunks, children) in instances.iter(world) {
+        let has_cloud = children.is_some_and(|children| {
+            children
+                .iter()
+                .any(|child| world.get::<FogVolume>(*child).is_some())
+        });
+        if has_mesh || has_chunks || has_cloud {
+            restored.push(entity);
+            continue;
+        }
+        let (Some(model), Some(_)) = (models.get(&instance.model), contexts.get(&instance.context))
+        else {
+            continue;
+        };
+        restored.push(entity);
+        if model.has_mesh || model.has_cloud {
+            unmeshed.push((entity, instance.model.id(), instance.context.id()));
+        }
+    }
+    for entity in restored {
+        world.entity_mut(entity).remove::<VoxelModelRestored>();
+    }
+    if unmeshed.is_empty() {
+        return;
+    }
+    // each model is meshed once, and its instances share the meshes, so that modifying the model updates all of them
+    let mut model_meshes: HashMap<(AssetId<VoxelModel>, AssetId<VoxelContext>), ModelMeshes> =
+        HashMap::default();
+    world.resource_scope(|world, mut meshes: Mut<Assets<Mesh>>| {
+        world.resource_scope(|world, mut materials: Mut<Assets<StandardMaterial>>| {
+            world.resource_scope(|world, mut images: Mut<Assets<Image>>| {
+                let models = world.resource::<Assets<VoxelModel>>();
+                let contexts = world.resource::<Assets<VoxelContext>>();
+                for (_, model_id, context_id) in unmeshed.iter() {
+                    let (Some(model), Some(context)) =
+                        (models.get(*model_id), contexts.get(*context_id))
+                    else {
+                        continue;
+                    };
+                    model_meshes
+                        .entry((*model_id, *context_id))
+                        .or_insert_with(|| {
+                            ModelMeshes::new(
+                                &model.data,
+                                context,
+                                &mut meshes,
+                                &mut materials,
+                                &mut images,
+                            )
+                        });
+                }
+            });
+        });
+    });
+    for (entity, model_id, context_id) in unmeshed {
+        let (Some(model_meshes), Ok(mut entity)) = (
+            model_meshes.get(&(model_id, context_id)),
+            world.get_entity_mut(entity),
+        ) else {
+            continue;
+        };
+        model_meshes.insert(&mut entity);
+    }
+}
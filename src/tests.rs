@@ -3,22 +3,33 @@ use std::{f32::consts::FRAC_PI_2, time::Duration};
 use super::*;
 
 #[cfg(feature = "modify_voxels")]
-use crate::{VoxelRegion, model::queryable::OutOfBoundsError};
+use crate::{VoxelRayHit, VoxelRegion, model::queryable::OutOfBoundsError};
 
 use crate::{VoxScenePlugin, VoxelModelInstance, model::RawVoxel};
 use bevy::{
     MinimalPlugins,
+    animation::{AnimationClip, AnimationPlayer, AnimationPlugin, AnimationTarget},
     app::App,
-    asset::{AssetApp, AssetPlugin, AssetServer, Assets, Handle, LoadState},
+    asset::{Asset, AssetApp, AssetPlugin, AssetServer, Assets, Handle, LoadState},
+    core_pipeline::bloom::Bloom,
     ecs::{hierarchy::Children, name::Name},
-    math::{IVec3, Quat, UVec3, Vec3, Vec3A},
-    pbr::{FogVolume, MeshMaterial3d, StandardMaterial},
+    math::{Dir3, IVec3, Quat, Ray3d, UVec2, UVec3, Vec3, Vec3A},
+    pbr::{
+        AmbientLight, DirectionalLight, DistanceFog, ExtendedMaterial, FogVolume,
+        MaterialExtension, MeshMaterial3d, StandardMaterial, light_consts,
+    },
     platform::collections::HashSet,
     prelude::{
-        Commands, GlobalTransform, InheritedVisibility, Mesh3d, OnAdd, Query, Transform, Trigger,
-        ViewVisibility, Visibility,
+        Camera, Camera3d, ClearColor, Commands, GlobalTransform, InheritedVisibility, Mesh3d,
+        OnAdd, Projection, Query, Reflect, Transform, Trigger, ViewVisibility, Visibility, With,
+    },
+    render::{
+        mesh::{Mesh, MeshAabb, PrimitiveTopology, VertexAttributeValues},
+        primitives::Aabb,
+        render_resource::AsBindGroup,
+        texture::ImagePlugin,
+        view::{VisibilityClass, VisibilityRange},
     },
-    render::{mesh::Mesh, texture::ImagePlugin, view::VisibilityClass},
     scene::{Scene, ScenePlugin, SceneRoot},
     transform::components::TransformTreeChanged,
     utils::default,
@@ -31,13 +42,13 @@ fn test_raw_voxel_conversion() {
     assert_eq!(
         vox,
         Voxel::EMPTY,
-        "RawVoxel(255) should have wrapped around to Voxel(0)"
+        "RawVoxel(65535) should have wrapped around to Voxel(0)"
     );
     let back_to_raw = RawVoxel::from(vox);
     assert_eq!(
         back_to_raw,
         RawVoxel::EMPTY,
-        "Voxel(0) should have wrapped around to RawVoxel(255)"
+        "Voxel(0) should have wrapped around to RawVoxel(65535)"
     );
 }
 
@@ -159,6 +170,168 @@ async fn test_spawn_play_animation() {
     );
 }
 
+#[async_std::test]
+async fn test_load_transform_keyframes() {
+    let mut app = App::new();
+    let handle = setup_and_load_voxel_scene(&mut app, "animation.vox").await;
+    app.update();
+    let clip_handle: Handle<AnimationClip> = app
+        .world()
+        .resource::<AssetServer>()
+        .get_handle("animation.vox#animation")
+        .expect("animation clip handle");
+    let clip = app
+        .world()
+        .resource::<Assets<AnimationClip>>()
+        .get(clip_handle.id())
+        .expect("retrieve clip from Res<Assets>");
+    assert_eq!(clip.curves().len(), 1, "Only the door has keyframes");
+    assert_eq!(
+        clip.duration(),
+        0.75,
+        "Last keyframe is frame 6 at 8 frames per second"
+    );
+    let scene_root = app.world_mut().spawn(SceneRoot(handle)).id();
+    app.update();
+    let root_entity = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("scene root");
+    assert!(
+        app.world().get::<AnimationPlayer>(root_entity).is_some(),
+        "Root of scene has an animation player"
+    );
+    let (target, name) = app
+        .world_mut()
+        .query::<(&AnimationTarget, &Name)>()
+        .single(app.world())
+        .expect("single animation target");
+    assert_eq!(name.as_str(), "door");
+    assert_eq!(target.player, root_entity);
+}
+
+#[async_std::test]
+async fn test_load_shape_frames() {
+    let mut app = App::new();
+    let handle = setup_and_load_voxel_scene(&mut app, "flipbook.vox#flipbook").await;
+    app.update();
+    let scene_root = app.world_mut().spawn(SceneRoot(handle)).id();
+    app.update();
+    let entity = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("flipbook entity");
+    let player = app
+        .world()
+        .get::<VoxelAnimationPlayer>(entity)
+        .expect("animation player");
+    assert_eq!(
+        player.frames,
+        vec![1, 1, 1, 0, 2],
+        "Frames are ordered by their keyframe, and held until the next keyframe"
+    );
+    assert_eq!(player.frame_rate, Duration::from_secs_f32(1.0 / 8.0));
+    let frame_entities = app.world().get::<Children>(entity).expect("children");
+    assert_eq!(
+        app.world().get::<Visibility>(frame_entities[0]),
+        Some(&Visibility::Hidden),
+        "Model keyframed at frame 3 is hidden"
+    );
+    assert_eq!(
+        app.world().get::<Visibility>(frame_entities[1]),
+        Some(&Visibility::Inherited),
+        "Model keyframed at frame 0 is showing"
+    );
+}
+
+#[async_std::test]
+async fn test_import_cameras() {
+    let mut app = App::new();
+    setup_app_with_settings(
+        &mut app,
+        Some(VoxLoaderSettings {
+            import_cameras: true,
+            ..default()
+        }),
+    );
+    let camera_handle = load_voxel_scene(&mut app, "test.vox#camera-0").await;
+    let scene_handle = load_voxel_scene(&mut app, "test.vox").await;
+    app.update();
+    let mut scenes = app.world_mut().resource_mut::<Assets<Scene>>();
+    let mut camera_world = scenes
+        .remove(camera_handle.id())
+        .expect("retrieve test.vox#camera-0 from Res<Assets>")
+        .world;
+    let mut scene_world = scenes
+        .remove(scene_handle.id())
+        .expect("retrieve test.vox from Res<Assets>")
+        .world;
+
+    let (name, camera, projection, transform) = camera_world
+        .query_filtered::<(&Name, &Camera, &Projection, &Transform), With<Camera3d>>()
+        .single(&camera_world)
+        .expect("single camera");
+    assert_eq!(name.as_str(), "camera-0");
+    assert!(!camera.is_active, "Imported cameras are inactive");
+    let Projection::Perspective(projection) = projection else {
+        panic!("Perspective projection");
+    };
+    assert!((projection.fov - 45_f32.to_radians()).abs() < 0.0001);
+    assert!(
+        transform.translation.abs_diff_eq(Vec3::NEG_Z, 0.0001),
+        "Camera is 1 unit in front of the focus point"
+    );
+    assert!(transform.forward().abs_diff_eq(Vec3::Z, 0.0001));
+
+    assert_eq!(
+        scene_world.query::<&Camera3d>().iter(&scene_world).len(),
+        10,
+        "Every camera slot is imported into the scene"
+    );
+}
+
+#[async_std::test]
+async fn test_import_render_settings() {
+    let mut app = App::new();
+    setup_app_with_settings(
+        &mut app,
+        Some(VoxLoaderSettings {
+            import_cameras: true,
+            import_render_settings: true,
+            ..default()
+        }),
+    );
+    let handle = load_voxel_scene(&mut app, "test.vox").await;
+    app.update();
+    let mut world = app
+        .world_mut()
+        .resource_mut::<Assets<Scene>>()
+        .remove(handle.id())
+        .expect("retrieve test.vox from Res<Assets>")
+        .world;
+    let (name, sun) = world
+        .query::<(&Name, &DirectionalLight)>()
+        .single(&world)
+        .expect("single sun");
+    assert_eq!(name.as_str(), "sun");
+    assert!((sun.illuminance - 0.7 * light_consts::lux::AMBIENT_DAYLIGHT).abs() < 0.01);
+    let ambient = world.get_resource::<AmbientLight>().expect("ambient light");
+    assert!((ambient.brightness - 700.0).abs() < 0.01);
+    assert!(world.get_resource::<ClearColor>().is_some());
+    for (camera, bloom, fog) in world
+        .query::<(&Camera, Option<&Bloom>, Option<&DistanceFog>)>()
+        .iter(&world)
+    {
+        assert!(camera.hdr, "Bloom requires HDR");
+        assert!(bloom.is_some(), "Bloom added to imported cameras");
+        assert!(fog.is_none(), "Fog density is 0 in test.vox");
+    }
+}
+
 #[async_std::test]
 async fn test_transmissive_mat() {
     let mut app = App::new();
@@ -205,6 +378,158 @@ async fn test_transmissive_mat() {
     assert!(material.metallic_roughness_texture.is_some());
 }
 
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone, Default)]
+struct TestExtension {}
+
+impl MaterialExtension for TestExtension {}
+
+#[async_std::test]
+async fn test_custom_material() {
+    type TestMaterial = ExtendedMaterial<StandardMaterial, TestExtension>;
+    let mut app = App::new();
+    setup_app(&mut app);
+    app.init_asset::<TestMaterial>()
+        .add_plugins(VoxelMaterialPlugin::<TestMaterial>::default());
+    let handle = load_voxel_scene(&mut app, "test.vox#outer-group/inner-group/walls").await;
+    let scene_root = app.world_mut().spawn(SceneRoot(handle)).id();
+    app.update();
+    let entity = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("scene root");
+    assert!(
+        app.world()
+            .get::<MeshMaterial3d<StandardMaterial>>(entity)
+            .is_none()
+    );
+    let mat_handle = &app
+        .world()
+        .get::<MeshMaterial3d<TestMaterial>>(entity)
+        .expect("Walls has a custom material")
+        .0;
+    let material = app
+        .world()
+        .resource::<Assets<TestMaterial>>()
+        .get(mat_handle)
+        .expect("material");
+    assert!(
+        material.base.base_color_texture.is_some(),
+        "custom material has the palette textures"
+    );
+    assert_eq!(material.base.specular_transmission, 1.0);
+}
+
+#[cfg(all(feature = "generate_voxels", feature = "modify_voxels"))]
+#[test]
+fn test_custom_material_resync() {
+    type TestMaterial = ExtendedMaterial<StandardMaterial, TestExtension>;
+    let mut app = App::new();
+    setup_app(&mut app);
+    app.init_asset::<TestMaterial>()
+        .add_plugins(VoxelMaterialPlugin::<TestMaterial>::default());
+    let palette = VoxelPalette::new(
+        vec![
+            VoxelElement::default(),
+            VoxelElement {
+                translucency: 1.0,
+                refraction_index: 1.33,
+                ..default()
+            },
+            VoxelElement {
+                translucency: 1.0,
+                refraction_index: 1.5,
+                ..default()
+            },
+        ],
+        true,
+    );
+    let world = app.world_mut();
+    let context = world
+        .run_system_cached_with(create_voxel_context, palette)
+        .expect("Context has been created");
+    let empty = VoxelData::new(UVec3::splat(4), VoxLoaderSettings::default());
+    let scene_handle = world
+        .run_system_cached_with(create_voxel_scene, (empty, "empty".to_string(), context))
+        .expect("Add empty model");
+    let scene_root = world.spawn(SceneRoot(scene_handle)).id();
+    app.update();
+    let entity = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("model entity");
+    let instance = app
+        .world()
+        .get::<VoxelModelInstance>(entity)
+        .expect("instance")
+        .clone();
+    let mesh_handle = app
+        .world_mut()
+        .resource_mut::<Assets<Mesh>>()
+        .reserve_handle();
+    let fill = |app: &mut App, voxel: Voxel| {
+        let modifier = VoxelModifier::new(
+            instance.clone(),
+            mesh_handle.clone(),
+            VoxelRegionMode::All,
+            move |_pos, _voxel, _model| voxel.clone(),
+        );
+        app.world_mut()
+            .run_system_cached_with(modify_voxel_model, Some(modifier))
+            .expect("model modified");
+        app.update();
+    };
+    let material = |app: &App| {
+        assert!(
+            app.world()
+                .get::<MeshMaterial3d<StandardMaterial>>(entity)
+                .is_none(),
+            "the standard material is replaced"
+        );
+        let handle = &app.world().get::<MeshMaterial3d<TestMaterial>>(entity)?.0;
+        app.world()
+            .resource::<Assets<TestMaterial>>()
+            .get(handle)
+            .cloned()
+    };
+
+    fill(&mut app, Voxel(1));
+    let opaque = material(&app).expect("a model gaining a mesh has the custom material");
+    assert_eq!(opaque.base.specular_transmission, 0.0);
+
+    fill(&mut app, Voxel(2));
+    let transmissive = material(&app).expect("a transmissive model has the custom material");
+    assert!((transmissive.base.ior - 1.33).abs() < 0.001);
+
+    fill(&mut app, Voxel(3));
+    let transmissive = material(&app).expect("custom material");
+    assert!(
+        (transmissive.base.ior - 1.5).abs() < 0.001,
+        "the custom material follows the index of refraction"
+    );
+
+    // the custom materials of replaced transmissive materials are dropped
+    for voxel in [Voxel(1), Voxel(2), Voxel(1), Voxel(2), Voxel(1)] {
+        fill(&mut app, voxel);
+    }
+    app.update();
+    app.update();
+    assert_eq!(
+        app.world().resource::<Assets<TestMaterial>>().len(),
+        1,
+        "only the custom material of the opaque material is left"
+    );
+
+    fill(&mut app, Voxel::EMPTY);
+    assert!(
+        material(&app).is_none(),
+        "the custom material is removed with the mesh"
+    );
+}
+
 #[async_std::test]
 async fn test_opaque_mat() {
     let mut app = App::new();
@@ -315,6 +640,441 @@ async fn test_spawn_system() {
     app.update(); // fire the hooks
 }
 
+#[async_std::test]
+async fn test_level_of_detail() {
+    let mut app = App::new();
+    setup_app_with_settings(
+        &mut app,
+        Some(VoxLoaderSettings {
+            lod_distances: vec![10.0, 20.0],
+            ..default()
+        }),
+    );
+    app.register_type::<VisibilityRange>();
+    let handle = load_voxel_scene(&mut app, "test.vox#outer-group/inner-group/walls").await;
+    app.update();
+    let assets = app.world().resource::<AssetServer>();
+    let aabbs: Vec<Aabb> = [
+        "test.vox#outer-group/inner-group/walls@mesh",
+        "test.vox#outer-group/inner-group/walls@mesh-lod1",
+        "test.vox#outer-group/inner-group/walls@mesh-lod2",
+    ]
+    .iter()
+    .map(|path| {
+        let mesh: Handle<Mesh> = assets.get_handle(*path).expect("mesh handle");
+        app.world()
+            .resource::<Assets<Mesh>>()
+            .get(mesh.id())
+            .expect("retrieve mesh from Res<Assets>")
+            .compute_aabb()
+            .expect("mesh bounds")
+    })
+    .collect();
+    assert!(
+        aabbs.iter().all(|aabb| *aabb == aabbs[0]),
+        "Levels of detail are aligned with the full resolution mesh"
+    );
+    let scene_root = app.world_mut().spawn(SceneRoot(handle)).id();
+    app.update();
+    let entity = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("walls");
+    assert!(
+        app.world().get::<VisibilityRange>(entity) == Some(&VisibilityRange::abrupt(0.0, 10.0))
+    );
+    let lods = app.world().get::<Children>(entity).expect("lod children");
+    assert_eq!(lods.len(), 2);
+    assert!(
+        app.world().get::<VisibilityRange>(lods[1])
+            == Some(&VisibilityRange::abrupt(20.0, f32::MAX))
+    );
+}
+
+#[cfg(feature = "modify_voxels")]
+#[async_std::test]
+async fn test_modify_regenerates_levels_of_detail() {
+    let mut app = App::new();
+    setup_app_with_settings(
+        &mut app,
+        Some(VoxLoaderSettings {
+            lod_distances: vec![10.0, 20.0],
+            ..default()
+        }),
+    );
+    app.register_type::<VisibilityRange>();
+    let handle = load_voxel_scene(&mut app, "test.vox#outer-group/inner-group/walls").await;
+    app.update();
+    let scene_root = app.world_mut().spawn(SceneRoot(handle)).id();
+    app.update();
+    let entity = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("walls");
+    let lod_meshes = |app: &App| -> Vec<Handle<Mesh>> {
+        app.world()
+            .get::<Children>(entity)
+            .expect("lod children")
+            .iter()
+            .map(|child| {
+                app.world()
+                    .get::<Mesh3d>(*child)
+                    .expect("lod mesh")
+                    .0
+                    .clone()
+            })
+            .collect()
+    };
+    let before = lod_meshes(&app);
+    assert_eq!(before.len(), 2);
+    let model_instance = app
+        .world()
+        .get::<VoxelModelInstance>(entity)
+        .expect("voxel model instance")
+        .clone();
+    let mesh = app
+        .world()
+        .get::<Mesh3d>(entity)
+        .expect("voxel mesh")
+        .clone();
+    let modifier = VoxelModifier::new(
+        model_instance,
+        mesh.0,
+        VoxelRegionMode::Box(VoxelRegion {
+            origin: IVec3::ZERO,
+            size: IVec3::splat(4),
+        }),
+        |_pos, _voxel, _model| Voxel::EMPTY,
+    );
+    app.world_mut()
+        .run_system_cached_with(modify_voxel_model, Some(modifier))
+        .expect("model modified");
+    app.update();
+    let after = lod_meshes(&app);
+    assert_eq!(after.len(), 2, "Stale levels of detail are despawned");
+    assert!(
+        after.iter().all(|mesh| !before.contains(mesh)),
+        "Levels of detail are regenerated"
+    );
+    let lods = app.world().get::<Children>(entity).expect("lod children");
+    assert!(
+        app.world().get::<VisibilityRange>(lods[1])
+            == Some(&VisibilityRange::abrupt(20.0, f32::MAX))
+    );
+}
+
+#[cfg(feature = "modify_voxels")]
+#[test]
+fn test_downsample_voxels() {
+    let palette = VoxelPalette::from_colors(
+        vec![
+            bevy::color::palettes::css::GREEN.into(),
+            bevy::color::palettes::css::RED.into(),
+        ],
+        true,
+    );
+    let mut data = VoxelData::new(UVec3::new(5, 4, 4), VoxLoaderSettings::default());
+    data.set_voxel(Voxel(1), UVec3::new(0, 0, 0));
+    data.set_voxel(Voxel(2), UVec3::new(1, 0, 0));
+    data.set_voxel(Voxel(2), UVec3::new(1, 1, 0));
+    data.set_voxel(Voxel(1), UVec3::new(4, 3, 3));
+    let downsampled = data.downsampled(2, &palette.density_for_voxel);
+    assert_eq!(
+        downsampled.size(),
+        IVec3::new(3, 2, 2),
+        "Size is rounded up"
+    );
+    assert_eq!(downsampled.settings.voxel_size, 2.0);
+    assert_eq!(
+        downsampled.get_voxel_at_point(IVec3::ZERO),
+        Ok(Voxel(2)),
+        "Most common voxel in the block"
+    );
+    assert_eq!(
+        downsampled.get_voxel_at_point(IVec3::new(1, 0, 0)),
+        Ok(Voxel::EMPTY)
+    );
+    assert_eq!(
+        downsampled.get_voxel_at_point(IVec3::new(2, 1, 1)),
+        Ok(Voxel(1))
+    );
+}
+
+#[cfg(feature = "modify_voxels")]
+#[test]
+fn test_ambient_occlusion() {
+    let palette = VoxelPalette::from_colors(vec![bevy::color::palettes::css::GREEN.into()], true);
+    let mut data = VoxelData::new(
+        UVec3::new(3, 2, 3),
+        VoxLoaderSettings {
+            ambient_occlusion: true,
+            ..default()
+        },
+    );
+    data.set_voxel(Voxel(1), UVec3::new(1, 0, 1));
+    let mesh_colors = |data: &VoxelData| {
+        let (mesh, _, _) = data.remesh(&palette.indices_of_refraction, &palette.density_for_voxel);
+        let mesh = mesh.expect("mesh generated");
+        let Some(VertexAttributeValues::Float32x4(colors)) = mesh.attribute(Mesh::ATTRIBUTE_COLOR)
+        else {
+            panic!("vertex colors");
+        };
+        let colors = colors.clone();
+        (mesh, colors)
+    };
+    let (_, colors) = mesh_colors(&data);
+    assert_eq!(colors.len(), 6 * 4);
+    assert!(
+        colors.iter().all(|color| color[0] == 1.0),
+        "a lone voxel is unoccluded"
+    );
+
+    for x in 0..3 {
+        for z in 0..3 {
+            data.set_voxel(Voxel(1), UVec3::new(x, 0, z));
+        }
+    }
+    data.set_voxel(Voxel(1), UVec3::new(0, 1, 0));
+    let (mesh, colors) = mesh_colors(&data);
+    let Some(VertexAttributeValues::Float32x3(positions)) =
+        mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+    else {
+        panic!("vertex positions");
+    };
+    let Some(VertexAttributeValues::Float32x3(normals)) = mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+    else {
+        panic!("vertex normals");
+    };
+    // the top of the slab, at the corner of the voxel placed on it
+    let occluded_corner = (0..colors.len())
+        .filter(|index| {
+            positions[*index] == [-0.5, 0.0, -0.5] && normals[*index] == [0.0, 1.0, 0.0]
+        })
+        .map(|index| colors[index][0])
+        .collect::<Vec<f32>>();
+    assert!(!occluded_corner.is_empty());
+    assert!(
+        occluded_corner.iter().all(|brightness| *brightness == 0.8),
+        "the corner is occluded by one neighbouring voxel"
+    );
+    data.settings.ambient_occlusion = false;
+    let (mesh, _, _) = data.remesh(&palette.indices_of_refraction, &palette.density_for_voxel);
+    let mesh = mesh.expect("mesh generated");
+    assert!(mesh.attribute(Mesh::ATTRIBUTE_COLOR).is_none());
+    assert!(
+        mesh.count_vertices() < colors.len(),
+        "occluded faces are not merged with unoccluded ones"
+    );
+
+    let mut row = VoxelData::new(
+        UVec3::new(3, 1, 2),
+        VoxLoaderSettings {
+            ambient_occlusion: true,
+            ..default()
+        },
+    );
+    for x in 0..3 {
+        row.set_voxel(Voxel(1), UVec3::new(x, 0, 0));
+    }
+    // occludes the sides of the row, but not its top
+    row.set_voxel(Voxel(1), UVec3::new(1, 0, 1));
+    let (mesh, _) = mesh_colors(&row);
+    let Some(VertexAttributeValues::Float32x3(normals)) = mesh.attribute(Mesh::ATTRIBUTE_NORMAL)
+    else {
+        panic!("vertex normals");
+    };
+    assert_eq!(
+        normals
+            .iter()
+            .filter(|normal| **normal == [0.0, 1.0, 0.0])
+            .count(),
+        2 * 4,
+        "coplanar faces are merged when only the occlusion of other faces differs"
+    );
+}
+
+#[async_std::test]
+async fn test_bake_voxel_scene() {
+    let mut app = App::new();
+    let handle = setup_and_load_voxel_scene(&mut app, "test.vox").await;
+    app.update();
+    let scene_root = app.world_mut().spawn(SceneRoot(handle)).id();
+    app.update();
+    let mut mesh_query = app
+        .world_mut()
+        .query::<(&Mesh3d, Option<&VoxelModelInstance>)>();
+    let meshes_before = mesh_query.iter(app.world()).count();
+    let baked = app
+        .world_mut()
+        .run_system_cached_with(bake_voxel_scene, (scene_root, false))
+        .expect("system ran");
+    assert_eq!(
+        baked.len(),
+        1,
+        "One merged mesh for the scene's voxel context"
+    );
+    let remaining_instances = mesh_query
+        .iter(app.world())
+        .filter(|(_, instance)| instance.is_some())
+        .count();
+    assert!(remaining_instances < meshes_before - 1);
+    let baked_mesh = app.world().get::<Mesh3d>(baked[0]).expect("baked mesh");
+    assert!(
+        app.world()
+            .resource::<Assets<Mesh>>()
+            .get(baked_mesh.id())
+            .is_some_and(|mesh| mesh.count_vertices() > 0)
+    );
+    app.update();
+    assert_eq!(
+        mesh_query
+            .iter(app.world())
+            .filter(|(_, instance)| instance.is_some())
+            .count(),
+        remaining_instances,
+        "baked instances aren't meshed again"
+    );
+
+    let mut app = App::new();
+    let handle = setup_and_load_voxel_scene(&mut app, "test.vox").await;
+    app.update();
+    let scene_root = app.world_mut().spawn(SceneRoot(handle)).id();
+    app.update();
+    let baked = app
+        .world_mut()
+        .run_system_cached_with(bake_voxel_scene, (scene_root, true))
+        .expect("system ran");
+    assert_eq!(baked.len(), 1, "All of the nodes are in the scenery layer");
+    let layer = app.world().get::<VoxelLayer>(baked[0]).expect("layer");
+    assert_eq!(layer.id, 0);
+    assert_eq!(layer.name.as_deref(), Some("scenery"));
+}
+
+#[async_std::test]
+async fn test_save_vox_file() {
+    let mut app = App::new();
+    let handle = setup_and_load_voxel_scene(&mut app, "test.vox#outer-group/inner-group").await;
+    app.update();
+    let scene_root = app.world_mut().spawn(SceneRoot(handle)).id();
+    app.update();
+    let root = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("scene root");
+    let file = app
+        .world_mut()
+        .run_system_cached_with(create_vox_file, root)
+        .expect("system ran")
+        .expect("vox file created");
+    assert_eq!(file.models.len(), 3, "3 unique models are saved");
+    assert_eq!(
+        file.nodes[0].name.as_deref(),
+        Some("outer-group/inner-group")
+    );
+    assert_eq!(file.nodes[0].children.len(), 5);
+
+    let mut bytes = Vec::new();
+    file.write(&mut bytes).expect("file written");
+    let saved = dot_vox::load_bytes(&bytes).expect("saved file parsed");
+    let original = dot_vox::load("assets/test.vox").expect("original file parsed");
+    assert_eq!(saved.palette[..255], original.palette[..255]);
+    for (data, model) in file.models.iter().zip(saved.models.iter()) {
+        let reloaded = VoxelData::from_model(model, data.settings.clone());
+        assert_eq!(
+            reloaded.voxels, data.voxels,
+            "voxels survive the round trip"
+        );
+    }
+
+    let dice = *app
+        .world()
+        .get::<Children>(root)
+        .expect("children of inner-group")
+        .iter()
+        .find(|child| {
+            app.world()
+                .get::<Name>(**child)
+                .is_some_and(|name| name.as_str() == "outer-group/inner-group/dice")
+        })
+        .expect("dice");
+    let saved_dice = saved
+        .scenes
+        .iter()
+        .find_map(|node| match node {
+            dot_vox::SceneNode::Transform {
+                attributes, frames, ..
+            } if attributes.get("_name").map(|n| n.as_str()) == Some("dice") => Some(frames),
+            _ => None,
+        })
+        .expect("dice saved without the names of its ancestors");
+    let transform = Transform::from_matrix(crate::load::transform_from_frame(&saved_dice[0], 1.0));
+    let expected = app.world().get::<Transform>(dice).expect("dice transform");
+    assert!(
+        transform
+            .compute_matrix()
+            .abs_diff_eq(expected.compute_matrix(), 0.001),
+        "transform survives the round trip"
+    );
+}
+
+#[cfg(feature = "generate_voxels")]
+#[test]
+fn test_save_transform_rotation() {
+    let data =
+        SDF::cuboid(Vec3::ONE).voxelize(UVec3::splat(2), VoxLoaderSettings::default(), Voxel(1));
+    let palette = VoxelPalette::from_colors(vec![bevy::color::palettes::css::GREEN.into()], true);
+    let mut file = VoxFile::new(data, palette);
+    let transform =
+        Transform::from_xyz(3.0, 2.0, -1.0).with_rotation(Quat::from_rotation_y(FRAC_PI_2));
+    file.nodes[0].children.push(VoxFileNode {
+        transform,
+        models: vec![0],
+        ..Default::default()
+    });
+    let mut bytes = Vec::new();
+    file.write(&mut bytes).expect("file written");
+    let saved = dot_vox::load_bytes(&bytes).expect("saved file parsed");
+    let frame = saved
+        .scenes
+        .iter()
+        .filter_map(|node| match node {
+            dot_vox::SceneNode::Transform { frames, .. } => frames[0].orientation().map(|_| frames),
+            _ => None,
+        })
+        .find(|frames| frames[0].position().is_some_and(|p| p.x != 0))
+        .expect("rotated node");
+    let reloaded = Transform::from_matrix(crate::load::transform_from_frame(&frame[0], 1.0));
+    assert!(
+        reloaded
+            .compute_matrix()
+            .abs_diff_eq(transform.compute_matrix(), 0.001),
+        "{:?} should equal {:?}",
+        reloaded,
+        transform
+    );
+}
+
+#[test]
+fn test_save_large_model() {
+    let palette = VoxelPalette::from_colors(vec![bevy::color::palettes::css::GREEN.into()], true);
+    let data = VoxelData::new(UVec3::new(257, 1, 1), VoxLoaderSettings::default());
+    let mut bytes = Vec::new();
+    assert!(matches!(
+        VoxFile::new(data, palette.clone()).write(&mut bytes),
+        Err(VoxSaverError::ModelTooLarge(size)) if size == UVec3::new(257, 1, 1)
+    ));
+    let data = VoxelData::new(UVec3::new(1, 1, 256), VoxLoaderSettings::default());
+    VoxFile::new(data, palette)
+        .write(&mut bytes)
+        .expect("256 voxels fit");
+}
+
 #[cfg(feature = "modify_voxels")]
 #[async_std::test]
 async fn test_modify_voxels() {
@@ -376,13 +1136,273 @@ async fn test_modify_voxels() {
     assert_eq!(voxel.0, 7, "Voxel material should've been changed to 7");
 }
 
-#[cfg(feature = "generate_voxels")]
-#[test]
-fn test_generate_voxels() {
-    use bevy::render::mesh::MeshAabb;
-
+#[cfg(feature = "modify_voxels")]
+#[async_std::test]
+async fn test_voxel_edit_history() {
     let mut app = App::new();
-    setup_app(&mut app);
+    let handle =
+        setup_and_load_voxel_scene(&mut app, "test.vox#outer-group/inner-group/dice").await;
+    app.update();
+    app.init_resource::<VoxelEditHistory>();
+    let scene_root = app.world_mut().spawn(SceneRoot(handle)).id();
+    app.update();
+    let entity = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("scene root");
+    let model_instance = app
+        .world()
+        .get::<VoxelModelInstance>(entity)
+        .expect("voxel model instance")
+        .clone();
+    let mesh = app
+        .world()
+        .get::<Mesh3d>(entity)
+        .expect("voxel mesh")
+        .clone();
+    let voxel_at = |app: &App, point: IVec3| {
+        app.world()
+            .resource::<Assets<VoxelModel>>()
+            .get(model_instance.model.id())
+            .expect("retrieve model from Res<Assets>")
+            .get_voxel_at_point(point)
+            .expect("Retrieve voxel")
+    };
+    let original = voxel_at(&app, IVec3::ZERO);
+    for voxel in [Voxel(7), Voxel(8)] {
+        let modifier = VoxelModifier::new(
+            model_instance.clone(),
+            mesh.0.clone(),
+            VoxelRegionMode::All,
+            move |_pos, _voxel, _model| voxel.clone(),
+        );
+        app.world_mut()
+            .run_system_cached_with(modify_voxel_model, Some(modifier))
+            .expect("model modified");
+    }
+    assert_eq!(voxel_at(&app, IVec3::ZERO), Voxel(8));
+    let model_id = Some(model_instance.model.id());
+    assert!(
+        app.world()
+            .resource::<VoxelEditHistory>()
+            .can_undo(model_id)
+    );
+
+    app.world_mut()
+        .run_system_cached_with(undo_voxel_edit, model_id)
+        .expect("edit undone");
+    assert_eq!(voxel_at(&app, IVec3::splat(3)), Voxel(7));
+    app.world_mut()
+        .run_system_cached_with(undo_voxel_edit, None)
+        .expect("edit undone");
+    assert_eq!(voxel_at(&app, IVec3::ZERO), original);
+    let history = app.world().resource::<VoxelEditHistory>();
+    assert!(!history.can_undo(None));
+    assert!(history.can_redo(model_id));
+    app.update();
+
+    app.world_mut()
+        .run_system_cached_with(redo_voxel_edit, None)
+        .expect("edit redone");
+    assert_eq!(voxel_at(&app, IVec3::ZERO), Voxel(7));
+
+    // a new modification discards the edits that could be redone
+    let modifier = VoxelModifier::new(
+        model_instance.clone(),
+        mesh.0.clone(),
+        VoxelRegionMode::Box(VoxelRegion {
+            origin: IVec3::ZERO,
+            size: IVec3::ONE,
+        }),
+        |_pos, _voxel, _model| Voxel::EMPTY,
+    );
+    app.world_mut()
+        .run_system_cached_with(modify_voxel_model, Some(modifier))
+        .expect("model modified");
+    let mut history = app.world_mut().resource_mut::<VoxelEditHistory>();
+    assert!(!history.can_redo(None));
+    assert!(history.memory_usage() > 0);
+
+    // the oldest edits are dropped once the history is over its budget
+    history.memory_budget = history.memory_usage() - 1;
+    let modifier = VoxelModifier::new(
+        model_instance.clone(),
+        mesh.0.clone(),
+        VoxelRegionMode::All,
+        |_pos, _voxel, _model| Voxel(1),
+    );
+    app.world_mut()
+        .run_system_cached_with(modify_voxel_model, Some(modifier))
+        .expect("model modified");
+    let history = app.world().resource::<VoxelEditHistory>();
+    assert!(history.memory_usage() <= history.memory_budget);
+    app.world_mut()
+        .run_system_cached_with(undo_voxel_edit, None)
+        .expect("edit undone");
+    assert_eq!(voxel_at(&app, IVec3::ZERO), Voxel::EMPTY);
+    assert_eq!(voxel_at(&app, IVec3::splat(3)), Voxel(7));
+}
+
+#[cfg(all(feature = "generate_voxels", feature = "modify_voxels"))]
+#[test]
+fn test_undo_chunked_resize() {
+    let mut app = App::new();
+    setup_app(&mut app);
+    app.init_resource::<VoxelEditHistory>();
+    let palette = VoxelPalette::from_colors(vec![bevy::color::palettes::css::GREEN.into()], true);
+    let mut data = VoxelData::new(
+        UVec3::splat(8),
+        VoxLoaderSettings {
+            supports_remeshing: true,
+            chunk_size: Some(4),
+            ..default()
+        },
+    );
+    data.set_voxel(Voxel(1), UVec3::ZERO);
+    let world = app.world_mut();
+    let context = world
+        .run_system_cached_with(create_voxel_context, palette)
+        .expect("Context has been created");
+    let scene_handle = world
+        .run_system_cached_with(create_voxel_scene, (data, "chunks".to_string(), context))
+        .expect("Add chunked model");
+    let scene_root = world.spawn(SceneRoot(scene_handle)).id();
+    app.update();
+    let entity = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("model entity");
+    let instance = app
+        .world()
+        .get::<VoxelModelInstance>(entity)
+        .expect("instance")
+        .clone();
+    let chunks = |app: &App| {
+        app.world()
+            .get::<VoxelChunks>(entity)
+            .expect("voxel chunks")
+            .meshes
+            .clone()
+    };
+    let chunk_children = |app: &App| {
+        app.world()
+            .get::<Children>(entity)
+            .expect("chunks")
+            .iter()
+            .filter(|child| app.world().get::<Mesh3d>(**child).is_some())
+            .count()
+    };
+    let original_chunks = chunks(&app);
+    let modifier = VoxelModifier::for_chunks(
+        instance.clone(),
+        &app.world()
+            .get::<VoxelChunks>(entity)
+            .expect("voxel chunks")
+            .clone(),
+        VoxelRegionMode::Box(VoxelRegion {
+            origin: IVec3::new(11, 0, 0),
+            size: IVec3::ONE,
+        }),
+        |_pos, _voxel, _model| Voxel(1),
+    )
+    .with_resize(VoxelResize::To {
+        size: UVec3::new(12, 8, 8),
+        anchor: UnitOffset::ZERO,
+    });
+    app.world_mut()
+        .run_system_cached_with(modify_voxel_model, Some(modifier))
+        .expect("model modified");
+    app.update();
+    let grown_chunks = chunks(&app);
+    assert_eq!(grown_chunks.len(), 12);
+
+    // undoing the resize removes the chunks it added
+    app.world_mut()
+        .run_system_cached_with(undo_voxel_edit, None)
+        .expect("edit undone");
+    app.update();
+    assert_eq!(chunks(&app), original_chunks);
+    assert_eq!(chunk_children(&app), 8);
+    let meshes = app.world().resource::<Assets<Mesh>>();
+    assert!(grown_chunks[8..].iter().all(|mesh| !meshes.contains(mesh)));
+
+    // redoing it adds them back once
+    app.world_mut()
+        .run_system_cached_with(redo_voxel_edit, None)
+        .expect("edit redone");
+    app.update();
+    assert_eq!(chunks(&app).len(), 12);
+    assert_eq!(chunk_children(&app), 12);
+    let model = app
+        .world()
+        .resource::<Assets<VoxelModel>>()
+        .get(&instance.model)
+        .expect("model");
+    assert_eq!(model.size(), IVec3::new(12, 8, 8));
+    assert_eq!(model.get_voxel_at_point(IVec3::new(11, 0, 0)), Ok(Voxel(1)));
+    assert_eq!(model.get_voxel_at_point(IVec3::ZERO), Ok(Voxel(1)));
+}
+
+#[cfg(all(feature = "generate_voxels", feature = "modify_voxels"))]
+#[test]
+fn test_create_voxel_animation() {
+    let mut app = App::new();
+    setup_app(&mut app);
+    app.register_type::<VisibilityRange>();
+    let palette = VoxelPalette::from_colors(vec![bevy::color::palettes::css::GREEN.into()], true);
+    let frames: Vec<VoxelData> = (0..2)
+        .map(|index| {
+            let mut data = VoxelData::new(
+                UVec3::splat(8),
+                VoxLoaderSettings {
+                    lod_distances: vec![10.0],
+                    ..default()
+                },
+            );
+            data.set_voxel(Voxel(1), UVec3::splat(index));
+            data
+        })
+        .collect();
+    let world = app.world_mut();
+    let context = world
+        .run_system_cached_with(create_voxel_context, palette)
+        .expect("Context has been created");
+    let scene_handle = world
+        .run_system_cached_with(
+            create_voxel_animation,
+            (frames, "animation".to_string(), context),
+        )
+        .expect("Add animation");
+    let scene_root = world.spawn(SceneRoot(scene_handle)).id();
+    app.update();
+    let root = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("animation root");
+    assert!(app.world().get::<VoxelAnimationPlayer>(root).is_some());
+    let frames = app.world().get::<Children>(root).expect("frames");
+    assert_eq!(frames.len(), 2);
+    for &frame in frames.iter() {
+        assert!(app.world().get::<VoxelModelInstance>(frame).is_some());
+        assert!(app.world().get::<Mesh3d>(frame).is_some());
+        assert!(
+            app.world().get::<VisibilityRange>(frame).is_some(),
+            "frames have levels of detail, like models made with create_voxel_scene"
+        );
+    }
+}
+
+#[cfg(feature = "generate_voxels")]
+#[test]
+fn test_generate_voxels() {
+    let mut app = App::new();
+    setup_app(&mut app);
     let palette = VoxelPalette::from_colors(vec![bevy::color::palettes::css::GREEN.into()], true);
     let tall_box = SDF::cuboid(Vec3::new(0.5, 2.5, 0.5)).voxelize(
         UVec3::splat(6),
@@ -393,38 +1413,1742 @@ fn test_generate_voxels() {
     let context = world
         .run_system_cached_with(create_voxel_context, palette)
         .expect("Context has been created");
-    let scene_handle = world
+    let scene_handle = world
+        .run_system_cached_with(
+            create_voxel_scene,
+            (tall_box, "tall box".to_string(), context),
+        )
+        .expect("Add box model");
+    let scene_root = world.spawn(SceneRoot(scene_handle)).id();
+    app.update();
+    let entity = app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("model entity");
+
+    let mesh_handle = &app.world().get::<Mesh3d>(*entity).expect("voxel mesh").0;
+    let mesh = app
+        .world()
+        .resource::<Assets<Mesh>>()
+        .get(mesh_handle)
+        .expect("mesh generated");
+    assert_eq!(
+        mesh.compute_aabb().expect("aabb").half_extents,
+        Vec3A::new(0.5, 2.5, 0.5)
+    );
+    assert_eq!(
+        mesh.count_vertices(),
+        6 * 4,
+        "resulting mesh should have 6 quads"
+    );
+}
+
+#[test]
+fn test_voxel_model_round_trip() {
+    let mut app = App::new();
+    setup_app(&mut app);
+    let palette = VoxelPalette::from_colors(vec![bevy::color::palettes::css::GREEN.into()], true);
+    let settings = VoxLoaderSettings {
+        mesh_offset: UnitOffset::CENTER_BASE,
+        lod_distances: vec![10.0],
+        chunk_size: Some(8),
+        ..default()
+    };
+    let tall_box =
+        SDF::cuboid(Vec3::new(0.5, 2.5, 0.5)).voxelize(UVec3::splat(6), settings, Voxel(1));
+    let model = VoxelModel {
+        name: "tall box".to_string(),
+        data: tall_box,
+        has_mesh: true,
+        has_cloud: false,
+    };
+    let mut bytes = Vec::new();
+    model.write(&mut bytes).expect("model written");
+    assert!(bytes.len() < 100, "voxels are run-length encoded");
+    let reloaded = VoxelModel::read(&bytes).expect("model read");
+    assert_eq!(reloaded.name, "tall box");
+    assert!(reloaded.has_mesh);
+    assert!(!reloaded.has_cloud);
+    assert_eq!(reloaded.data.voxels, model.data.voxels);
+    assert_eq!(reloaded.data._size(), model.data._size());
+    assert_eq!(
+        reloaded.data.settings.mesh_offset.0,
+        Vec3::new(0.5, 0.0, 0.5)
+    );
+    assert_eq!(reloaded.data.settings.lod_distances, vec![10.0]);
+    assert_eq!(reloaded.data.settings.chunk_size, Some(8));
+    assert!(matches!(
+        VoxelModel::read(&bytes[..bytes.len() - 1]),
+        Err(VoxelModelLoaderError::InvalidData(_))
+    ));
+    assert!(matches!(
+        VoxelModel::read(b"VOX 1234"),
+        Err(VoxelModelLoaderError::InvalidHeader)
+    ));
+
+    // version 1 files stored the raw index of each voxel as a byte, with 255 for empty voxels
+    let mut data = VoxelData::new(UVec3::new(2, 1, 1), VoxLoaderSettings::default());
+    data.set_voxel(Voxel(1), UVec3::ZERO);
+    let pair = VoxelModel {
+        name: "pair".to_string(),
+        data,
+        has_mesh: true,
+        has_cloud: false,
+    };
+    let mut v1_bytes = Vec::new();
+    pair.write(&mut v1_bytes).expect("model written");
+    let runs = v1_bytes.len() - 4;
+    assert_eq!(v1_bytes[runs..], [1, 1, 0, 1]);
+    v1_bytes[4..8].copy_from_slice(&1u32.to_le_bytes());
+    v1_bytes[runs..].copy_from_slice(&[0, 1, 255, 1]);
+    let restored = VoxelModel::read(&v1_bytes).expect("version 1 model read");
+    assert_eq!(restored.data.voxels, pair.data.voxels);
+    v1_bytes[4..8].copy_from_slice(&3u32.to_le_bytes());
+    assert!(matches!(
+        VoxelModel::read(&v1_bytes),
+        Err(VoxelModelLoaderError::UnsupportedVersion(3))
+    ));
+
+    // sizes that are empty, or too large to allocate, are rejected before the voxels are allocated
+    let mut data_bytes = Vec::new();
+    pair.data.write(&mut data_bytes).expect("data written");
+    assert_eq!(data_bytes[..3], [2, 1, 1]);
+    let u32_max = [0xff, 0xff, 0xff, 0xff, 0x0f];
+    for size in [
+        vec![0, 1, 1],
+        [u32_max; 3].concat(),
+        [[0x80, 0x08]; 3].concat(),
+    ] {
+        let corrupt = [size, data_bytes[3..].to_vec()].concat();
+        assert!(matches!(
+            VoxelData::read(&corrupt),
+            Err(VoxelModelLoaderError::InvalidData(_))
+        ));
+    }
+
+    // an instance restored without its meshes has them rebuilt
+    let world = app.world_mut();
+    let context = world
+        .run_system_cached_with(create_voxel_context, palette)
+        .expect("Context has been created");
+    let mut reloaded = reloaded;
+    reloaded.data.settings.chunk_size = None;
+    let model_handle = world.resource_mut::<Assets<VoxelModel>>().add(reloaded);
+    let [instance, other_instance] = [(); 2].map(|_| {
+        world
+            .spawn((
+                VoxelModelInstance::new(model_handle.clone(), context.clone()),
+                VoxelModelRestored,
+            ))
+            .id()
+    });
+    app.update();
+    assert!(
+        app.world().get::<VoxelModelRestored>(instance).is_none(),
+        "marker removed once rebuilt"
+    );
+    let mesh_handle = &app.world().get::<Mesh3d>(instance).expect("voxel mesh").0;
+    let mesh = app
+        .world()
+        .resource::<Assets<Mesh>>()
+        .get(mesh_handle)
+        .expect("mesh generated");
+    assert_eq!(mesh.count_vertices(), 6 * 4);
+    assert!(
+        app.world().get::<VisibilityRange>(instance).is_some(),
+        "levels of detail are rebuilt"
+    );
+    assert_eq!(
+        app.world().get::<Mesh3d>(other_instance),
+        app.world().get::<Mesh3d>(instance),
+        "instances of the same model share its mesh"
+    );
+    app.update();
+    assert_eq!(
+        app.world().resource::<Assets<Mesh>>().len(),
+        2,
+        "meshes are only rebuilt once"
+    );
+}
+
+#[cfg(feature = "modify_voxels")]
+#[async_std::test]
+async fn test_modify_voxels_async() {
+    let mut app = App::new();
+    let handle =
+        setup_and_load_voxel_scene(&mut app, "test.vox#outer-group/inner-group/dice").await;
+    app.update();
+    let scene_root = app.world_mut().spawn(SceneRoot(handle)).id();
+    app.update();
+    let entity = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("scene root");
+    let model_instance = app
+        .world()
+        .get::<VoxelModelInstance>(entity)
+        .expect("voxel model instance")
+        .clone();
+    let mesh = app
+        .world()
+        .get::<Mesh3d>(entity)
+        .expect("voxel mesh")
+        .0
+        .clone();
+    let first = VoxelModifier::new(
+        model_instance.clone(),
+        mesh.clone(),
+        VoxelRegionMode::Box(VoxelRegion {
+            origin: IVec3::splat(2),
+            size: IVec3::ONE,
+        }),
+        |_pos, _voxel, _model| Voxel(7),
+    );
+    // the second modification depends on the first having been applied
+    let second = VoxelModifier::new(
+        model_instance.clone(),
+        mesh.clone(),
+        VoxelRegionMode::Box(VoxelRegion {
+            origin: IVec3::splat(1),
+            size: IVec3::ONE,
+        }),
+        |_pos, voxel, model| {
+            if model.get_voxel_at_point(IVec3::splat(2)) == Ok(Voxel(7)) {
+                Voxel(8)
+            } else {
+                voxel.clone()
+            }
+        },
+    );
+    for modifier in [first, second] {
+        app.world_mut()
+            .run_system_cached_with(modify_voxel_model_async, Some(modifier))
+            .expect("modification queued");
+    }
+    let voxel_at = |app: &App, point: IVec3| {
+        app.world()
+            .resource::<Assets<VoxelModel>>()
+            .get(model_instance.model.id())
+            .expect("retrieve model from Res<Assets>")
+            .get_voxel_at_point(point)
+            .expect("Retrieve voxel")
+    };
+    assert_ne!(
+        voxel_at(&app, IVec3::splat(2)),
+        Voxel(7),
+        "model isn't modified until the task finishes"
+    );
+    let mut cursor = app
+        .world()
+        .resource::<bevy::ecs::event::Events<VoxelModelRemeshed>>()
+        .get_cursor();
+    let mut remeshed = 0;
+    for _ in 0..1000 {
+        app.update();
+        let events = app
+            .world()
+            .resource::<bevy::ecs::event::Events<VoxelModelRemeshed>>();
+        remeshed += cursor
+            .read(events)
+            .filter(|event| event.model == model_instance.model.id())
+            .count();
+        if remeshed == 2 {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(remeshed, 2, "an event is sent for each modification");
+    assert_eq!(voxel_at(&app, IVec3::splat(2)), Voxel(7));
+    assert_eq!(
+        voxel_at(&app, IVec3::splat(1)),
+        Voxel(8),
+        "modifications are applied in order"
+    );
+
+    let set = |point: IVec3, voxel: Voxel| {
+        VoxelModifier::new(
+            model_instance.clone(),
+            mesh.clone(),
+            VoxelRegionMode::Box(VoxelRegion {
+                origin: point,
+                size: IVec3::ONE,
+            }),
+            move |_pos, _voxel, _model| voxel.clone(),
+        )
+    };
+    app.world_mut()
+        .run_system_cached_with(
+            modify_voxel_model_async,
+            Some(set(IVec3::splat(3), Voxel(9))),
+        )
+        .expect("modification queued");
+    app.update();
+    // the model changes while the task is running
+    app.world_mut()
+        .run_system_cached_with(modify_voxel_model, Some(set(IVec3::ZERO, Voxel(5))))
+        .expect("model modified");
+    remeshed = 0;
+    for _ in 0..1000 {
+        app.update();
+        let events = app
+            .world()
+            .resource::<bevy::ecs::event::Events<VoxelModelRemeshed>>();
+        remeshed += cursor
+            .read(events)
+            .filter(|event| event.model == model_instance.model.id())
+            .count();
+        if remeshed == 1 {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(remeshed, 1);
+    assert_eq!(voxel_at(&app, IVec3::splat(3)), Voxel(9));
+    assert_eq!(
+        voxel_at(&app, IVec3::ZERO),
+        Voxel(5),
+        "changes made while the task ran aren't overwritten"
+    );
+
+    // a modification still lands if the model changes every frame
+    app.world_mut()
+        .run_system_cached_with(
+            modify_voxel_model_async,
+            Some(set(IVec3::splat(2), Voxel(4))),
+        )
+        .expect("modification queued");
+    remeshed = 0;
+    for frame in 0..1000 {
+        let voxel = Voxel(5 + frame % 2);
+        app.world_mut()
+            .run_system_cached_with(modify_voxel_model, Some(set(IVec3::ZERO, voxel)))
+            .expect("model modified");
+        app.update();
+        let events = app
+            .world()
+            .resource::<bevy::ecs::event::Events<VoxelModelRemeshed>>();
+        remeshed += cursor
+            .read(events)
+            .filter(|event| event.model == model_instance.model.id())
+            .count();
+        if remeshed == 1 {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    assert_eq!(remeshed, 1);
+    assert_eq!(voxel_at(&app, IVec3::splat(2)), Voxel(4));
+}
+
+#[cfg(all(feature = "generate_voxels", feature = "modify_voxels"))]
+#[test]
+fn test_modify_resyncs_instances() {
+    let mut app = App::new();
+    setup_app(&mut app);
+    let palette = VoxelPalette::new(
+        vec![
+            VoxelElement::default(),
+            VoxelElement {
+                translucency: 1.0,
+                refraction_index: 1.33,
+                ..default()
+            },
+            VoxelElement {
+                density: 0.5,
+                ..default()
+            },
+        ],
+        true,
+    );
+    let world = app.world_mut();
+    let context_handle = world
+        .run_system_cached_with(create_voxel_context, palette)
+        .expect("Context has been created");
+    let empty = VoxelData::new(UVec3::splat(4), VoxLoaderSettings::default());
+    let scene_handle = world
+        .run_system_cached_with(
+            create_voxel_scene,
+            (empty, "empty".to_string(), context_handle.clone()),
+        )
+        .expect("Add empty model");
+    let scene_root = world.spawn(SceneRoot(scene_handle)).id();
+    app.update();
+    let entity = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("model entity");
+    let instance = app
+        .world()
+        .get::<VoxelModelInstance>(entity)
+        .expect("instance")
+        .clone();
+    assert!(app.world().get::<Mesh3d>(entity).is_none());
+    let mesh_handle = app
+        .world_mut()
+        .resource_mut::<Assets<Mesh>>()
+        .reserve_handle();
+    let fill = |app: &mut App, voxel: Voxel| {
+        let modifier = VoxelModifier::new(
+            instance.clone(),
+            mesh_handle.clone(),
+            VoxelRegionMode::Box(VoxelRegion {
+                origin: IVec3::ZERO,
+                size: IVec3::new(4, 4, 2),
+            }),
+            move |_pos, _voxel, _model| voxel.clone(),
+        );
+        app.world_mut()
+            .run_system_cached_with(modify_voxel_model, Some(modifier))
+            .expect("model modified");
+        app.world()
+            .resource::<Assets<VoxelModel>>()
+            .get(&instance.model)
+            .expect("model")
+            .clone()
+    };
+    let material = |app: &App| {
+        app.world()
+            .get::<MeshMaterial3d<StandardMaterial>>(entity)
+            .expect("material")
+            .0
+            .clone()
+    };
+    let fog_volumes = |app: &App| {
+        app.world()
+            .get::<Children>(entity)
+            .map(|children| {
+                children
+                    .iter()
+                    .filter(|child| app.world().get::<FogVolume>(**child).is_some())
+                    .count()
+            })
+            .unwrap_or_default()
+    };
+
+    // a model that started empty gains a mesh
+    let model = fill(&mut app, Voxel(1));
+    assert!(model.has_mesh);
+    assert_eq!(
+        app.world().get::<Mesh3d>(entity).expect("mesh inserted").0,
+        mesh_handle
+    );
+    assert!(
+        app.world()
+            .resource::<Assets<Mesh>>()
+            .contains(&mesh_handle)
+    );
+    let context = app
+        .world()
+        .resource::<Assets<VoxelContext>>()
+        .get(&context_handle)
+        .expect("context")
+        .clone();
+    assert_eq!(material(&app), context.opaque_material);
+
+    // the material takes on the index of refraction when the model becomes transmissive
+    fill(&mut app, Voxel(2));
+    let transmissive = material(&app);
+    assert_ne!(transmissive, context.transmissive_material);
+    let transmissive_material = app
+        .world()
+        .resource::<Assets<StandardMaterial>>()
+        .get(&transmissive)
+        .expect("transmissive material");
+    assert!((transmissive_material.ior - 1.33).abs() < 0.001);
+    assert_eq!(transmissive_material.thickness, 4.0);
+
+    // cloud voxels add a fog volume, and the model reverts to the opaque material
+    let model = fill(&mut app, Voxel(3));
+    assert!(model.has_cloud);
+    assert!(!model.has_mesh);
+    assert!(app.world().get::<Mesh3d>(entity).is_none());
+    assert_eq!(fog_volumes(&app), 1);
+
+    // a model carved to empty loses its mesh and cloud
+    fill(&mut app, Voxel(1));
+    assert_eq!(material(&app), context.opaque_material);
+    assert_eq!(fog_volumes(&app), 0);
+    let model = fill(&mut app, Voxel::EMPTY);
+    assert!(!model.has_mesh);
+    assert!(!model.has_cloud);
+    assert!(app.world().get::<Mesh3d>(entity).is_none());
+}
+
+#[cfg(all(feature = "generate_voxels", feature = "modify_voxels"))]
+#[test]
+fn test_split_voxel_islands() {
+    let mut data = VoxelData::new(UVec3::new(6, 3, 3), VoxLoaderSettings::default());
+    data.set_voxel(Voxel(1), UVec3::new(0, 0, 0));
+    data.set_voxel(Voxel(1), UVec3::new(1, 0, 0));
+    data.set_voxel(Voxel(2), UVec3::new(2, 1, 0));
+    data.set_voxel(Voxel(1), UVec3::new(4, 0, 0));
+    data.set_voxel(Voxel(1), UVec3::new(4, 2, 2));
+    data.set_voxel(Voxel(1), UVec3::new(4, 1, 2));
+    assert_eq!(data.islands(VoxelConnectivity::Faces).len(), 4);
+    let islands = data.islands(VoxelConnectivity::Corners);
+    assert_eq!(islands.len(), 3);
+    let island = &islands[0];
+    assert_eq!(island.origin, IVec3::ZERO);
+    assert_eq!(island.data.size(), IVec3::new(3, 2, 1));
+    assert_eq!(
+        island.data.get_voxel_at_point(IVec3::new(2, 1, 0)),
+        Ok(Voxel(2))
+    );
+    assert_eq!(
+        island.data.voxel_coord_to_local_space(IVec3::new(2, 1, 0)) + island.translation,
+        data.voxel_coord_to_local_space(IVec3::new(2, 1, 0)),
+        "The island's voxels should stay in place"
+    );
+    assert_eq!(islands[2].origin, IVec3::new(4, 1, 2));
+
+    let mut app = App::new();
+    setup_app(&mut app);
+    let palette = VoxelPalette::from_colors(
+        vec![
+            bevy::color::palettes::css::GREEN.into(),
+            bevy::color::palettes::css::RED.into(),
+        ],
+        true,
+    );
+    let world = app.world_mut();
+    let context = world
+        .run_system_cached_with(create_voxel_context, palette)
+        .expect("Context has been created");
+    let scene_handle = world
+        .run_system_cached_with(create_voxel_scene, (data, "pieces".to_string(), context))
+        .expect("Add model");
+    let scene_root = world.spawn(SceneRoot(scene_handle)).id();
+    app.update();
+    let entity = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("model entity");
+    // the test app doesn't propagate transforms
+    app.world_mut()
+        .entity_mut(entity)
+        .insert(GlobalTransform::from_xyz(10.0, 0.0, 0.0));
+    let pieces = app
+        .world_mut()
+        .run_system_cached_with(split_voxel_islands, (entity, VoxelConnectivity::Corners))
+        .expect("Model split");
+    assert_eq!(pieces.len(), 3);
+    assert!(
+        app.world().get_entity(entity).is_err(),
+        "original despawned"
+    );
+    app.update();
+    let transform = app.world().get::<Transform>(pieces[0]).expect("transform");
+    // the island is 3x2x1 voxels at the minimum corner of the 6x3x3 model, which are both centered
+    assert_eq!(transform.translation, Vec3::new(8.5, -0.5, -1.0));
+    let piece = *app
+        .world()
+        .get::<Children>(pieces[0])
+        .expect("children")
+        .first()
+        .expect("island entity");
+    let instance = app
+        .world()
+        .get::<VoxelModelInstance>(piece)
+        .expect("island instance");
+    let model = app
+        .world()
+        .resource::<Assets<VoxelModel>>()
+        .get(&instance.model)
+        .expect("island model");
+    assert_eq!(model.name, "pieces-island0");
+    assert!(model.has_mesh);
+}
+
+#[cfg(all(feature = "generate_voxels", feature = "modify_voxels"))]
+#[test]
+fn test_shaped_regions() {
+    let mut app = App::new();
+    setup_app(&mut app);
+    let palette = VoxelPalette::from_colors(vec![bevy::color::palettes::css::GREEN.into()], true);
+    let data = VoxelData::new(UVec3::splat(16), VoxLoaderSettings::default());
+    let world = app.world_mut();
+    let context = world
+        .run_system_cached_with(create_voxel_context, palette)
+        .expect("Context has been created");
+    let scene_handle = world
+        .run_system_cached_with(create_voxel_scene, (data, "empty".to_string(), context))
+        .expect("Add empty model");
+    let scene_root = world.spawn(SceneRoot(scene_handle)).id();
+    app.update();
+    let entity = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("model entity");
+    let instance = app
+        .world()
+        .get::<VoxelModelInstance>(entity)
+        .expect("instance")
+        .clone();
+    let mesh = app
+        .world_mut()
+        .resource_mut::<Assets<Mesh>>()
+        .reserve_handle();
+    // returns the positions visited by the region, with their distances
+    let visit = |app: &mut App, region: VoxelRegionMode| {
+        let visited = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = visited.clone();
+        let modifier = VoxelModifier::new_with_distance(
+            instance.clone(),
+            mesh.clone(),
+            region,
+            move |position, voxel, distance, _model| {
+                recorded
+                    .lock()
+                    .expect("lock visited voxels")
+                    .push((position, distance));
+                voxel.clone()
+            },
+        );
+        app.world_mut()
+            .run_system_cached_with(modify_voxel_model, Some(modifier))
+            .expect("model modified");
+        std::mem::take(&mut *visited.lock().expect("lock visited voxels"))
+    };
+
+    let center = IVec3::splat(8);
+    let sphere = VoxelRegionMode::Sphere {
+        center: center.as_vec3(),
+        radius: 3.0,
+    };
+    assert_eq!(
+        sphere.bounds(),
+        Some(VoxelRegion {
+            origin: IVec3::splat(5),
+            size: IVec3::splat(7),
+        })
+    );
+    let visited = visit(&mut app, sphere);
+    let expected = (5..=11)
+        .flat_map(|x| (5..=11).flat_map(move |y| (5..=11).map(move |z| IVec3::new(x, y, z))))
+        .filter(|position| position.distance_squared(center) <= 9)
+        .count();
+    assert_eq!(
+        visited.len(),
+        expected,
+        "only voxels inside the sphere are visited"
+    );
+    assert!(visited.contains(&(center, -3.0)));
+    assert!(visited.contains(&(IVec3::new(11, 8, 8), 0.0)));
+
+    let capsule = visit(
+        &mut app,
+        VoxelRegionMode::Capsule {
+            start: Vec3::new(4.0, 8.0, 8.0),
+            end: Vec3::new(12.0, 8.0, 8.0),
+            radius: 1.0,
+        },
+    );
+    assert!(
+        capsule.contains(&(IVec3::new(3, 8, 8), 0.0)),
+        "rounded ends"
+    );
+    assert!(capsule.contains(&(IVec3::new(8, 8, 8), -1.0)));
+    assert!(
+        !capsule
+            .iter()
+            .any(|(position, _)| *position == IVec3::new(3, 9, 8))
+    );
+
+    let cylinder = visit(
+        &mut app,
+        VoxelRegionMode::Cylinder {
+            start: Vec3::new(4.0, 8.0, 8.0),
+            end: Vec3::new(12.0, 8.0, 8.0),
+            radius: 1.0,
+        },
+    );
+    assert!(
+        !cylinder.iter().any(|(position, _)| position.x == 3),
+        "flat ends"
+    );
+    assert!(cylinder.contains(&(IVec3::new(4, 9, 8), 0.0)));
+    assert_eq!(cylinder.len(), 9 * 5);
+
+    let sdf = visit(
+        &mut app,
+        VoxelRegionMode::Sdf {
+            sdf: SDF::cuboid(Vec3::ONE).translate(-center.as_vec3()),
+            bounds: VoxelRegion::from_center(center, IVec3::splat(2)),
+        },
+    );
+    assert_eq!(sdf.len(), 27);
+    assert!(sdf.contains(&(center, -1.0)));
+
+    let region = VoxelRegion {
+        origin: IVec3::splat(2),
+        size: IVec3::new(3, 1, 1),
+    };
+    let boxed = visit(&mut app, VoxelRegionMode::Box(region));
+    assert_eq!(boxed.len(), 3);
+    assert!(boxed.iter().all(|(_, distance)| *distance < 0.0));
+}
+
+#[cfg(feature = "modify_voxels")]
+#[test]
+fn test_resize_voxel_data() {
+    let mut data = VoxelData::new(UVec3::splat(4), VoxLoaderSettings::default());
+    data.set_voxel(Voxel(1), UVec3::ONE);
+    let local_position = data.voxel_coord_to_local_space(IVec3::ONE);
+    let shift = data.resize(UVec3::splat(6), UnitOffset::CENTER);
+    assert_eq!(shift, IVec3::ONE);
+    assert_eq!(data.size(), IVec3::splat(6));
+    assert_eq!(data.get_voxel_at_point(IVec3::splat(2)), Ok(Voxel(1)));
+    assert_eq!(
+        data.voxel_coord_to_local_space(IVec3::splat(2)),
+        local_position,
+        "Existing voxels shouldn't move"
+    );
+
+    let shift = data.resize(UVec3::new(2, 6, 6), UnitOffset::ZERO);
+    assert_eq!(shift, IVec3::ZERO);
+    assert_eq!(
+        data.get_voxel_at_point(IVec3::splat(2)),
+        Err(OutOfBoundsError)
+    );
+    assert_eq!(
+        data.voxels
+            .iter()
+            .filter(|voxel| *voxel != RawVoxel::EMPTY)
+            .count(),
+        0,
+        "Voxels outside the new bounds should be dropped"
+    );
+}
+
+#[cfg(all(feature = "generate_voxels", feature = "modify_voxels"))]
+#[test]
+fn test_sparse_voxel_storage() {
+    let sparse_settings = VoxLoaderSettings {
+        storage: VoxelStorage::Sparse,
+        ..default()
+    };
+    let dense = SDF::sphere(4.0).voxelize(UVec3::splat(48), VoxLoaderSettings::default(), Voxel(1));
+    let mut sparse = SDF::sphere(4.0).voxelize(UVec3::splat(48), sparse_settings.clone(), Voxel(1));
+    assert_eq!(sparse.storage(), VoxelStorage::Sparse);
+    assert_eq!(sparse.voxels, dense.voxels, "same voxels in either storage");
+    assert!(
+        sparse.memory_usage() * 10 < dense.memory_usage(),
+        "sparse storage only allocates the bricks that contain voxels"
+    );
+    let (dense_mesh, _, _) = dense.remesh(&[None; 256], &[None; 256]);
+    let (sparse_mesh, _, _) = sparse.remesh(&[None; 256], &[None; 256]);
+    assert_eq!(
+        sparse_mesh.expect("sparse mesh").count_vertices(),
+        dense_mesh.expect("dense mesh").count_vertices()
+    );
+
+    // a slab crossing the regions that sparse models are meshed in, and a voxel in a distant brick
+    let mut dense_slab = VoxelData::new(UVec3::new(80, 8, 8), VoxLoaderSettings::default());
+    let mut sparse_slab = VoxelData::new(UVec3::new(80, 8, 8), sparse_settings);
+    for slab in [&mut dense_slab, &mut sparse_slab] {
+        for x in 0..40 {
+            for y in 0..4 {
+                slab.set_voxel(Voxel(1), UVec3::new(x, y, 0));
+            }
+        }
+        slab.set_voxel(Voxel(1), UVec3::new(70, 7, 7));
+    }
+    let surface = |data: &VoxelData| {
+        let (mesh, _, _) = data.remesh(&[None; 256], &[None; 256]);
+        let mesh = mesh.expect("mesh");
+        let Some(VertexAttributeValues::Float32x3(positions)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            panic!("vertex positions");
+        };
+        let Some(bevy::render::mesh::Indices::U32(indices)) = mesh.indices() else {
+            panic!("mesh indices");
+        };
+        let area: f32 = indices
+            .chunks(3)
+            .map(|triangle| {
+                let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(positions[triangle[i] as usize]));
+                (b - a).cross(c - a).length() / 2.0
+            })
+            .sum();
+        (mesh.compute_aabb().expect("mesh bounds"), area)
+    };
+    assert_eq!(
+        surface(&sparse_slab),
+        surface(&dense_slab),
+        "sparse models are meshed by region with the same surface"
+    );
+
+    sparse.set_voxel(Voxel(2), UVec3::new(40, 40, 40));
+    assert_eq!(sparse.get_voxel_at_point(IVec3::splat(40)), Ok(Voxel(2)));
+    let with_voxel = sparse.memory_usage();
+    sparse.set_voxel(Voxel::EMPTY, UVec3::new(40, 40, 40));
+    assert!(
+        sparse.memory_usage() < with_voxel,
+        "bricks are freed when they're emptied"
+    );
+
+    let shift = sparse.resize(UVec3::splat(64), UnitOffset::ZERO);
+    assert_eq!(shift, IVec3::ZERO);
+    assert_eq!(sparse.storage(), VoxelStorage::Sparse);
+    assert_eq!(sparse.get_voxel_at_point(IVec3::splat(24)), Ok(Voxel(1)));
+    assert_eq!(
+        sparse.get_voxel_at_point(IVec3::splat(60)),
+        Ok(Voxel::EMPTY)
+    );
+}
+
+#[test]
+fn test_wide_palette() {
+    let colors: Vec<bevy::color::Color> = (0..300)
+        .map(|index| bevy::color::Color::srgb_u8(index as u8, 0, 0))
+        .collect();
+    let palette = VoxelPalette::from_colors(colors, true);
+    assert_eq!(
+        palette.elements.len(),
+        32 * 32,
+        "padded to fill the palette texture"
+    );
+    let mut images = Assets::<bevy::image::Image>::default();
+    let material = palette.create_material(&mut images);
+    let image = images
+        .get(&material.base_color_texture.expect("color texture"))
+        .expect("color image");
+    assert_eq!(image.size(), UVec2::splat(32));
+
+    let mut data = VoxelData::new(UVec3::new(2, 1, 1), VoxLoaderSettings::default());
+    data.set_voxel(Voxel(1), UVec3::ZERO);
+    assert!(!data.voxels.is_wide(), "8 bit voxels by default");
+    data.set_voxel(Voxel(300), UVec3::X);
+    assert!(data.voxels.is_wide(), "widened to fit the index");
+    assert_eq!(data.get_voxel_at_point(IVec3::ZERO), Ok(Voxel(1)));
+    assert_eq!(data.get_voxel_at_point(IVec3::X), Ok(Voxel(300)));
+
+    let (mesh, _, _) = data.remesh(&palette.indices_of_refraction, &palette.density_for_voxel);
+    let mesh = mesh.expect("mesh");
+    let Some(VertexAttributeValues::Float32x2(uvs)) = mesh.attribute(Mesh::ATTRIBUTE_UV_0) else {
+        panic!("Mesh should have UVs");
+    };
+    // Voxel(300) is element 299, which is in column 11, row 9 of the 32x32 palette texture
+    let expected = [11.5 / 32.0, 9.5 / 32.0];
+    assert!(uvs.contains(&expected), "UVs adapt to the texture size");
+    assert!(uvs.contains(&[0.5 / 32.0, 0.5 / 32.0]));
+
+    let narrow_palette = VoxelPalette::from_colors(vec![bevy::color::Color::WHITE], true);
+    let (mesh, _, _) = data.remesh(
+        &narrow_palette.indices_of_refraction,
+        &narrow_palette.density_for_voxel,
+    );
+    assert_eq!(
+        mesh.expect("mesh").count_vertices(),
+        10 * 4,
+        "voxels beyond the end of the palette are meshed as opaque"
+    );
+
+    let mut data = VoxelData::new(UVec3::new(3, 1, 1), VoxLoaderSettings::default());
+    data.set_voxel(Voxel(255), UVec3::ZERO);
+    data.set_voxel(Voxel(256), UVec3::X);
+    data.set_voxel(Voxel(300), UVec3::new(2, 0, 0));
+    let mut bytes = Vec::new();
+    VoxFile::new(data, palette)
+        .write(&mut bytes)
+        .expect("file written");
+    let saved = dot_vox::load_bytes(&bytes).expect("saved file parsed");
+    assert_eq!(
+        saved.models[0].voxels.len(),
+        1,
+        "only voxels within Magica Voxel's palette are saved"
+    );
+    assert_eq!(saved.models[0].voxels[0].i, 254);
+}
+
+#[cfg(feature = "generate_voxels")]
+#[test]
+fn test_edit_voxel_palette() {
+    let mut app = App::new();
+    setup_app(&mut app);
+    let palette = VoxelPalette::from_colors(
+        vec![
+            bevy::color::palettes::css::GREEN.into(),
+            bevy::color::palettes::css::RED.into(),
+        ],
+        true,
+    );
+    let cube = SDF::cuboid(Vec3::splat(1.5)).voxelize(
+        UVec3::splat(4),
+        VoxLoaderSettings::default(),
+        Voxel(1),
+    );
+    let world = app.world_mut();
+    let context = world
+        .run_system_cached_with(create_voxel_context, palette)
+        .expect("Context has been created");
+    let scene_handle = world
+        .run_system_cached_with(
+            create_voxel_scene,
+            (cube, "cube".to_string(), context.clone()),
+        )
+        .expect("Add model");
+    let scene_root = world.spawn(SceneRoot(scene_handle)).id();
+    app.update();
+    let entity = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("model entity");
+    let mesh = app.world().get::<Mesh3d>(entity).expect("mesh").0.clone();
+    let material = app
+        .world()
+        .get::<MeshMaterial3d<StandardMaterial>>(entity)
+        .expect("material")
+        .0
+        .clone();
+    let texel = |app: &App, texture: &Handle<bevy::image::Image>| {
+        let image = app
+            .world()
+            .resource::<Assets<bevy::image::Image>>()
+            .get(texture)
+            .expect("image");
+        image.data.as_ref().expect("image data")[..4].to_vec()
+    };
+    let color_texture = app
+        .world()
+        .resource::<Assets<StandardMaterial>>()
+        .get(&material)
+        .expect("material")
+        .base_color_texture
+        .clone()
+        .expect("color texture");
+    assert_eq!(texel(&app, &color_texture), vec![0, 128, 0, 255]);
+
+    let glowing_blue = VoxelElement {
+        color: bevy::color::palettes::css::BLUE.into(),
+        emission: 2.0,
+        ..default()
+    };
+    app.world_mut()
+        .run_system_cached_with(
+            edit_voxel_palette,
+            (context.clone(), vec![(Voxel(1), glowing_blue)]),
+        )
+        .expect("Edit palette");
+    assert_eq!(
+        texel(&app, &color_texture),
+        vec![0, 0, 255, 255],
+        "the image behind the existing handle is rewritten"
+    );
+    let edited = app
+        .world()
+        .resource::<Assets<StandardMaterial>>()
+        .get(&material)
+        .expect("material");
+    assert_eq!(edited.base_color_texture, Some(color_texture));
+    assert!(edited.emissive_texture.is_some(), "emission texture added");
+    assert_eq!(app.world().get::<Mesh3d>(entity).expect("mesh").0, mesh);
+    let contexts = app.world().resource::<Assets<VoxelContext>>();
+    let palette = &contexts.get(&context).expect("context").palette;
+    assert_eq!(palette.element(&Voxel(1)).expect("element").emission, 2.0);
+    assert_eq!(
+        palette.element(&Voxel(2)).expect("element").color,
+        bevy::color::palettes::css::RED.into()
+    );
+    assert!(palette.element(&Voxel::EMPTY).is_none());
+}
+
+#[cfg(feature = "generate_voxels")]
+#[test]
+fn test_voxel_palette_animation() {
+    let mut app = App::new();
+    setup_app(&mut app);
+    let palette = VoxelPalette::from_colors(
+        vec![
+            bevy::color::palettes::css::GREEN.into(),
+            bevy::color::palettes::css::RED.into(),
+            bevy::color::palettes::css::BLUE.into(),
+            bevy::color::palettes::css::BLACK.into(),
+        ],
+        true,
+    );
+    let world = app.world_mut();
+    let context = world
+        .run_system_cached_with(create_voxel_context, palette)
+        .expect("Context has been created");
+    let half_period = Duration::from_secs(1);
+    let mut animation = VoxelPaletteAnimation::new(
+        context.clone(),
+        vec![
+            VoxelPaletteEffect::Cycle {
+                first: Voxel(1),
+                last: Voxel(2),
+                period: half_period * 2,
+            },
+            VoxelPaletteEffect::Pulse {
+                voxel: Voxel(3),
+                min: 0.0,
+                max: 4.0,
+                period: half_period * 2,
+            },
+            VoxelPaletteEffect::Lerp {
+                voxel: Voxel(4),
+                from: VoxelElement::default(),
+                to: VoxelElement {
+                    color: bevy::color::palettes::css::WHITE.into(),
+                    ..default()
+                },
+                period: half_period * 2,
+            },
+        ],
+    );
+    animation.is_paused = true;
+    let animation_entity = world.spawn(animation).id();
+    let color_texture = world
+        .resource::<Assets<StandardMaterial>>()
+        .get(
+            &world
+                .resource::<Assets<VoxelContext>>()
+                .get(&context)
+                .expect("context")
+                .opaque_material,
+        )
+        .expect("material")
+        .base_color_texture
+        .clone()
+        .expect("color texture");
+    let texel = |app: &App, index: usize| {
+        let image = app
+            .world()
+            .resource::<Assets<bevy::image::Image>>()
+            .get(&color_texture)
+            .expect("image");
+        image.data.as_ref().expect("image data")[index * 4..index * 4 + 4].to_vec()
+    };
+    app.update();
+    assert_eq!(texel(&app, 0), vec![0, 128, 0, 255], "paused");
+
+    let mut animation = app
+        .world_mut()
+        .get_mut::<VoxelPaletteAnimation>(animation_entity)
+        .expect("animation");
+    animation.is_paused = false;
+    animation.elapsed = half_period;
+    app.update();
+    assert_eq!(texel(&app, 0), vec![255, 0, 0, 255], "cycled by one step");
+    assert_eq!(texel(&app, 1), vec![0, 128, 0, 255], "cycled by one step");
+    assert_eq!(texel(&app, 3), vec![255, 255, 255, 255], "blended to white");
+    let emission = |app: &App| {
+        let materials = app.world().resource::<Assets<StandardMaterial>>();
+        let contexts = app.world().resource::<Assets<VoxelContext>>();
+        let material = materials
+            .get(&contexts.get(&context).expect("context").opaque_material)
+            .expect("material");
+        let image = app
+            .world()
+            .resource::<Assets<bevy::image::Image>>()
+            .get(
+                material
+                    .emissive_texture
+                    .as_ref()
+                    .expect("emission texture"),
+            )
+            .expect("image");
+        let data = image.data.as_ref().expect("image data");
+        // the red and blue channels of the third element
+        [0, 8].map(|channel| {
+            f32::from_le_bytes(data[32 + channel..36 + channel].try_into().expect("f32"))
+        })
+    };
+    assert_eq!(
+        emission(&app),
+        [0.0, 4.0],
+        "pulse peaks half way through its period, adding an emission texture"
+    );
+    let contexts = app.world().resource::<Assets<VoxelContext>>();
+    let palette = &contexts.get(&context).expect("context").palette;
+    assert_eq!(
+        palette.element(&Voxel(3)).expect("element").emission,
+        0.0,
+        "the context's palette isn't animated"
+    );
+
+    // edits made while the animation plays are kept, with the effects applied on top of them
+    let white = VoxelElement {
+        color: bevy::color::palettes::css::WHITE.into(),
+        ..default()
+    };
+    app.world_mut()
         .run_system_cached_with(
-            create_voxel_scene,
-            (tall_box, "tall box".to_string(), context),
+            edit_voxel_palette,
+            (context.clone(), vec![(Voxel(3), white)]),
         )
-        .expect("Add box model");
+        .expect("Edit palette");
+    app.update();
+    assert!(
+        emission(&app)
+            .iter()
+            .all(|channel| (channel - 4.0).abs() < 0.01),
+        "the pulse is applied to the edited color"
+    );
+    assert_eq!(texel(&app, 0), vec![255, 0, 0, 255], "still cycled");
+
+    app.world_mut().despawn(animation_entity);
+    assert_eq!(texel(&app, 0), vec![0, 128, 0, 255], "palette restored");
+    assert_eq!(texel(&app, 2), vec![255, 255, 255, 255], "edit kept");
+    let materials = app.world().resource::<Assets<StandardMaterial>>();
+    let contexts = app.world().resource::<Assets<VoxelContext>>();
+    let material = materials
+        .get(&contexts.get(&context).expect("context").opaque_material)
+        .expect("material");
+    assert!(material.emissive_texture.is_none(), "nothing is emissive");
+}
+
+#[cfg(all(feature = "generate_voxels", feature = "modify_voxels"))]
+#[test]
+fn test_modify_resizes_model() {
+    let mut app = App::new();
+    setup_app(&mut app);
+    app.init_resource::<VoxelEditHistory>();
+    let palette = VoxelPalette::from_colors(vec![bevy::color::palettes::css::GREEN.into()], true);
+    let cube = |app: &mut App, size: UVec3, settings: VoxLoaderSettings| {
+        let mut data = VoxelData::new(size, settings);
+        for z in 0..size.z {
+            for y in 0..size.y {
+                for x in 0..size.x {
+                    data.set_voxel(Voxel(1), UVec3::new(x, y, z));
+                }
+            }
+        }
+        let world = app.world_mut();
+        let context = world
+            .run_system_cached_with(create_voxel_context, palette.clone())
+            .expect("Context has been created");
+        let scene_handle = world
+            .run_system_cached_with(create_voxel_scene, (data, "cube".to_string(), context))
+            .expect("Add cube model");
+        let scene_root = world.spawn(SceneRoot(scene_handle)).id();
+        app.update();
+        let entity = *app
+            .world()
+            .get::<Children>(scene_root)
+            .expect("children")
+            .first()
+            .expect("model entity");
+        let instance = app
+            .world()
+            .get::<VoxelModelInstance>(entity)
+            .expect("instance")
+            .clone();
+        (entity, instance)
+    };
+    let model = |app: &App, instance: &VoxelModelInstance| {
+        app.world()
+            .resource::<Assets<VoxelModel>>()
+            .get(&instance.model)
+            .expect("model")
+            .clone()
+    };
+
+    // a model grows to fit a voxel added below its minimum corner
+    let (entity, instance) = cube(&mut app, UVec3::splat(4), VoxLoaderSettings::default());
+    let mesh = app.world().get::<Mesh3d>(entity).expect("mesh").0.clone();
+    let modifier = VoxelModifier::new(
+        instance.clone(),
+        mesh.clone(),
+        VoxelRegionMode::Box(VoxelRegion {
+            origin: IVec3::ZERO,
+            size: IVec3::ONE,
+        }),
+        |_pos, _voxel, _model| Voxel::EMPTY,
+    );
+    app.world_mut()
+        .run_system_cached_with(modify_voxel_model, Some(modifier))
+        .expect("model modified");
+    let modifier = VoxelModifier::new(
+        instance.clone(),
+        mesh.clone(),
+        VoxelRegionMode::Box(VoxelRegion {
+            origin: IVec3::new(-2, 0, 0),
+            size: IVec3::ONE,
+        }),
+        |pos, _voxel, _model| {
+            assert_eq!(
+                pos,
+                IVec3::ZERO,
+                "positions are in the resized model's space"
+            );
+            Voxel(1)
+        },
+    )
+    .with_resize(VoxelResize::ToFit);
+    app.world_mut()
+        .run_system_cached_with(modify_voxel_model, Some(modifier))
+        .expect("model modified");
+    let resized = model(&app, &instance);
+    assert_eq!(resized.size(), IVec3::new(6, 4, 4));
+    assert_eq!(resized.get_voxel_at_point(IVec3::ZERO), Ok(Voxel(1)));
+    assert_eq!(
+        resized.get_voxel_at_point(IVec3::new(2, 0, 0)),
+        Ok(Voxel::EMPTY)
+    );
+    let aabb = app
+        .world()
+        .resource::<Assets<Mesh>>()
+        .get(&mesh)
+        .expect("mesh")
+        .compute_aabb()
+        .expect("aabb");
+    assert_eq!(aabb.min(), Vec3A::new(-4.0, -2.0, -2.0));
+    assert_eq!(
+        aabb.max(),
+        Vec3A::splat(2.0),
+        "The existing voxels shouldn't move"
+    );
+
+    // undoing a resize restores the size of the model, and edits recorded before it are moved with its voxels
+    app.world_mut()
+        .run_system_cached_with(undo_voxel_edit, None)
+        .expect("edit undone");
+    let restored = model(&app, &instance);
+    assert_eq!(restored.size(), IVec3::splat(4));
+    assert_eq!(restored.get_voxel_at_point(IVec3::ZERO), Ok(Voxel::EMPTY));
+    let mesh_aabb = |app: &App| {
+        app.world()
+            .resource::<Assets<Mesh>>()
+            .get(&mesh)
+            .expect("mesh")
+            .compute_aabb()
+            .expect("aabb")
+    };
+    assert_eq!(mesh_aabb(&app).min(), Vec3A::splat(-2.0));
+    assert_eq!(mesh_aabb(&app).max(), Vec3A::splat(2.0));
+    app.world_mut()
+        .run_system_cached_with(undo_voxel_edit, None)
+        .expect("edit undone");
+    assert_eq!(
+        model(&app, &instance).get_voxel_at_point(IVec3::ZERO),
+        Ok(Voxel(1))
+    );
+    for _ in 0..2 {
+        app.world_mut()
+            .run_system_cached_with(redo_voxel_edit, None)
+            .expect("edit redone");
+    }
+    let redone = model(&app, &instance);
+    assert_eq!(redone.size(), IVec3::new(6, 4, 4));
+    assert_eq!(redone.get_voxel_at_point(IVec3::ZERO), Ok(Voxel(1)));
+    assert_eq!(
+        redone.get_voxel_at_point(IVec3::new(2, 0, 0)),
+        Ok(Voxel::EMPTY)
+    );
+    assert_eq!(mesh_aabb(&app).min(), Vec3A::new(-4.0, -2.0, -2.0));
+
+    // undoing a shrink restores the voxels that it dropped
+    let modifier = VoxelModifier::new(
+        instance.clone(),
+        mesh.clone(),
+        VoxelRegionMode::All,
+        |_pos, voxel, _model| voxel.clone(),
+    )
+    .with_resize(VoxelResize::To {
+        size: UVec3::splat(2),
+        anchor: UnitOffset::CENTER,
+    });
+    app.world_mut()
+        .run_system_cached_with(modify_voxel_model, Some(modifier))
+        .expect("model modified");
+    assert_eq!(model(&app, &instance).size(), IVec3::splat(2));
+    app.world_mut()
+        .run_system_cached_with(undo_voxel_edit, None)
+        .expect("edit undone");
+    let restored = model(&app, &instance);
+    assert_eq!(restored.size(), redone.size());
+    for x in 0..6 {
+        for y in 0..4 {
+            for z in 0..4 {
+                let point = IVec3::new(x, y, z);
+                assert_eq!(
+                    restored.get_voxel_at_point(point),
+                    redone.get_voxel_at_point(point)
+                );
+            }
+        }
+    }
+
+    // resizing a chunked model adds chunks
+    let (entity, instance) = cube(
+        &mut app,
+        UVec3::splat(8),
+        VoxLoaderSettings {
+            supports_remeshing: true,
+            chunk_size: Some(4),
+            ..default()
+        },
+    );
+    let chunks = app
+        .world()
+        .get::<VoxelChunks>(entity)
+        .expect("voxel chunks")
+        .clone();
+    let modifier = VoxelModifier::for_chunks(
+        instance.clone(),
+        &chunks,
+        VoxelRegionMode::Box(VoxelRegion {
+            origin: IVec3::new(11, 0, 0),
+            size: IVec3::ONE,
+        }),
+        |_pos, _voxel, _model| Voxel(1),
+    )
+    .with_resize(VoxelResize::To {
+        size: UVec3::new(12, 8, 8),
+        anchor: UnitOffset::ZERO,
+    });
+    app.world_mut()
+        .run_system_cached_with(modify_voxel_model, Some(modifier))
+        .expect("model modified");
+    app.update();
+    let chunks = app
+        .world()
+        .get::<VoxelChunks>(entity)
+        .expect("voxel chunks");
+    assert_eq!(chunks.meshes.len(), 12, "3x2x2 chunks");
+    assert_eq!(
+        app.world().get::<Children>(entity).expect("chunks").len(),
+        12
+    );
+    let meshes = app.world().resource::<Assets<Mesh>>();
+    assert!(chunks.meshes.iter().all(|mesh| meshes.contains(mesh)));
+    assert_eq!(
+        model(&app, &instance).get_voxel_at_point(IVec3::new(11, 0, 0)),
+        Ok(Voxel(1))
+    );
+
+    // undoing resizes of a chunked model restores its size and chunks
+    let chunk_children = |app: &App| {
+        app.world()
+            .get::<Children>(entity)
+            .expect("chunks")
+            .iter()
+            .filter(|child| app.world().get::<Mesh3d>(**child).is_some())
+            .count()
+    };
+    app.world_mut()
+        .run_system_cached_with(undo_voxel_edit, None)
+        .expect("edit undone");
+    app.update();
+    assert_eq!(model(&app, &instance).size(), IVec3::splat(8));
+    let chunks = app
+        .world()
+        .get::<VoxelChunks>(entity)
+        .expect("voxel chunks")
+        .clone();
+    assert_eq!(chunks.meshes.len(), 8, "2x2x2 chunks");
+    assert_eq!(chunk_children(&app), 8);
+    let modifier = VoxelModifier::for_chunks(
+        instance.clone(),
+        &chunks,
+        VoxelRegionMode::All,
+        |_pos, voxel, _model| voxel.clone(),
+    )
+    .with_resize(VoxelResize::To {
+        size: UVec3::new(4, 8, 8),
+        anchor: UnitOffset::ZERO,
+    });
+    app.world_mut()
+        .run_system_cached_with(modify_voxel_model, Some(modifier))
+        .expect("model modified");
+    app.update();
+    assert_eq!(chunk_children(&app), 4);
+    app.world_mut()
+        .run_system_cached_with(undo_voxel_edit, None)
+        .expect("edit undone");
+    app.update();
+    assert_eq!(chunk_children(&app), 8);
+    assert_eq!(
+        app.world()
+            .get::<VoxelChunks>(entity)
+            .expect("voxel chunks")
+            .meshes
+            .len(),
+        8
+    );
+    let restored = model(&app, &instance);
+    assert_eq!(restored.size(), IVec3::splat(8));
+    assert_eq!(
+        restored.get_voxel_at_point(IVec3::splat(7)),
+        Ok(Voxel(1)),
+        "the voxels dropped by the shrink are restored"
+    );
+}
+
+#[cfg(all(feature = "generate_voxels", feature = "modify_voxels"))]
+#[test]
+fn test_chunked_remeshing() {
+    let mut app = App::new();
+    setup_app(&mut app);
+    let palette = VoxelPalette::from_colors(vec![bevy::color::palettes::css::GREEN.into()], true);
+    let mut data = VoxelData::new(
+        UVec3::splat(8),
+        VoxLoaderSettings {
+            supports_remeshing: true,
+            chunk_size: Some(4),
+            ..default()
+        },
+    );
+    for z in 0..8 {
+        for y in 0..8 {
+            for x in 0..8 {
+                data.set_voxel(Voxel(1), UVec3::new(x, y, z));
+            }
+        }
+    }
+    let world = app.world_mut();
+    let context = world
+        .run_system_cached_with(create_voxel_context, palette)
+        .expect("Context has been created");
+    let scene_handle = world
+        .run_system_cached_with(create_voxel_scene, (data, "cube".to_string(), context))
+        .expect("Add cube model");
     let scene_root = world.spawn(SceneRoot(scene_handle)).id();
     app.update();
-    let entity = app
+    let entity = *app
         .world()
         .get::<Children>(scene_root)
         .expect("children")
         .first()
         .expect("model entity");
+    assert!(app.world().get::<Mesh3d>(entity).is_none());
+    assert_eq!(
+        app.world().get::<Children>(entity).expect("chunks").len(),
+        8
+    );
+    let chunks = app
+        .world()
+        .get::<VoxelChunks>(entity)
+        .expect("voxel chunks")
+        .clone();
+    assert_eq!(chunks.meshes.len(), 8, "2x2x2 chunks");
+    let meshes = app.world().resource::<Assets<Mesh>>();
+    let vertex_count = |meshes: &Assets<Mesh>, index: usize| {
+        meshes
+            .get(&chunks.meshes[index])
+            .expect("chunk mesh")
+            .count_vertices()
+    };
+    assert!(
+        (0..8).all(|index| vertex_count(meshes, index) == 3 * 4),
+        "each chunk has 3 outer faces, and no faces between chunks"
+    );
+    let bounds = chunks.meshes.iter().fold(Aabb::default(), |bounds, mesh| {
+        let aabb = meshes.get(mesh).unwrap().compute_aabb().expect("aabb");
+        Aabb::from_min_max(
+            bounds.min().min(aabb.min()).into(),
+            bounds.max().max(aabb.max()).into(),
+        )
+    });
+    assert_eq!(bounds.half_extents, Vec3A::splat(4.0));
+    assert_eq!(bounds.center, Vec3A::ZERO);
 
-    let mesh_handle = &app.world().get::<Mesh3d>(*entity).expect("voxel mesh").0;
-    let mesh = app
+    // a chunk that isn't affected by the modification shouldn't be remeshed
+    app.world_mut().resource_mut::<Assets<Mesh>>().insert(
+        &chunks.meshes[7],
+        Mesh::new(PrimitiveTopology::TriangleList, default()),
+    );
+    let instance = app
         .world()
-        .resource::<Assets<Mesh>>()
-        .get(mesh_handle)
-        .expect("mesh generated");
+        .get::<VoxelModelInstance>(entity)
+        .expect("instance")
+        .clone();
+    // removing a voxel on the border of chunk 0 exposes a face of chunk 1
+    let modifier = VoxelModifier::for_chunks(
+        instance,
+        &chunks,
+        VoxelRegionMode::Box(VoxelRegion {
+            origin: IVec3::new(3, 0, 0),
+            size: IVec3::ONE,
+        }),
+        |_pos, _voxel, _model| Voxel::EMPTY,
+    );
+    app.world_mut()
+        .run_system_cached_with(modify_voxel_model, Some(modifier))
+        .expect("model modified");
+    let meshes = app.world().resource::<Assets<Mesh>>();
+    assert_eq!(vertex_count(meshes, 1), 4 * 4);
+    assert!(vertex_count(meshes, 0) > 3 * 4);
+    assert_eq!(vertex_count(meshes, 2), 3 * 4);
+    assert_eq!(vertex_count(meshes, 7), 0);
+}
+
+#[cfg(feature = "generate_voxels")]
+#[test]
+fn test_split_transmissive_by_ior() {
+    let palette = VoxelPalette::new(
+        vec![
+            VoxelElement::default(),
+            VoxelElement {
+                translucency: 1.0,
+                refraction_index: 1.5,
+                ..default()
+            },
+            VoxelElement {
+                translucency: 1.0,
+                refraction_index: 1.33,
+                ..default()
+            },
+        ],
+        true,
+    );
+    let transmissive_iors = |ior_bucket_size: Option<f32>| {
+        let mut app = App::new();
+        setup_app(&mut app);
+        let mut data = VoxelData::new(
+            UVec3::new(3, 1, 1),
+            VoxLoaderSettings {
+                ior_bucket_size,
+                ..default()
+            },
+        );
+        for x in 0..3 {
+            data.set_voxel(Voxel(x as u16 + 1), UVec3::new(x, 0, 0));
+        }
+        let world = app.world_mut();
+        let context = world
+            .run_system_cached_with(create_voxel_context, palette.clone())
+            .expect("Context has been created");
+        let scene_handle = world
+            .run_system_cached_with(create_voxel_scene, (data, "glass".to_string(), context))
+            .expect("Add model");
+        let scene_root = world.spawn(SceneRoot(scene_handle)).id();
+        app.update();
+        let entity = *app
+            .world()
+            .get::<Children>(scene_root)
+            .expect("children")
+            .first()
+            .expect("model entity");
+        let materials = app.world().resource::<Assets<StandardMaterial>>();
+        let material = |entity| {
+            let handle = &app
+                .world()
+                .get::<MeshMaterial3d<StandardMaterial>>(entity)
+                .expect("material")
+                .0;
+            materials.get(handle).expect("material exists").clone()
+        };
+        let mut iors: Vec<f32> = app
+            .world()
+            .get::<Children>(entity)
+            .map(|children| children.iter().map(|child| material(*child).ior).collect())
+            .unwrap_or_default();
+        iors.sort_by(f32::total_cmp);
+        (material(entity).ior, iors)
+    };
+
+    let (_, iors) = transmissive_iors(None);
+    assert!(iors.is_empty(), "IoRs are averaged by default");
+
+    let (ior, iors) = transmissive_iors(Some(0.0));
     assert_eq!(
-        mesh.compute_aabb().expect("aabb").half_extents,
-        Vec3A::new(0.5, 2.5, 0.5)
+        ior,
+        StandardMaterial::default().ior,
+        "the model's own mesh is opaque"
     );
+    assert_eq!(iors, vec![1.33, 1.5], "a mesh for each distinct IoR");
+
+    let (_, iors) = transmissive_iors(Some(1.0));
     assert_eq!(
-        mesh.count_vertices(),
-        6 * 4,
-        "resulting mesh should have 6 quads"
+        iors,
+        vec![(1.33 + 1.5) / 2.0],
+        "IoRs in the same bucket are averaged"
+    );
+}
+
+#[cfg(all(feature = "generate_voxels", feature = "modify_voxels"))]
+#[test]
+fn test_modify_split_transmissive() {
+    let mut app = App::new();
+    setup_app(&mut app);
+    let palette = VoxelPalette::new(
+        vec![
+            VoxelElement::default(),
+            VoxelElement {
+                translucency: 1.0,
+                refraction_index: 1.5,
+                ..default()
+            },
+            VoxelElement {
+                translucency: 1.0,
+                refraction_index: 1.33,
+                ..default()
+            },
+        ],
+        true,
+    );
+    let mut data = VoxelData::new(
+        UVec3::new(3, 1, 1),
+        VoxLoaderSettings {
+            ior_bucket_size: Some(0.0),
+            ..default()
+        },
+    );
+    for x in 0..3 {
+        data.set_voxel(Voxel(x as u16 + 1), UVec3::new(x, 0, 0));
+    }
+    let world = app.world_mut();
+    let context = world
+        .run_system_cached_with(create_voxel_context, palette)
+        .expect("Context has been created");
+    let scene_handle = world
+        .run_system_cached_with(create_voxel_scene, (data, "glass".to_string(), context))
+        .expect("Add model");
+    let scene_root = world.spawn(SceneRoot(scene_handle)).id();
+    app.update();
+    let entity = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("model entity");
+    let instance = app
+        .world()
+        .get::<VoxelModelInstance>(entity)
+        .expect("instance")
+        .clone();
+    let mesh_handle = app.world().get::<Mesh3d>(entity).expect("mesh").0.clone();
+    let set = |app: &mut App, x: i32, voxel: Voxel| {
+        let modifier = VoxelModifier::new(
+            instance.clone(),
+            mesh_handle.clone(),
+            VoxelRegionMode::Box(VoxelRegion {
+                origin: IVec3::new(x, 0, 0),
+                size: IVec3::ONE,
+            }),
+            move |_pos, _voxel, _model| voxel.clone(),
+        );
+        app.world_mut()
+            .run_system_cached_with(modify_voxel_model, Some(modifier))
+            .expect("model modified");
+    };
+    let transmissive_iors = |app: &App| {
+        let materials = app.world().resource::<Assets<StandardMaterial>>();
+        let mut iors: Vec<f32> = app
+            .world()
+            .get::<Children>(entity)
+            .map(|children| {
+                children
+                    .iter()
+                    .filter_map(|child| app.world().get::<MeshMaterial3d<StandardMaterial>>(*child))
+                    .map(|material| materials.get(&material.0).expect("material exists").ior)
+                    .collect()
+            })
+            .unwrap_or_default();
+        iors.sort_by(f32::total_cmp);
+        iors
+    };
+    assert_eq!(transmissive_iors(&app), vec![1.33, 1.5]);
+
+    set(&mut app, 2, Voxel(1));
+    assert_eq!(
+        transmissive_iors(&app),
+        vec![1.5],
+        "the mesh of an IoR that is no longer used is removed"
+    );
+
+    set(&mut app, 0, Voxel(3));
+    assert_eq!(
+        transmissive_iors(&app),
+        vec![1.33, 1.5],
+        "a mesh is added for a new IoR"
     );
 }
 
+#[cfg(feature = "modify_voxels")]
+#[async_std::test]
+async fn test_voxel_collider() {
+    let palette = VoxelPalette::new(
+        vec![
+            VoxelElement::default(),
+            VoxelElement {
+                translucency: 1.0,
+                ..default()
+            },
+            VoxelElement {
+                density: 1.0,
+                ..default()
+            },
+        ],
+        true,
+    );
+    let mut data = VoxelData::new(UVec3::new(3, 2, 2), VoxLoaderSettings::default());
+    for x in 0..2 {
+        for y in 0..2 {
+            for z in 0..2 {
+                data.set_voxel(Voxel(1), UVec3::new(x, y, z));
+            }
+        }
+    }
+    data.set_voxel(Voxel(2), UVec3::new(2, 0, 0));
+    data.set_voxel(Voxel(3), UVec3::new(2, 1, 0));
+    let volume = |collider: &VoxelCollider| -> u32 {
+        collider
+            .voxel_boxes
+            .iter()
+            .map(|voxel_box| (voxel_box.max - voxel_box.min).element_product())
+            .sum()
+    };
+    let collider = data.collider(&palette, &VoxelColliderSettings::default());
+    assert_eq!(volume(&collider), 9, "cloud voxels are excluded by default");
+    let collider = data.collider(
+        &palette,
+        &VoxelColliderSettings {
+            exclude_transmissive: true,
+            ..default()
+        },
+    );
+    assert_eq!(
+        collider.voxel_boxes,
+        vec![VoxelBox {
+            min: UVec3::ZERO,
+            max: UVec3::splat(2)
+        }],
+        "a solid block is a single box"
+    );
+    assert_eq!(collider.boxes[0].min, Vec3A::new(-1.5, -1.0, -1.0));
+    assert_eq!(collider.boxes[0].max, Vec3A::new(0.5, 1.0, 1.0));
+
+    let mut app = App::new();
+    setup_app_with_settings(
+        &mut app,
+        Some(VoxLoaderSettings {
+            colliders: Some(VoxelColliderSettings::default()),
+            ..default()
+        }),
+    );
+    let handle = app
+        .world()
+        .resource::<AssetServer>()
+        .load_untyped_async("test.vox#outer-group/inner-group/walls@collider")
+        .await
+        .expect("Loaded collider")
+        .typed::<VoxelCollider>();
+    app.update();
+    let collider = app
+        .world()
+        .resource::<Assets<VoxelCollider>>()
+        .get(&handle)
+        .expect("collider");
+    assert!(!collider.boxes.is_empty());
+}
+
 #[cfg(feature = "generate_voxels")]
 #[test]
 fn test_sdf_intersect() {
@@ -482,8 +3206,144 @@ fn test_voxel_queryable() {
     );
 }
 
+#[cfg(feature = "modify_voxels")]
+#[test]
+fn test_voxel_raycast() {
+    let mut data = VoxelData::new(UVec3::splat(4), VoxLoaderSettings::default());
+    data.set_voxel(Voxel(7), UVec3::new(1, 2, 3));
+    let global_xform = GlobalTransform::from(
+        Transform::from_xyz(10.0, 0.0, 0.0)
+            .with_rotation(Quat::from_rotation_y(FRAC_PI_2))
+            .with_scale(Vec3::splat(2.0)),
+    );
+    let voxel_center =
+        global_xform.transform_point(data.voxel_coord_to_local_space(IVec3::new(1, 2, 3)) + 0.5);
+    let hit = data
+        .raycast(
+            Ray3d::new(voxel_center + Vec3::Y * 20.0, Dir3::NEG_Y),
+            &global_xform,
+        )
+        .expect("ray hits the voxel");
+    assert_eq!(
+        hit,
+        VoxelRayHit {
+            voxel_coord: IVec3::new(1, 2, 3),
+            normal: IVec3::Y,
+            distance: 19.0,
+            voxel: Voxel(7),
+        }
+    );
+    let hit = data
+        .raycast(Ray3d::new(voxel_center, Dir3::X), &global_xform)
+        .expect("ray starts inside the voxel");
+    assert_eq!(hit.normal, IVec3::ZERO);
+    assert_eq!(hit.distance, 0.0);
+    assert!(
+        data.raycast(
+            Ray3d::new(voxel_center + Vec3::Y * 20.0, Dir3::Y),
+            &global_xform
+        )
+        .is_none()
+    );
+    assert!(
+        data.raycast(
+            Ray3d::new(voxel_center + Vec3::Y * 2.0, Dir3::X),
+            &global_xform
+        )
+        .is_none(),
+        "ray passes through empty voxels"
+    );
+}
+
+#[cfg(all(feature = "picking", feature = "modify_voxels"))]
+#[test]
+fn test_voxel_picking() {
+    use crate::{VoxelPickingPlugin, VoxelPointerHits};
+    use bevy::picking::{
+        backend::{PointerHits, ray::RayId, ray::RayMap},
+        pointer::PointerId,
+    };
+
+    let mut app = App::new();
+    setup_app(&mut app);
+    app.init_resource::<RayMap>()
+        .add_event::<PointerHits>()
+        .add_plugins(VoxelPickingPlugin);
+    let palette = VoxelPalette::from_colors(
+        vec![
+            bevy::color::palettes::css::GREEN.into(),
+            bevy::color::palettes::css::RED.into(),
+        ],
+        true,
+    );
+    let mut data = VoxelData::new(UVec3::splat(4), VoxLoaderSettings::default());
+    data.set_voxel(Voxel(2), UVec3::new(1, 2, 3));
+    let voxel_center = data.voxel_coord_to_local_space(IVec3::new(1, 2, 3)) + 0.5;
+    let world = app.world_mut();
+    let context = world
+        .run_system_cached_with(create_voxel_context, palette)
+        .expect("Context has been created");
+    let scene_handle = world
+        .run_system_cached_with(create_voxel_scene, (data, "voxel".to_string(), context))
+        .expect("Add model");
+    let scene_root = world.spawn(SceneRoot(scene_handle)).id();
+    let camera = world.spawn(Camera::default()).id();
+    app.update();
+    let entity = *app
+        .world()
+        .get::<Children>(scene_root)
+        .expect("children")
+        .first()
+        .expect("model entity");
+    // visibility is not propagated without the render plugins
+    app.world_mut()
+        .entity_mut(entity)
+        .insert(InheritedVisibility::VISIBLE);
+    app.world_mut().resource_mut::<RayMap>().map.insert(
+        RayId::new(camera, PointerId::Mouse),
+        Ray3d::new(voxel_center + Vec3::Y * 10.0, Dir3::NEG_Y),
+    );
+    app.update();
+    let pick = app
+        .world()
+        .get::<VoxelPointerHits>(entity)
+        .expect("pointer hits")
+        .get(&PointerId::Mouse)
+        .expect("mouse is over the voxel")
+        .clone();
+    assert_eq!(pick.hit.voxel_coord, IVec3::new(1, 2, 3));
+    assert_eq!(pick.hit.normal, IVec3::Y);
+    assert_eq!(pick.hit.voxel, Voxel(2));
+    assert_eq!(pick.element.color, bevy::color::palettes::css::RED.into());
+    let hits: Vec<&PointerHits> = app
+        .world()
+        .resource::<bevy::ecs::event::Events<PointerHits>>()
+        .iter_current_update_events()
+        .collect();
+    assert_eq!(hits.len(), 1);
+    let (hit_entity, hit_data) = &hits[0].picks[0];
+    assert_eq!(*hit_entity, entity);
+    assert_eq!(hit_data.depth, 9.5);
+    assert_eq!(hit_data.normal, Some(Vec3::Y));
+
+    app.world_mut().resource_mut::<RayMap>().map.clear();
+    app.update();
+    assert!(
+        app.world()
+            .get::<VoxelPointerHits>(entity)
+            .expect("pointer hits")
+            .get(&PointerId::Mouse)
+            .is_none(),
+        "mouse is no longer over the voxel"
+    );
+}
+
 async fn setup_and_load_voxel_scene(app: &mut App, filename: &'static str) -> Handle<Scene> {
     setup_app(app);
+    load_voxel_scene(app, filename).await
+}
+
+async fn load_voxel_scene(app: &mut App, filename: &'static str) -> Handle<Scene> {
     let assets = app.world().resource::<AssetServer>();
     assets
         .load_untyped_async(filename)
@@ -493,12 +3353,17 @@ async fn setup_and_load_voxel_scene(app: &mut App, filename: &'static str) -> Ha
 }
 
 fn setup_app(app: &mut App) {
+    setup_app_with_settings(app, None);
+}
+
+fn setup_app_with_settings(app: &mut App, global_settings: Option<VoxLoaderSettings>) {
     app.add_plugins((
         MinimalPlugins,
         AssetPlugin::default(),
         ImagePlugin::default(),
         ScenePlugin,
-        VoxScenePlugin::default(),
+        AnimationPlugin,
+        VoxScenePlugin { global_settings },
     ))
     .init_asset::<StandardMaterial>()
     .init_asset::<Mesh>()